@@ -1,8 +1,8 @@
 import * as fs from "fs";
-import * as anchor from "@project-serum/anchor";
-import { BN, Program } from "@project-serum/anchor";
+import * as anchor from "@coral-xyz/anchor";
+import { BN, Program } from "@coral-xyz/anchor";
 import * as cron from "node-cron";
-import { Connection, PublicKey } from "@solana/web3.js";
+import { Connection, ParsedAccountData, PublicKey } from "@solana/web3.js";
 import fetch from "node-fetch";
 import { getOrca, OrcaPoolConfig } from "@orca-so/sdk";
 import Decimal from "decimal.js";
@@ -19,7 +19,7 @@ import {
 
 process.env.ANCHOR_PROVIDER_URL = "https://api.devnet.solana.com";
 process.env.ANCHOR_WALLET = "../.secret";
-const provider = anchor.Provider.env();
+const provider = anchor.AnchorProvider.env();
 
 anchor.setProvider(provider);
 
@@ -50,7 +50,8 @@ const connection = new Connection(
 );
 const orca = getOrca(connection);
 
-const DECIMALS = 9;
+// Prices are sent in units of 10^-PRICE_EXPONENT USD, the exponent of the aggregator
+const PRICE_EXPONENT = 9;
 const payer = provider.wallet.publicKey;
 const authority = provider.wallet.publicKey;
 const systemProgram = anchor.web3.SystemProgram.programId;
@@ -66,6 +67,13 @@ async function programCall(
   });
 }
 
+async function getMintDecimals(mint: PublicKey): Promise<number> {
+  let mintAccount = await provider.connection.getParsedAccountInfo(
+    new PublicKey(mint)
+  );
+  return (mintAccount.value.data as ParsedAccountData).parsed.info.decimals;
+}
+
 async function delphorInitCoin(
   mint: PublicKey,
   symbol: string,
@@ -73,7 +81,9 @@ async function delphorInitCoin(
   pythProductAccount: PublicKey,
   switchboardOptimizedFeedAccount: PublicKey
 ) {
-  let params = [DECIMALS, symbol];
+  // init_coin rejects decimals that don't match the mint
+  let decimals = await getMintDecimals(mint);
+  let params = [decimals, symbol];
   let accounts = {
     switchboardOptimizedFeedAccount,
     pythProductAccount,
@@ -168,10 +178,10 @@ async function getOrcaPrice(orcaPoolAccount: OrcaPoolConfig): Promise<BN> {
   let quote = await orcaPool.getQuote(orcaPool.getTokenA(), new Decimal(0.001));
   let orcaMinAmount = quote.getMinOutputAmount().value.toNumber() * 10 ** 3;
   let scale = quote.getMinOutputAmount().scale;
-  if (scale < DECIMALS) {
-    orcaMinAmount *= 10 ** (DECIMALS - scale);
-  } else if (scale > DECIMALS) {
-    orcaMinAmount /= 10 ** (scale - DECIMALS);
+  if (scale < PRICE_EXPONENT) {
+    orcaMinAmount *= 10 ** (PRICE_EXPONENT - scale);
+  } else if (scale > PRICE_EXPONENT) {
+    orcaMinAmount /= 10 ** (scale - PRICE_EXPONENT);
   }
   return new BN(orcaMinAmount);
 }
@@ -185,7 +195,7 @@ async function getCoingeckoPrice(tokenId: string): Promise<BN> {
   let priceJson: Object = await priceResponse.json();
   let price = priceJson[tokenId]["usd"];
   if (price) {
-    return new BN(Math.trunc(price.toFixed(5) * 10 ** DECIMALS));
+    return new BN(Math.trunc(price.toFixed(5) * 10 ** PRICE_EXPONENT));
   }
   return new BN(0);
 }
@@ -1,5 +1,5 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::Mint;
+use anchor_spl::token_interface::Mint;
 use delphor_oracle::CoinInfo;
 use pyth_client::{load_price, load_product, Price, PriceConf, PriceStatus, Product};
 use std::{cmp, str};
@@ -7,6 +7,8 @@ use switchboard_program::{FastRoundResultAccountData, SwitchboardAccountType};
 
 declare_id!("HbyTY89Se2c8Je7KDKHVjUEGN2sAruFAw3S3NwubzeyU");
 const MAX_SYMBOL_LEN: usize = 36;
+// CoinData.price is in units of 10^-PRICE_EXPONENT USD, whatever the token decimals
+pub const PRICE_EXPONENT: u8 = 9;
 
 #[program]
 pub mod delphor_oracle_aggregator {
@@ -16,9 +18,7 @@ pub mod delphor_oracle_aggregator {
         let delphor_oracle = &mut ctx.accounts.delphor_oracle;
 
         let mut switchboard_price: u64 = delphor_oracle.coin_gecko_price;
-        if coin_data
-            .switchboard_optimized_feed_account
-            .to_string()
+        if coin_data.switchboard_optimized_feed_account.to_string()
             != "11111111111111111111111111111111"
         {
             let switchboard_price_result =
@@ -29,11 +29,15 @@ pub mod delphor_oracle_aggregator {
             }
         }
         let mut pyth_price: u64 = delphor_oracle.coin_gecko_price;
+        let mut pyth_confidence: u64 = 0;
         if coin_data.pyth_price_account.to_string() != "11111111111111111111111111111111" {
             let pyth_price_result =
-                get_pyth_price(&ctx.accounts.pyth_price_account, coin_data.decimals);
+                get_pyth_price(&ctx.accounts.pyth_price_account, PRICE_EXPONENT);
             match pyth_price_result {
-                Ok(price) => pyth_price = price,
+                Ok((price, confidence)) => {
+                    pyth_price = price;
+                    pyth_confidence = confidence;
+                }
                 Err(error) => return Err(error),
             }
         }
@@ -43,21 +47,35 @@ pub mod delphor_oracle_aggregator {
             &pyth_price,
             &switchboard_price,
         );
+        coin_data.confidence = pyth_confidence;
+        // Accounts created before the price exponent was stored get it on their next update
+        coin_data.price_exponent = PRICE_EXPONENT;
+
+        // The aggregated price is as old as the delphor-oracle price it is built from,
+        // refreshing it without a new feeder price must not make it look fresh
+        coin_data.last_update_timestamp = delphor_oracle.last_update_timestamp;
+        coin_data.last_update_slot = Clock::get().unwrap().slot;
 
         Ok(())
     }
 
     // Deserialization error in borsh with the order of the parameters.
     // String must be the last.
-    pub fn init_coin(
-        ctx: Context<InitCoinPrice>,
-        decimals: u8,
-        symbol: String,
-    ) -> Result<()> {
+    pub fn init_coin(ctx: Context<InitCoinPrice>, decimals: u8, symbol: String) -> Result<()> {
         let coin_data = &mut ctx.accounts.coin_data;
         let mint = &ctx.accounts.mint;
         let authority = &ctx.accounts.authority;
 
+        if decimals != mint.decimals {
+            msg!(
+                "Mint {} has {} decimals, received {}",
+                mint.key(),
+                mint.decimals,
+                decimals
+            );
+            return Err(error!(ErrorCode::InvalidDecimals));
+        }
+
         let switchboard_optimized_feed_account = &ctx.accounts.switchboard_optimized_feed_account;
         if ctx
             .accounts
@@ -92,7 +110,7 @@ pub mod delphor_oracle_aggregator {
                 msg!("Received: {}", pyth_product_metadata);
                 return Err(error!(ErrorCode::PythProductAccountError));
             }
-            coin_data.pyth_price_account = Pubkey::new(&pyth_product_data.px_acc.val);
+            coin_data.pyth_price_account = Pubkey::new_from_array(pyth_product_data.px_acc.val);
         } else {
             coin_data.pyth_price_account = ctx.accounts.pyth_product_account.key();
         }
@@ -106,13 +124,12 @@ pub mod delphor_oracle_aggregator {
         coin_data.mint = *mint.to_account_info().key;
         coin_data.authority = *authority.key;
         coin_data.decimals = decimals;
+        coin_data.price_exponent = PRICE_EXPONENT;
         Ok(())
     }
 }
 
-fn get_switchboard_price(
-    switchboard_account: &AccountInfo<'_>,
-) -> Result<u64> {
+fn get_switchboard_price(switchboard_account: &AccountInfo<'_>) -> Result<u64> {
     let account_buf = switchboard_account.try_borrow_data()?;
     if account_buf.len() == 0 {
         msg!("The provided account is empty.");
@@ -122,14 +139,13 @@ fn get_switchboard_price(
         return Err(ProgramError::InvalidAccountData.into());
     }
     let feed_data = FastRoundResultAccountData::deserialize(&account_buf).unwrap();
-    return Ok((feed_data.result.result * u64::pow(10, 9) as f64) as u64);
+    return Ok((feed_data.result.result * u64::pow(10, PRICE_EXPONENT as u32) as f64) as u64);
 }
 
-fn get_pyth_price(
-    pyth_account: &AccountInfo<'_>,
-    decimals: u8,
-) -> Result<u64> {
+// Returns price and confidence interval, both scaled to price_exponent
+fn get_pyth_price(pyth_account: &AccountInfo<'_>, price_exponent: u8) -> Result<(u64, u64)> {
     let mut pyth_price: u64 = 0;
+    let mut pyth_confidence: u64 = 0;
     let pyth_price_account = &pyth_account.try_borrow_data().unwrap();
     let pyth_price_data: &Price = load_price(&pyth_price_account).unwrap();
     if pyth_price_data.agg.status == PriceStatus::Trading {
@@ -140,13 +156,16 @@ fn get_pyth_price(
         } else {
             pyth_price = pyth_price_conf_data.price as u64;
         }
-        if pyth_expo < decimals {
-            pyth_price = pyth_price * u64::pow(10, (decimals - pyth_expo) as u32);
-        } else if pyth_expo > decimals {
-            pyth_price = pyth_price / u64::pow(10, (pyth_expo - decimals) as u32);
+        pyth_confidence = pyth_price_conf_data.conf;
+        if pyth_expo < price_exponent {
+            pyth_price = pyth_price * u64::pow(10, (price_exponent - pyth_expo) as u32);
+            pyth_confidence *= u64::pow(10, (price_exponent - pyth_expo) as u32);
+        } else if pyth_expo > price_exponent {
+            pyth_price = pyth_price / u64::pow(10, (pyth_expo - price_exponent) as u32);
+            pyth_confidence /= u64::pow(10, (pyth_expo - price_exponent) as u32);
         }
     }
-    Ok(pyth_price)
+    Ok((pyth_price, pyth_confidence))
 }
 
 fn calculate_price(price_a: &u64, price_b: &u64, price_c: &u64) -> u64 {
@@ -175,8 +194,14 @@ pub struct UpdateCoinPrice<'info> {
     /// CHECK:
     #[account(constraint = pyth_price_account.key() == coin_data.pyth_price_account)]
     pyth_price_account: AccountInfo<'info>,
-    // struct CoinInfo is imported from delphor-oracle, so the owner MUST be delphor-oracle
-    // no need for additional checks
+    // struct CoinInfo is imported from delphor-oracle, so the owner MUST be delphor-oracle,
+    // the seeds and the authority tie it to the coin
+    #[account(
+        seeds = [coin_data.symbol.as_bytes()],
+        bump,
+        seeds::program = delphor_oracle::ID,
+        constraint = delphor_oracle.authority == coin_data.authority @ ErrorCode::CoinAuthorityMismatch,
+    )]
     delphor_oracle: Account<'info, CoinInfo>,
     #[account(mut)]
     coin_data: Account<'info, CoinData>,
@@ -189,14 +214,14 @@ pub struct InitCoinPrice<'info> {
     #[account(
         init_if_needed,
         payer = payer,
-        space = 32+32+64+64+8+MAX_SYMBOL_LEN,
+        space = 32+32+64+64+8+MAX_SYMBOL_LEN+8+8+1,
         seeds = [
             mint.key().as_ref()
         ],
         bump,
     )]
     coin_data: Account<'info, CoinData>,
-    mint: Account<'info, Mint>,
+    mint: InterfaceAccount<'info, Mint>,
     /// CHECK:
     authority: AccountInfo<'info>,
     /// CHECK:
@@ -216,9 +241,15 @@ pub struct CoinData {
     pub price: u64,
     pub last_update_timestamp: u64,
     pub symbol: String,
+    // Decimals of the mint, checked at init_coin
     pub decimals: u8,
     pub pyth_price_account: Pubkey,
     pub switchboard_optimized_feed_account: Pubkey,
+    pub last_update_slot: u64,
+    // Pyth confidence interval, same scale as price
+    pub confidence: u64,
+    // price is in units of 10^-price_exponent USD
+    pub price_exponent: u8,
 }
 
 #[error_code]
@@ -229,4 +260,8 @@ pub enum ErrorCode {
     PythProductAccountError,
     #[msg("Switchboard accounts don't match.")]
     SwitchboardAccountError,
+    #[msg("Decimals don't match the mint.")]
+    InvalidDecimals,
+    #[msg("Delphor oracle coin has another authority.")]
+    CoinAuthorityMismatch,
 }
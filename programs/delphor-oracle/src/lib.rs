@@ -93,7 +93,7 @@ pub mod delphor_oracle {
 pub struct CreateCoin<'info> {
     #[account(init,payer=payer,seeds=[symbol.as_bytes().as_ref()],bump,space=32+64+64+64+64+MAX_SYMBOL_LEN+128)]
     coin: Account<'info, CoinInfo>,
-    /// CHECK: 
+    /// CHECK:
     authority: AccountInfo<'info>,
     #[account(mut)]
     payer: Signer<'info>,
@@ -6,4 +6,46 @@ pub enum DelphorError {
     AccessDenied,
     #[msg("Not the owner")]
     NotTheOwner,
+    #[msg("Program paused")]
+    ProgramPaused,
+    #[msg("Mint paused")]
+    MintPaused,
+    #[msg("Too many paused mints")]
+    TooManyPausedMints,
+    #[msg("Fee exceeds 10000 basis points")]
+    InvalidFee,
+    #[msg("Withdrawal leaves the vault below its min amount")]
+    BelowMinAmount,
+    #[msg("Recipient is not the vault owner or an approved recipient")]
+    InvalidRecipient,
+    #[msg("Too many pool mints")]
+    TooManyPoolMints,
+    #[msg("Invalid pool accounts")]
+    InvalidPoolAccounts,
+    #[msg("Invalid amount of LP shares")]
+    InvalidShares,
+    #[msg("Vault min is greater than max")]
+    InvalidVaultBounds,
+    #[msg("Limit price must be set when enabled")]
+    InvalidLimitPrice,
+    #[msg("Too many allowed takers")]
+    TooManyAllowedTakers,
+    #[msg("Taker not in the vault allowlist")]
+    TakerNotAllowed,
+    #[msg("Outflow limit requires an outflow window")]
+    InvalidOutflowWindow,
+    #[msg("Vault rejects deposits from other accounts")]
+    ExternalDepositRejected,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Math underflow")]
+    MathUnderflow,
+    #[msg("Division by zero")]
+    DivisionByZero,
+    #[msg("Price deviation requires a reference price")]
+    InvalidReferencePrice,
+    #[msg("Mint extension not supported by the vaults")]
+    UnsupportedMintExtension,
+    #[msg("Vault already bound to a global state")]
+    VaultAlreadyMigrated,
 }
@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+
+// ------------
+// -- Events --
+// ------------
+#[event]
+pub struct DepositEvent {
+    pub user_vault: Pubkey,
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    // owner or delegate_authority of the tokens deposited
+    pub depositor: Pubkey,
+    // amount credited to the vault, net of transfer fees
+    pub amount: u64,
+    // vault balance after the deposit
+    pub vault_amount: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct WithdrawEvent {
+    pub user_vault: Pubkey,
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub send_token_to: Pubkey,
+    pub amount: u64,
+    // vault balance after the withdraw
+    pub vault_amount: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct SwapEvent {
+    pub user_vault_from: Pubkey,
+    pub user_vault_to: Pubkey,
+    // owner of both vaults
+    pub user: Pubkey,
+    // owner or delegate_authority of the tokens swapped
+    pub taker: Pubkey,
+    pub mint_send: Pubkey,
+    pub mint_receive: Pubkey,
+    pub swap_amount: u64,
+    pub amount_to_send: u64,
+    // oracle prices used
+    pub get_coin_price: u64,
+    pub send_coin_price: u64,
+    // fees charged, in basis points
+    pub buy_fee: u32,
+    pub sell_fee: u32,
+    // protocol fee in units of mint_receive
+    pub protocol_fee: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct UpdateUserVaultEvent {
+    pub user_vault: Pubkey,
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub buy_fee: u32,
+    pub sell_fee: u32,
+    pub min: u64,
+    pub max: u64,
+    pub receive_status: bool,
+    pub provide_status: bool,
+    pub limit_price_status: bool,
+    pub limit_price: u64,
+    pub max_price_age: u64,
+    pub reference_price: u64,
+    pub max_price_deviation: u32,
+    pub fee_curve: u32,
+    pub buy_limit_price_status: bool,
+    pub buy_limit_price: u64,
+    pub limit_price_mint: Pubkey,
+    pub allowlist_status: bool,
+    pub max_outflow: u64,
+    pub outflow_window: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct PoolDepositEvent {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    // LP shares minted
+    pub shares: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct PoolWithdrawEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    // LP shares burned
+    pub shares: u64,
+    // amounts withdrawn, in the order of the pool mints
+    pub amounts: Vec<u64>,
+    pub timestamp: u64,
+}
@@ -1,7 +1,9 @@
+use crate::error::*;
+use crate::events::*;
 use crate::states::*;
+use crate::token::check_mint_extensions;
 use anchor_lang::prelude::*;
-use anchor_spl::token::Mint;
-use anchor_spl::token::{InitializeAccount, Token, TokenAccount};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
 
 //-----------------------------------------------------
 #[derive(Accounts)]
@@ -15,7 +17,7 @@ pub struct Initialize<'info> {
     #[account(
         init,
         payer = admin_account,
-        space = 8 + core::mem::size_of::<GlobalState>() + 128, // 128 bytes future expansion
+        space = 8 + core::mem::size_of::<GlobalState>() + MAX_PAUSED_MINTS * 32 + 128, // 128 bytes future expansion
         seeds = [
             admin_account.key().as_ref(),
         ],
@@ -30,6 +32,8 @@ impl<'info> Initialize<'info> {
     pub fn process(&mut self, bump: u8) -> Result<()> {
         self.global_state.admin_account = *self.admin_account.key;
         self.global_state.bump = bump;
+        self.global_state.max_price_age = DEFAULT_MAX_PRICE_AGE;
+        self.global_state.max_confidence = DEFAULT_MAX_CONFIDENCE;
         Ok(())
     }
 }
@@ -46,7 +50,7 @@ pub struct InitUserVault<'info> {
     pub user_account: Signer<'info>,
 
     // for what token
-    pub mint: Account<'info, Mint>,
+    pub mint: InterfaceAccount<'info, Mint>,
 
     // user vault, create PDA
     #[account(
@@ -64,34 +68,19 @@ pub struct InitUserVault<'info> {
     pub system_program: Program<'info, System>,
 }
 impl<'info> InitUserVault<'info> {
-    #[allow(unused_variables)]
-    pub fn process(
-        &mut self,
-        bump: u8,
-        buy_fee: u32,
-        sell_fee: u32,
-        min: u64,
-        max: u64,
-        receive_status: bool,
-        provide_status: bool,
-        limit_price_status: bool,
-        limit_price: u64,
-    ) -> Result<()> {
+    pub fn process(&mut self, bump: u8, config: VaultConfig) -> Result<()> {
+        check_mint_extensions(&self.mint.to_account_info())?;
+        config.validate()?;
+
         *self.user_vault = UserCoinVault {
             bump,
             user: self.user_account.key(),
             mint: self.mint.key(),
-            amount: 0,
-            min,
-            max,
-            buy_fee,
-            sell_fee,
             timestamp: Clock::get().unwrap().unix_timestamp as u64,
-            receive_status: false,
-            provide_status: false,
-            limit_price_status: false,
-            limit_price: 0,
+            global_state: self.global_state.key(),
+            ..UserCoinVault::default()
         };
+        self.user_vault.set_config(&config);
         Ok(())
     }
 }
@@ -100,6 +89,7 @@ impl<'info> InitUserVault<'info> {
 #[derive(Accounts)]
 pub struct InitTokenStore<'info> {
     // global state
+    #[account(has_one = admin_account)]
     pub global_state: Account<'info, GlobalState>,
 
     // admin account, signer
@@ -107,43 +97,34 @@ pub struct InitTokenStore<'info> {
     pub admin_account: Signer<'info>,
 
     // for what token
-    pub mint: Account<'info, Mint>,
+    pub mint: InterfaceAccount<'info, Mint>,
     /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
     pub token_store_authority: AccountInfo<'info>,
 
-    // token store, token account
-    /// CHECK:
+    // token store, token account holding the deposits of all vaults for a token
     #[account(
         init,
         payer = admin_account,
-        space = core::mem::size_of::<TokenAccount>(),
         seeds = [
             global_state.key().as_ref(),
             mint.key().as_ref(),
         ],
         bump,
+        token::mint = mint,
+        token::authority = token_store_authority,
+        token::token_program = token_program,
     )]
-    pub token_store: AccountInfo<'info>,
+    pub token_store: InterfaceAccount<'info, TokenAccount>,
 
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    /// CHECK:
-    pub rent: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
 }
 impl<'info> InitTokenStore<'info> {
-    #[allow(unused_variables)]
-    pub fn process(&mut self) -> Result<()> {
-        anchor_spl::token::initialize_account(CpiContext::new(
-            self.token_program.to_account_info().clone(),
-            InitializeAccount {
-                account: self.token_store.to_account_info(),
-                mint: self.mint.to_account_info(),
-                authority: self.token_store_authority.clone(),
-                rent: self.rent.to_account_info(),
-            },
-        ))?;
-
-        Ok(())
+    pub fn process(&self) -> Result<()> {
+        // Stores only hold tokens the vaults can account for
+        check_mint_extensions(&self.mint.to_account_info())
     }
 }
 
@@ -168,37 +149,481 @@ impl<'info> ChangeAuthority<'info> {
     }
 }
 
+//--------------------------------------
+#[derive(Accounts)]
+pub struct UpdateOracleConfig<'info> {
+    // global state
+    #[account(mut, has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // current admin account (must match the one in GlobalState)
+    pub admin_account: Signer<'info>,
+}
+impl<'info> UpdateOracleConfig<'info> {
+    pub fn process(&mut self, max_price_age: u64, max_confidence: u32) -> Result<()> {
+        self.global_state.max_price_age = max_price_age;
+        self.global_state.max_confidence = max_confidence;
+        Ok(())
+    }
+}
+
+//--------------------------------------
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    // global state
+    #[account(mut, has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // current admin account (must match the one in GlobalState)
+    pub admin_account: Signer<'info>,
+}
+impl<'info> SetPaused<'info> {
+    pub fn process(&mut self, paused: bool) -> Result<()> {
+        self.global_state.paused = paused;
+        Ok(())
+    }
+}
+
+//--------------------------------------
+#[derive(Accounts)]
+pub struct SetMintPaused<'info> {
+    // global state
+    #[account(mut, has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // current admin account (must match the one in GlobalState)
+    pub admin_account: Signer<'info>,
+
+    // token to pause or unpause
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+impl<'info> SetMintPaused<'info> {
+    pub fn process(&mut self, paused: bool) -> Result<()> {
+        let mint = self.mint.key();
+        let paused_mints = &mut self.global_state.paused_mints;
+        if !paused {
+            paused_mints.retain(|paused_mint| *paused_mint != mint);
+        } else if !paused_mints.contains(&mint) {
+            if paused_mints.len() >= MAX_PAUSED_MINTS {
+                return err!(DelphorError::TooManyPausedMints);
+            }
+            paused_mints.push(mint);
+        }
+        Ok(())
+    }
+}
+
+//--------------------------------------
+#[derive(Accounts)]
+pub struct SetProtocolFee<'info> {
+    // global state
+    #[account(mut, has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // current admin account (must match the one in GlobalState)
+    pub admin_account: Signer<'info>,
+}
+impl<'info> SetProtocolFee<'info> {
+    pub fn process(&mut self, protocol_fee_share: u32) -> Result<()> {
+        if protocol_fee_share > 10000 {
+            return err!(DelphorError::InvalidFee);
+        }
+        self.global_state.protocol_fee_share = protocol_fee_share;
+        Ok(())
+    }
+}
+
 //-----------------------------------------------------
 #[derive(Accounts)]
-pub struct UpdateUserVault<'info> {
+pub struct InitTreasury<'info> {
+    // global state
+    #[account(has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // admin account, signer
+    #[account(mut)]
+    pub admin_account: Signer<'info>,
+
+    // for what token
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+
+    // treasury, token account collecting protocol fees
+    #[account(
+        init,
+        payer = admin_account,
+        seeds = [
+            b"treasury",
+            global_state.key().as_ref(),
+            mint.key().as_ref(),
+        ],
+        bump,
+        token::mint = mint,
+        token::authority = token_store_authority,
+        token::token_program = token_program,
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    // global state
+    #[account(has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // current admin account (must match the one in GlobalState)
+    pub admin_account: Signer<'info>,
+
+    // for what token
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+
+    // treasury to collect fees from
+    #[account(mut, seeds = [
+        b"treasury", global_state.key().as_ref(), mint.key().as_ref()
+    ], bump)]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+
+    // account to send the fees to
+    #[account(mut, constraint = send_token_to.mint == mint.key())]
+    pub send_token_to: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+impl<'info> CollectProtocolFees<'info> {
+    pub fn process(&mut self, bump: u8) -> Result<()> {
+        let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
+        let signer = &[seeds];
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.treasury.to_account_info(),
+                    mint: self.mint.to_account_info(),
+                    to: self.send_token_to.to_account_info(),
+                    authority: self.token_store_authority.to_account_info(),
+                },
+                signer,
+            ),
+            self.treasury.amount,
+            self.mint.decimals,
+        )?;
+
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+// Migrate Token Store Instruction
+// Vaults created before the token stores were program-derived kept their tokens in the
+// associated token account of store_auth. Moves them to the token store of the mint.
+#[derive(Accounts)]
+pub struct MigrateTokenStore<'info> {
+    // global state
+    #[account(has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // current admin account (must match the one in GlobalState)
+    pub admin_account: Signer<'info>,
+
+    // for what token
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+
+    // store of the previous versions, associated token account of store_auth
+    #[account(mut, associated_token::mint = mint, associated_token::authority = token_store_authority,
+        associated_token::token_program = token_program)]
+    pub legacy_token_store: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint.key().as_ref()
+    ], bump)]
+    pub token_store: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+impl<'info> MigrateTokenStore<'info> {
+    pub fn process(&mut self, bump: u8) -> Result<()> {
+        let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
+        let signer = &[seeds];
+
+        msg!(
+            "Moving {} from {} to {}",
+            self.legacy_token_store.amount,
+            self.legacy_token_store.key(),
+            self.token_store.key()
+        );
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.legacy_token_store.to_account_info(),
+                    mint: self.mint.to_account_info(),
+                    to: self.token_store.to_account_info(),
+                    authority: self.token_store_authority.to_account_info(),
+                },
+                signer,
+            ),
+            self.legacy_token_store.amount,
+            self.mint.decimals,
+        )?;
+
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+// Migrate User Vault Instruction
+// Vaults created before global_state was stored in them fail the has_one checks,
+// binds them to the global state of the admin
+#[derive(Accounts)]
+pub struct MigrateUserVault<'info> {
+    // global state
+    #[account(has_one = admin_account)]
+    pub global_state: Account<'info, GlobalState>,
+
+    // current admin account (must match the one in GlobalState)
+    pub admin_account: Signer<'info>,
+
+    #[account(mut, constraint = user_vault.global_state == Pubkey::default() @ DelphorError::VaultAlreadyMigrated)]
+    pub user_vault: Account<'info, UserCoinVault>,
+}
+impl<'info> MigrateUserVault<'info> {
+    pub fn process(&mut self) -> Result<()> {
+        self.user_vault.global_state = self.global_state.key();
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct SetWithdrawRecipient<'info> {
     pub user_account: Signer<'info>,
-    pub mint: Account<'info, Mint>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [
+        user_account.key().as_ref(), mint.key().as_ref()
+    ], bump = user_vault.bump)]
+    pub user_vault: Account<'info, UserCoinVault>,
+}
+impl<'info> SetWithdrawRecipient<'info> {
+    pub fn process(&mut self, withdraw_recipient: Pubkey) -> Result<()> {
+        self.user_vault.withdraw_recipient = withdraw_recipient;
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct SetRejectExternalDeposits<'info> {
+    pub user_account: Signer<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [
+        user_account.key().as_ref(), mint.key().as_ref()
+    ], bump = user_vault.bump)]
+    pub user_vault: Account<'info, UserCoinVault>,
+}
+impl<'info> SetRejectExternalDeposits<'info> {
+    pub fn process(&mut self, reject_external_deposits: bool) -> Result<()> {
+        self.user_vault.reject_external_deposits = reject_external_deposits;
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct SetVaultOperator<'info> {
+    pub user_account: Signer<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [
+        user_account.key().as_ref(), mint.key().as_ref()
+    ], bump = user_vault.bump)]
+    pub user_vault: Account<'info, UserCoinVault>,
+}
+impl<'info> SetVaultOperator<'info> {
+    pub fn process(&mut self, operator: Pubkey) -> Result<()> {
+        self.user_vault.operator = operator;
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct UpdateUserVault<'info> {
+    // vault owner or operator
+    pub authority: Signer<'info>,
+    /// CHECK: vault owner, validated by the user_vault seeds
+    pub user_account: AccountInfo<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
     #[account(mut, seeds = [
         user_account.key().as_ref(), mint.key().as_ref()
     ], bump = user_vault.bump)]
     pub user_vault: Account<'info, UserCoinVault>,
 }
 impl<'info> UpdateUserVault<'info> {
-    pub fn process(
-        &mut self,
-        sell_fee: u32,
-        buy_fee: u32,
-        min: u64,
-        max: u64,
-        receive_status: bool,
-        provide_status: bool,
-        limit_price_status: bool,
-        limit_price: u64,
-    ) -> Result<()> {
-        self.user_vault.sell_fee = sell_fee;
-        self.user_vault.buy_fee = buy_fee;
-        self.user_vault.min = min;
-        self.user_vault.max = max;
+    pub fn process(&mut self, update: VaultConfigUpdate) -> Result<()> {
+        self.user_vault
+            .check_owner_or_operator(&self.authority.key())?;
+
+        let mut config = self.user_vault.config();
+        config.update(&update);
+        config.validate()?;
+
+        self.user_vault.set_config(&config);
         self.user_vault.timestamp = Clock::get().unwrap().unix_timestamp as u64;
-        self.user_vault.receive_status = receive_status;
-        self.user_vault.provide_status = provide_status;
-        self.user_vault.limit_price_status = limit_price_status;
-        self.user_vault.limit_price = limit_price;
+
+        let user_vault = &self.user_vault;
+        emit!(UpdateUserVaultEvent {
+            user_vault: user_vault.key(),
+            user: user_vault.user,
+            mint: user_vault.mint,
+            buy_fee: user_vault.buy_fee,
+            sell_fee: user_vault.sell_fee,
+            min: user_vault.min,
+            max: user_vault.max,
+            receive_status: user_vault.receive_status,
+            provide_status: user_vault.provide_status,
+            limit_price_status: user_vault.limit_price_status,
+            limit_price: user_vault.limit_price,
+            max_price_age: user_vault.max_price_age,
+            reference_price: user_vault.reference_price,
+            max_price_deviation: user_vault.max_price_deviation,
+            fee_curve: user_vault.fee_curve,
+            buy_limit_price_status: user_vault.buy_limit_price_status,
+            buy_limit_price: user_vault.buy_limit_price,
+            limit_price_mint: user_vault.limit_price_mint,
+            allowlist_status: user_vault.allowlist_status,
+            max_outflow: user_vault.max_outflow,
+            outflow_window: user_vault.outflow_window,
+            timestamp: user_vault.timestamp,
+        });
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct SetAllowedTaker<'info> {
+    // vault owner or operator
+    pub authority: Signer<'info>,
+    /// CHECK: vault owner, validated by the user_vault seeds
+    pub user_account: AccountInfo<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [
+        user_account.key().as_ref(), mint.key().as_ref()
+    ], bump = user_vault.bump)]
+    pub user_vault: Account<'info, UserCoinVault>,
+}
+impl<'info> SetAllowedTaker<'info> {
+    pub fn process(&mut self, taker: Pubkey, allowed: bool) -> Result<()> {
+        self.user_vault
+            .check_owner_or_operator(&self.authority.key())?;
+
+        let allowed_takers = &mut self.user_vault.allowed_takers;
+        if !allowed {
+            allowed_takers.retain(|allowed_taker| *allowed_taker != taker);
+        } else if !allowed_takers.contains(&taker) {
+            if allowed_takers.len() >= MAX_ALLOWED_TAKERS {
+                return err!(DelphorError::TooManyAllowedTakers);
+            }
+            allowed_takers.push(taker);
+        }
         Ok(())
     }
 }
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct InitPairConfig<'info> {
+    // vault owner, signer
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    // token the user vaults sell
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    // token the user vaults buy
+    #[account(constraint = mint_b.key() != mint_a.key())]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = user_account,
+        space = 8 + core::mem::size_of::<UserPairConfig>() + 256, // 256 bytes future expansion
+        seeds = [
+            b"pair_config",
+            user_account.key().as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub pair_config: Account<'info, UserPairConfig>,
+    pub system_program: Program<'info, System>,
+}
+impl<'info> InitPairConfig<'info> {
+    pub fn process(&mut self, bump: u8, config: PairConfig) -> Result<()> {
+        config.validate()?;
+        *self.pair_config = UserPairConfig {
+            bump,
+            user: self.user_account.key(),
+            mint_a: self.mint_a.key(),
+            mint_b: self.mint_b.key(),
+            config,
+        };
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct UpdatePairConfig<'info> {
+    // vault owner or operator of the mint_a vault
+    pub authority: Signer<'info>,
+    /// CHECK: vault owner, validated by the user_vault and pair_config seeds
+    pub user_account: AccountInfo<'info>,
+    #[account(seeds = [
+        user_account.key().as_ref(), pair_config.mint_a.as_ref()
+    ], bump = user_vault.bump)]
+    pub user_vault: Account<'info, UserCoinVault>,
+    #[account(mut, seeds = [
+        b"pair_config",
+        user_account.key().as_ref(),
+        pair_config.mint_a.as_ref(),
+        pair_config.mint_b.as_ref(),
+    ], bump = pair_config.bump)]
+    pub pair_config: Account<'info, UserPairConfig>,
+}
+impl<'info> UpdatePairConfig<'info> {
+    pub fn process(&mut self, config: PairConfig) -> Result<()> {
+        self.user_vault
+            .check_owner_or_operator(&self.authority.key())?;
+        config.validate()?;
+        self.pair_config.config = config;
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+#[derive(Accounts)]
+pub struct ClosePairConfig<'info> {
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    #[account(mut, close = user_account, seeds = [
+        b"pair_config",
+        user_account.key().as_ref(),
+        pair_config.mint_a.as_ref(),
+        pair_config.mint_b.as_ref(),
+    ], bump = pair_config.bump)]
+    pub pair_config: Account<'info, UserPairConfig>,
+}
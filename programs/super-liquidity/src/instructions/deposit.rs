@@ -1,36 +1,44 @@
 use crate::error::*;
+use crate::events::*;
 use crate::states::*;
+use crate::token::amount_after_fee;
 use anchor_lang::prelude::*;
-use anchor_spl::token::{TokenAccount, Transfer, Token, Mint};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
 
 //-----------------------------------------------------
 // Deposit Instruction
 #[derive(Accounts)]
 pub struct Deposit<'info> {
-    /// CHECK:
+    // global state the vault belongs to
+    pub global_state: Account<'info, GlobalState>,
+    /// CHECK: vault owner, validated by the user_vault seeds
     pub user_account: AccountInfo<'info>,
-    // User PDA according to the deposited token
+    // User PDA according to the deposited token, credited with the deposit
     #[account(mut, seeds = [
         user_account.key().as_ref(), mint.key().as_ref()
-    ], bump = user_vault.bump)]
+    ], bump = user_vault.bump, has_one = global_state)]
     pub user_vault: Account<'info, UserCoinVault>,
-    /// CHECK:
-    pub token_store_authority: AccountInfo<'info>,
     // for what token
-    pub mint: Account<'info, Mint>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
     // Account where user have tokens
-    #[account(mut, associated_token::mint = mint, associated_token::authority = get_token_from_authority)]
-    pub get_token_from: Account<'info, TokenAccount>,
-    // owner or delegate_authority
+    #[account(mut, associated_token::mint = mint, associated_token::authority = get_token_from_authority,
+        associated_token::token_program = token_program)]
+    pub get_token_from: InterfaceAccount<'info, TokenAccount>,
+    // owner or delegate_authority, the vault owner or any other account funding the vault
     pub get_token_from_authority: Signer<'info>,
     // Account where the program will store the tokens
-    #[account(mut, associated_token::mint = mint, associated_token::authority = token_store_authority)]
-    pub token_store_pda: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint.key().as_ref()
+    ], bump)]
+    pub token_store_pda: InterfaceAccount<'info, TokenAccount>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 impl<'info> Deposit<'info> {
     pub fn process(&mut self, amount: u64) -> Result<()> {
+        self.global_state.check_not_paused(&[self.mint.key()])?;
+
         // check mint
         if self.get_token_from.mint != self.user_vault.mint {
             msg!(
@@ -55,39 +63,38 @@ impl<'info> Deposit<'info> {
             );
             return Err(ProgramError::InsufficientFunds.into());
         }
-        //TODO check token_store_pda == find_program_address(user_vault.mint,"TSTORE").0
 
-        let (pda, _bump_seed) = Pubkey::find_program_address(
-            &[
-                self.get_token_from_authority.to_account_info().key.as_ref(),
-                self.mint.to_account_info().key.as_ref(),
-            ],
-            &crate::ID,
-        );
-
-        if *self.user_vault.to_account_info().key != pda {
-            msg!(
-                "Invalid user_vault {}. Expected {}",
-                self.user_vault.to_account_info().key,
-                pda,
-            );
-            return Err(ProgramError::InvalidAccountData.into());
-        }
+        self.user_vault
+            .check_depositor(&self.get_token_from_authority.key())?;
 
-        anchor_spl::token::transfer(
+        anchor_spl::token_interface::transfer_checked(
             CpiContext::new(
                 self.token_program.to_account_info().clone(),
-                Transfer {
+                TransferChecked {
                     from: self.get_token_from.to_account_info(),
+                    mint: self.mint.to_account_info(),
                     to: self.token_store_pda.to_account_info(),
                     authority: self.get_token_from_authority.to_account_info().clone(),
                 },
             ),
             amount,
+            self.mint.decimals,
         )?;
 
-        self.user_vault.amount += amount;
+        // The vault is credited with what the store received, net of transfer fees
+        let amount_received = amount_after_fee(&self.mint.to_account_info(), amount)?;
+        self.user_vault.credit(amount_received)?;
         self.user_vault.timestamp = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(DepositEvent {
+            user_vault: self.user_vault.key(),
+            user: self.user_vault.user,
+            mint: self.user_vault.mint,
+            depositor: self.get_token_from_authority.key(),
+            amount: amount_received,
+            vault_amount: self.user_vault.amount,
+            timestamp: self.user_vault.timestamp,
+        });
         Ok(())
     }
 }
@@ -1,4 +1,7 @@
 pub mod admin;
 pub mod deposit;
+pub mod native_sol;
+pub mod pool;
+pub mod swap;
+pub mod swap_multi;
 pub mod withdraw;
-pub mod swap;
\ No newline at end of file
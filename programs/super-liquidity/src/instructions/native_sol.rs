@@ -0,0 +1,470 @@
+use crate::events::*;
+use crate::instructions::swap::{
+    check_oracle_accounts, transfer_from_store, transfer_protocol_fee, SwapQuoter,
+};
+use crate::states::*;
+use crate::token::{amount_after_fee, amount_before_fee};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{program::invoke, system_instruction};
+use anchor_spl::token_interface::{
+    CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+use delphor_oracle_aggregator::CoinData;
+use spl_token::native_mint;
+
+//-----------------------------------------------------
+// Deposit Sol Instruction
+// Wraps lamports of the depositor into the wSOL store, crediting the user wSOL vault
+#[derive(Accounts)]
+pub struct DepositSol<'info> {
+    // global state the vault belongs to
+    pub global_state: Account<'info, GlobalState>,
+    /// CHECK: vault owner, validated by the user_vault seeds
+    pub user_account: AccountInfo<'info>,
+    // wSOL vault of the user, credited with the deposit
+    #[account(mut, seeds = [
+        user_account.key().as_ref(), mint.key().as_ref()
+    ], bump = user_vault.bump, has_one = global_state)]
+    pub user_vault: Account<'info, UserCoinVault>,
+    #[account(address = native_mint::ID, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    // system account paying the lamports, the vault owner or any other account
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    // wSOL store
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint.key().as_ref()
+    ], bump)]
+    pub token_store_pda: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+impl<'info> DepositSol<'info> {
+    pub fn process(&mut self, amount: u64) -> Result<()> {
+        self.global_state.check_not_paused(&[self.mint.key()])?;
+        self.user_vault.check_depositor(&self.depositor.key())?;
+
+        wrap_sol(
+            self.depositor.to_account_info(),
+            self.token_store_pda.to_account_info(),
+            self.system_program.to_account_info(),
+            amount,
+        )?;
+
+        self.user_vault.credit(amount)?;
+        self.user_vault.timestamp = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(DepositEvent {
+            user_vault: self.user_vault.key(),
+            user: self.user_vault.user,
+            mint: self.user_vault.mint,
+            depositor: self.depositor.key(),
+            amount,
+            vault_amount: self.user_vault.amount,
+            timestamp: self.user_vault.timestamp,
+        });
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+// Withdraw Sol Instruction
+// Unwraps tokens of the wSOL store into lamports of send_lamports_to
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    // global state the vault belongs to
+    pub global_state: Account<'info, GlobalState>,
+    #[account(mut, seeds = [
+        user_account.key().as_ref(), mint.key().as_ref()
+    ], bump = user_vault.bump, has_one = global_state)]
+    pub user_vault: Account<'info, UserCoinVault>,
+    #[account(address = native_mint::ID, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    // system account to receive the lamports
+    #[account(mut)]
+    pub send_lamports_to: SystemAccount<'info>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+    // wSOL store to withdraw from
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint.key().as_ref()
+    ], bump)]
+    pub token_store_pda: InterfaceAccount<'info, TokenAccount>,
+    // temporary wSOL account, closed back to user_account in the same instruction
+    #[account(
+        init,
+        payer = user_account,
+        seeds = [b"unwrap", user_account.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = token_store_authority,
+        token::token_program = token_program,
+    )]
+    pub unwrap_account: InterfaceAccount<'info, TokenAccount>,
+    // vault owner
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+impl<'info> WithdrawSol<'info> {
+    pub fn process(&mut self, bump: u8, amount: u64, full_exit: bool) -> Result<()> {
+        self.user_vault.check_withdraw(amount, full_exit)?;
+        self.user_vault
+            .check_withdraw_recipient(&self.send_lamports_to.key())?;
+
+        unwrap_sol(
+            &self.token_program,
+            &self.token_store_pda,
+            &self.mint,
+            &self.unwrap_account,
+            &self.token_store_authority,
+            self.user_account.to_account_info(),
+            amount,
+            bump,
+        )?;
+
+        if self.send_lamports_to.key() != self.user_account.key() {
+            invoke(
+                &system_instruction::transfer(
+                    &self.user_account.key(),
+                    &self.send_lamports_to.key(),
+                    amount,
+                ),
+                &[
+                    self.user_account.to_account_info(),
+                    self.send_lamports_to.to_account_info(),
+                    self.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        self.user_vault.debit(amount)?;
+        self.user_vault.timestamp = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(WithdrawEvent {
+            user_vault: self.user_vault.key(),
+            user: self.user_vault.user,
+            mint: self.user_vault.mint,
+            send_token_to: self.send_lamports_to.key(),
+            amount,
+            vault_amount: self.user_vault.amount,
+            timestamp: self.user_vault.timestamp,
+        });
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+// Swap Sol In Instruction
+// Swap where the user sends lamports, wrapped into the wSOL store
+#[derive(Accounts)]
+pub struct SwapSolIn<'info> {
+    // global state the vaults belong to
+    pub global_state: Box<Account<'info, GlobalState>>,
+    // Accounts with price from oracle
+    pub get_coin_data: Account<'info, CoinData>,
+    pub send_coin_data: Account<'info, CoinData>,
+    // user_vault_from and user_vault_to must be from the same user
+    #[account(mut, seeds = [
+        user_vault_to.user.as_ref(), mint_receive.key().as_ref()
+    ], bump = user_vault_from.bump, has_one = global_state)]
+    pub user_vault_from: Box<Account<'info, UserCoinVault>>,
+    #[account(mut, seeds = [
+        user_vault_from.user.as_ref(), mint_send.key().as_ref()
+    ], bump = user_vault_to.bump, has_one = global_state)]
+    pub user_vault_to: Box<Account<'info, UserCoinVault>>,
+    /// CHECK: pair config of the vaults owner, validated by seeds. Empty if not created
+    #[account(seeds = [
+        b"pair_config",
+        user_vault_from.user.as_ref(),
+        mint_receive.key().as_ref(),
+        mint_send.key().as_ref(),
+    ], bump)]
+    pub pair_config: AccountInfo<'info>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+    // wSOL, token user sends
+    #[account(address = native_mint::ID, mint::token_program = token_program)]
+    pub mint_send: InterfaceAccount<'info, Mint>,
+    // token user wants
+    #[account(constraint = mint_receive.key() != mint_send.key(),
+        mint::token_program = token_program_receive)]
+    pub mint_receive: InterfaceAccount<'info, Mint>,
+    // system account paying the lamports
+    #[account(mut)]
+    pub get_token_from_authority: Signer<'info>,
+    // User account to send tokens
+    #[account(mut)]
+    pub send_token_to: Box<InterfaceAccount<'info, TokenAccount>>,
+    // PDA to withdraw tokens
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub token_store_pda_from: Box<InterfaceAccount<'info, TokenAccount>>,
+    // wSOL store to deposit lamports
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_send.key().as_ref()
+    ], bump)]
+    pub token_store_pda_to: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Treasury collecting protocol fees in the token user wants, required with a protocol fee
+    #[account(mut, seeds = [
+        b"treasury", global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub treasury: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub system_program: Program<'info, System>,
+    // token program of wSOL
+    pub token_program: Interface<'info, TokenInterface>,
+    // token program of mint_receive
+    pub token_program_receive: Interface<'info, TokenInterface>,
+}
+impl<'info> SwapSolIn<'info> {
+    #[access_control(check_oracle_accounts(
+        &self.get_coin_data,
+        &self.send_coin_data,
+        &self.mint_send,
+        &self.mint_receive
+    ))]
+    pub fn process(&mut self, swap_amount: u64, min_amount: u64, bump: u8) -> Result<()> {
+        let quoter = SwapQuoter::new(
+            &self.global_state,
+            &self.get_coin_data,
+            &self.send_coin_data,
+            &self.mint_send,
+            &self.mint_receive,
+            &self.user_vault_from,
+            &self.user_vault_to,
+            self.get_token_from_authority.key(),
+        )
+        .with_pair_config(&self.pair_config)?;
+        let quote = quoter.quote(
+            swap_amount,
+            amount_before_fee(&self.mint_receive.to_account_info(), min_amount)?,
+        )?;
+
+        wrap_sol(
+            self.get_token_from_authority.to_account_info(),
+            self.token_store_pda_to.to_account_info(),
+            self.system_program.to_account_info(),
+            quote.swap_amount,
+        )?;
+
+        transfer_from_store(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            self.send_token_to.to_account_info(),
+            &self.token_store_authority,
+            quote.amount_to_send,
+            bump,
+        )?;
+
+        transfer_protocol_fee(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            &self.treasury,
+            &self.token_store_authority,
+            quote.protocol_fee,
+            bump,
+        )?;
+
+        quoter.settle(&quote, &mut self.user_vault_from, &mut self.user_vault_to)
+    }
+}
+
+//-----------------------------------------------------
+// Swap Sol Out Instruction
+// Swap where the user receives lamports, unwrapped from the wSOL store
+#[derive(Accounts)]
+pub struct SwapSolOut<'info> {
+    // global state the vaults belong to
+    pub global_state: Box<Account<'info, GlobalState>>,
+    // Accounts with price from oracle
+    pub get_coin_data: Account<'info, CoinData>,
+    pub send_coin_data: Account<'info, CoinData>,
+    // user_vault_from and user_vault_to must be from the same user
+    #[account(mut, seeds = [
+        user_vault_to.user.as_ref(), mint_receive.key().as_ref()
+    ], bump = user_vault_from.bump, has_one = global_state)]
+    pub user_vault_from: Box<Account<'info, UserCoinVault>>,
+    #[account(mut, seeds = [
+        user_vault_from.user.as_ref(), mint_send.key().as_ref()
+    ], bump = user_vault_to.bump, has_one = global_state)]
+    pub user_vault_to: Box<Account<'info, UserCoinVault>>,
+    /// CHECK: pair config of the vaults owner, validated by seeds. Empty if not created
+    #[account(seeds = [
+        b"pair_config",
+        user_vault_from.user.as_ref(),
+        mint_receive.key().as_ref(),
+        mint_send.key().as_ref(),
+    ], bump)]
+    pub pair_config: AccountInfo<'info>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+    // token user sends
+    #[account(mint::token_program = token_program)]
+    pub mint_send: InterfaceAccount<'info, Mint>,
+    // wSOL, token user wants
+    #[account(address = native_mint::ID, constraint = mint_receive.key() != mint_send.key(),
+        mint::token_program = token_program_receive)]
+    pub mint_receive: InterfaceAccount<'info, Mint>,
+    // Account where user have tokens
+    #[account(mut, associated_token::mint = mint_send, associated_token::authority = get_token_from_authority,
+        associated_token::token_program = token_program)]
+    pub get_token_from: Box<InterfaceAccount<'info, TokenAccount>>,
+    // owner or delegate_authority, receives the lamports
+    #[account(mut)]
+    pub get_token_from_authority: Signer<'info>,
+    // wSOL store to withdraw from
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub token_store_pda_from: Box<InterfaceAccount<'info, TokenAccount>>,
+    // PDA to deposit tokens
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_send.key().as_ref()
+    ], bump)]
+    pub token_store_pda_to: Box<InterfaceAccount<'info, TokenAccount>>,
+    // temporary wSOL account, closed back to the user in the same instruction
+    #[account(
+        init,
+        payer = get_token_from_authority,
+        seeds = [b"unwrap", get_token_from_authority.key().as_ref()],
+        bump,
+        token::mint = mint_receive,
+        token::authority = token_store_authority,
+        token::token_program = token_program_receive,
+    )]
+    pub unwrap_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    // wSOL treasury collecting protocol fees, required with a protocol fee
+    #[account(mut, seeds = [
+        b"treasury", global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub treasury: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub system_program: Program<'info, System>,
+    // token program of mint_send
+    pub token_program: Interface<'info, TokenInterface>,
+    // token program of wSOL
+    pub token_program_receive: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+impl<'info> SwapSolOut<'info> {
+    #[access_control(check_oracle_accounts(
+        &self.get_coin_data,
+        &self.send_coin_data,
+        &self.mint_send,
+        &self.mint_receive
+    ))]
+    pub fn process(&mut self, swap_amount: u64, min_amount: u64, bump: u8) -> Result<()> {
+        let quoter = SwapQuoter::new(
+            &self.global_state,
+            &self.get_coin_data,
+            &self.send_coin_data,
+            &self.mint_send,
+            &self.mint_receive,
+            &self.user_vault_from,
+            &self.user_vault_to,
+            self.get_token_from_authority.key(),
+        )
+        .with_pair_config(&self.pair_config)?;
+        let quote = quoter.quote(
+            amount_after_fee(&self.mint_send.to_account_info(), swap_amount)?,
+            min_amount,
+        )?;
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.get_token_from.to_account_info(),
+                    mint: self.mint_send.to_account_info(),
+                    to: self.token_store_pda_to.to_account_info(),
+                    authority: self.get_token_from_authority.to_account_info(),
+                },
+            ),
+            swap_amount,
+            self.mint_send.decimals,
+        )?;
+
+        unwrap_sol(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            &self.unwrap_account,
+            &self.token_store_authority,
+            self.get_token_from_authority.to_account_info(),
+            quote.amount_to_send,
+            bump,
+        )?;
+
+        transfer_protocol_fee(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            &self.treasury,
+            &self.token_store_authority,
+            quote.protocol_fee,
+            bump,
+        )?;
+
+        quoter.settle(&quote, &mut self.user_vault_from, &mut self.user_vault_to)
+    }
+}
+
+/// Moves lamports of a system account into a wSOL token account and syncs its balance
+pub fn wrap_sol<'info>(
+    from: AccountInfo<'info>,
+    token_account: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    invoke(
+        &system_instruction::transfer(from.key, token_account.key, amount),
+        &[from, token_account.clone(), system_program],
+    )?;
+    invoke(
+        &spl_token::instruction::sync_native(&spl_token::ID, token_account.key)?,
+        &[token_account],
+    )?;
+    Ok(())
+}
+
+/// Sends wSOL out of a store as lamports to `payer`, through unwrap_account
+/// which is closed back to the payer of its rent
+#[allow(clippy::too_many_arguments)]
+fn unwrap_sol<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    token_store: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    unwrap_account: &InterfaceAccount<'info, TokenAccount>,
+    token_store_authority: &AccountInfo<'info>,
+    payer: AccountInfo<'info>,
+    amount: u64,
+    bump: u8,
+) -> Result<()> {
+    transfer_from_store(
+        token_program,
+        token_store,
+        mint,
+        unwrap_account.to_account_info(),
+        token_store_authority,
+        amount,
+        bump,
+    )?;
+
+    let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
+    anchor_spl::token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: unwrap_account.to_account_info(),
+            destination: payer,
+            authority: token_store_authority.clone(),
+        },
+        &[seeds],
+    ))
+}
@@ -0,0 +1,441 @@
+use crate::error::*;
+use crate::events::*;
+use crate::instructions::swap::{check_oracle_price, check_vault_price_guards, ErrorCode};
+use crate::math::{self, calculate_value, mul_div, value_after_buy_fee, Rounding, TokenPrice};
+use crate::states::*;
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+use delphor_oracle_aggregator::CoinData;
+use std::convert::TryFrom;
+
+//-----------------------------------------------------
+// Init Pool Instruction
+#[derive(Accounts)]
+pub struct InitPool<'info> {
+    // global state
+    pub global_state: Account<'info, GlobalState>,
+
+    // pool manager, signer
+    #[account(mut)]
+    pub manager: Signer<'info>,
+
+    #[account(
+        init,
+        payer = manager,
+        space = 8 + core::mem::size_of::<Pool>() + MAX_POOL_MINTS * 32 + 128,
+        seeds = [
+            b"pool",
+            global_state.key().as_ref(),
+            manager.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // LP share token of the pool
+    #[account(
+        init,
+        payer = manager,
+        seeds = [b"lp_mint", pool.key().as_ref()],
+        bump,
+        mint::decimals = POOL_LP_DECIMALS,
+        mint::authority = pool,
+    )]
+    pub lp_mint: Account<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+impl<'info> InitPool<'info> {
+    pub fn process(&mut self, bump: u8) -> Result<()> {
+        *self.pool = Pool {
+            bump,
+            global_state: self.global_state.key(),
+            manager: self.manager.key(),
+            lp_mint: self.lp_mint.key(),
+            mints: Vec::new(),
+        };
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+// Init Pool Vault Instruction
+// The pool owns the vault and the manager is its operator
+#[derive(Accounts)]
+pub struct InitPoolVault<'info> {
+    // global state
+    pub global_state: Account<'info, GlobalState>,
+
+    // pool manager, signer
+    #[account(mut)]
+    pub manager: Signer<'info>,
+
+    #[account(mut, has_one = manager, has_one = global_state)]
+    pub pool: Account<'info, Pool>,
+
+    // for what token
+    pub mint: Account<'info, Mint>,
+
+    // pool vault, create PDA
+    #[account(
+        init,
+        payer = manager,
+        space = 8 + core::mem::size_of::<UserCoinVault>() + 3600, // 3600 bytes future expansion
+        seeds = [
+            pool.key().as_ref(),
+            mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub pool_vault: Account<'info, UserCoinVault>,
+
+    pub system_program: Program<'info, System>,
+}
+impl<'info> InitPoolVault<'info> {
+    pub fn process(&mut self, bump: u8) -> Result<()> {
+        if self.pool.mints.len() >= MAX_POOL_MINTS {
+            return err!(DelphorError::TooManyPoolMints);
+        }
+        self.pool.mints.push(self.mint.key());
+
+        *self.pool_vault = UserCoinVault {
+            bump,
+            user: self.pool.key(),
+            mint: self.mint.key(),
+            timestamp: Clock::get().unwrap().unix_timestamp as u64,
+            global_state: self.global_state.key(),
+            operator: self.manager.key(),
+            // Pool vaults are funded only through deposit_pool, minting shares
+            reject_external_deposits: true,
+            ..UserCoinVault::default()
+        };
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+// Deposit Pool Instruction
+// Deposits a single token, minting LP shares by its oracle value net of the pool vault
+// buy fee. Shares are a claim on every pool token, so a deposit is checked as a swap
+// into the pool vault: receive status, max and price guards.
+// Remaining accounts are (pool_vault, coin_data) pairs, in the order of pool.mints.
+#[derive(Accounts)]
+pub struct DepositPool<'info> {
+    // global state the pool belongs to
+    pub global_state: Account<'info, GlobalState>,
+    #[account(has_one = global_state, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    // for what token
+    pub mint: Account<'info, Mint>,
+    #[account(mut, seeds = [
+        pool.key().as_ref(), mint.key().as_ref()
+    ], bump = pool_vault.bump)]
+    pub pool_vault: Account<'info, UserCoinVault>,
+    // Account where user have tokens
+    #[account(mut, constraint = get_token_from.mint == mint.key())]
+    pub get_token_from: Account<'info, TokenAccount>,
+    // owner or delegate_authority
+    pub get_token_from_authority: Signer<'info>,
+    // Account where the program will store the tokens
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint.key().as_ref()
+    ], bump)]
+    pub token_store_pda: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    // Account to receive the LP shares
+    #[account(mut, constraint = lp_token_to.mint == lp_mint.key())]
+    pub lp_token_to: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+impl<'info> DepositPool<'info> {
+    pub fn process(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        amount: u64,
+    ) -> Result<()> {
+        self.global_state.check_not_paused(&[self.mint.key()])?;
+
+        if *self.get_token_from_authority.key != self.get_token_from.owner {
+            msg!("invalid get_token_from owner/auth",);
+            return Err(error!(DelphorError::NotTheOwner));
+        }
+
+        let (pool_value, token_price, coin_data) = self.pool_value(remaining_accounts)?;
+        self.check_pool_vault_receive(amount, &coin_data)?;
+        // Fee priced on the inventory before the deposit, as in swaps
+        let deposit_value = value_after_buy_fee(
+            calculate_value(amount, &token_price)?,
+            self.pool_vault.effective_buy_fee(),
+        )?;
+        let supply = self.lp_mint.supply;
+        let shares = if supply == 0 {
+            deposit_value
+        } else if pool_value == 0 {
+            return err!(DelphorError::InvalidShares);
+        } else {
+            // Rounded down, in favour of the pool
+            mul_div(deposit_value, supply as u128, pool_value, Rounding::Down)?
+        };
+        let shares = u64::try_from(shares).map_err(|_| error!(DelphorError::InvalidShares))?;
+        if shares == 0 {
+            return err!(DelphorError::InvalidShares);
+        }
+
+        anchor_spl::token::transfer(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                Transfer {
+                    from: self.get_token_from.to_account_info(),
+                    to: self.token_store_pda.to_account_info(),
+                    authority: self.get_token_from_authority.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let global_state = self.global_state.key();
+        let seeds: &[&[u8]] = &[
+            b"pool",
+            global_state.as_ref(),
+            self.pool.manager.as_ref(),
+            &[self.pool.bump],
+        ];
+        anchor_spl::token::mint_to(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                MintTo {
+                    mint: self.lp_mint.to_account_info(),
+                    to: self.lp_token_to.to_account_info(),
+                    authority: self.pool.to_account_info(),
+                },
+                &[seeds],
+            ),
+            shares,
+        )?;
+
+        self.pool_vault.credit(amount)?;
+        self.pool_vault.timestamp = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(PoolDepositEvent {
+            pool: self.pool.key(),
+            mint: self.mint.key(),
+            depositor: self.get_token_from_authority.key(),
+            amount,
+            shares,
+            timestamp: self.pool_vault.timestamp,
+        });
+        Ok(())
+    }
+
+    fn check_pool_vault_receive(&self, amount: u64, coin_data: &CoinData) -> Result<()> {
+        if !self.pool_vault.receive_status {
+            return err!(ErrorCode::VaultRecieveOff);
+        }
+        if self.pool_vault.amount.saturating_add(amount) > self.pool_vault.max {
+            return err!(ErrorCode::ExceedsMaxAmount);
+        }
+        check_vault_price_guards(
+            &self.pool_vault,
+            coin_data.price,
+            coin_data.last_update_timestamp,
+        )
+    }
+
+    /// Oracle value of all the pool vaults, with the price and the coin data of the
+    /// deposited token
+    fn pool_value(
+        &self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<(u128, TokenPrice, CoinData)> {
+        if remaining_accounts.len() != self.pool.mints.len() * 2 {
+            return err!(DelphorError::InvalidPoolAccounts);
+        }
+
+        let mut pool_value: u128 = 0;
+        let mut mint_coin_data = None;
+        for (mint, accounts) in self
+            .pool
+            .mints
+            .iter()
+            .zip(remaining_accounts.chunks_exact(2))
+        {
+            let pool_vault = Account::<UserCoinVault>::try_from(&accounts[0])?;
+            check_pool_vault(&pool_vault, &self.pool.key(), mint)?;
+
+            let coin_data = Account::<CoinData>::try_from(&accounts[1])?;
+            let (coin_pda, _bump_seed) =
+                Pubkey::find_program_address(&[mint.as_ref()], &delphor_oracle_aggregator::ID);
+            if coin_data.key() != coin_pda {
+                msg!(
+                    "Invalid coin_data {}. Expected {}",
+                    coin_data.key(),
+                    coin_pda
+                );
+                return err!(DelphorError::InvalidPoolAccounts);
+            }
+            check_oracle_price(&coin_data, &self.global_state)?;
+
+            // decimals of the other pool mints were checked against the mint by init_coin
+            let decimals = if *mint == self.mint.key() {
+                self.mint.decimals
+            } else {
+                coin_data.decimals
+            };
+            let token_price = TokenPrice::new(&coin_data, decimals);
+            let vault_value = calculate_value(pool_vault.amount, &token_price)?;
+            pool_value = pool_value
+                .checked_add(vault_value)
+                .ok_or(error!(DelphorError::MathOverflow))?;
+            if *mint == self.mint.key() {
+                mint_coin_data = Some((token_price, coin_data.into_inner()));
+            }
+        }
+
+        let (token_price, coin_data) =
+            mint_coin_data.ok_or(error!(DelphorError::InvalidPoolAccounts))?;
+        Ok((pool_value, token_price, coin_data))
+    }
+}
+
+//-----------------------------------------------------
+// Withdraw Pool Instruction
+// Burns LP shares for the pro-rata part of every pool vault.
+// Remaining accounts are (pool_vault, token_store, send_token_to) triples,
+// in the order of pool.mints.
+#[derive(Accounts)]
+pub struct WithdrawPool<'info> {
+    // global state the pool belongs to
+    pub global_state: Account<'info, GlobalState>,
+    #[account(has_one = global_state, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    // Account with the LP shares to burn
+    #[account(mut, constraint = lp_token_from.mint == lp_mint.key())]
+    pub lp_token_from: Account<'info, TokenAccount>,
+    // lp_token_from owner or delegate_authority, receives the tokens
+    pub user_account: Signer<'info>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+impl<'info> WithdrawPool<'info> {
+    pub fn process(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        shares: u64,
+        bump: u8,
+    ) -> Result<()> {
+        let supply = self.lp_mint.supply;
+        if shares == 0 || shares > supply {
+            return err!(DelphorError::InvalidShares);
+        }
+        if remaining_accounts.len() != self.pool.mints.len() * 3 {
+            return err!(DelphorError::InvalidPoolAccounts);
+        }
+
+        let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
+        let signer = &[seeds];
+        let timestamp = Clock::get().unwrap().unix_timestamp as u64;
+        let mut amounts = Vec::with_capacity(self.pool.mints.len());
+
+        for (mint, accounts) in self
+            .pool
+            .mints
+            .iter()
+            .zip(remaining_accounts.chunks_exact(3))
+        {
+            let mut pool_vault = Account::<UserCoinVault>::try_from(&accounts[0])?;
+            check_pool_vault(&pool_vault, &self.pool.key(), mint)?;
+
+            let (token_store_pda, _bump_seed) = Pubkey::find_program_address(
+                &[self.global_state.key().as_ref(), mint.as_ref()],
+                &crate::ID,
+            );
+            if accounts[1].key() != token_store_pda {
+                msg!(
+                    "Invalid token_store {}. Expected {}",
+                    accounts[1].key(),
+                    token_store_pda
+                );
+                return err!(DelphorError::InvalidPoolAccounts);
+            }
+
+            let send_token_to = Account::<TokenAccount>::try_from(&accounts[2])?;
+            if send_token_to.mint != *mint || send_token_to.owner != self.user_account.key() {
+                msg!("Invalid send_token_to {}", send_token_to.key());
+                return err!(DelphorError::InvalidRecipient);
+            }
+
+            // Rounded down, in favour of the pool
+            let amount = math::to_u64(mul_div(
+                pool_vault.amount as u128,
+                shares as u128,
+                supply as u128,
+                Rounding::Down,
+            )?)?;
+            if amount > 0 {
+                anchor_spl::token::transfer(
+                    CpiContext::new_with_signer(
+                        self.token_program.to_account_info(),
+                        Transfer {
+                            from: accounts[1].clone(),
+                            to: send_token_to.to_account_info(),
+                            authority: self.token_store_authority.to_account_info(),
+                        },
+                        signer,
+                    ),
+                    amount,
+                )?;
+
+                pool_vault.debit(amount)?;
+                pool_vault.timestamp = timestamp;
+                // Vaults from remaining accounts are not persisted by anchor
+                pool_vault.exit(&crate::ID)?;
+            }
+            amounts.push(amount);
+        }
+
+        anchor_spl::token::burn(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                Burn {
+                    mint: self.lp_mint.to_account_info(),
+                    from: self.lp_token_from.to_account_info(),
+                    authority: self.user_account.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+
+        emit!(PoolWithdrawEvent {
+            pool: self.pool.key(),
+            user: self.user_account.key(),
+            shares,
+            amounts,
+            timestamp,
+        });
+        Ok(())
+    }
+}
+
+fn check_pool_vault(
+    pool_vault: &Account<UserCoinVault>,
+    pool: &Pubkey,
+    mint: &Pubkey,
+) -> Result<()> {
+    let pda = Pubkey::create_program_address(
+        &[pool.as_ref(), mint.as_ref(), &[pool_vault.bump]],
+        &crate::ID,
+    )
+    .map_err(|_| error!(DelphorError::InvalidPoolAccounts))?;
+    if pool_vault.key() != pda || pool_vault.user != *pool || pool_vault.mint != *mint {
+        msg!("Invalid pool_vault {}. Expected {}", pool_vault.key(), pda);
+        return err!(DelphorError::InvalidPoolAccounts);
+    }
+    Ok(())
+}
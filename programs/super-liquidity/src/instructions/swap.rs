@@ -1,158 +1,788 @@
+use crate::events::*;
+use crate::math::{
+    self, calculate_counter_price, calculate_protocol_fee, Rounding, SwapPrice, TokenPrice,
+};
 use crate::states::*;
+use crate::token::{amount_after_fee, amount_before_fee};
 use anchor_lang::prelude::*;
-use anchor_spl::token::Mint;
-use anchor_spl::token::{Token, TokenAccount, Transfer};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
 use delphor_oracle_aggregator::CoinData;
+use std::cmp;
 
 //-----------------------------------------------------
 // Swap Instruction
+// Amounts are the ones the user sends and receives, the vaults trade them net of the
+// transfer fees of Token-2022 mints
 #[derive(Accounts)]
 pub struct Swap<'info> {
+    // global state the vaults belong to
+    pub global_state: Box<Account<'info, GlobalState>>,
     // Accounts with price from oracle
     pub get_coin_data: Account<'info, CoinData>,
     pub send_coin_data: Account<'info, CoinData>,
     // user_vault_from and user_vault_to must be from the same user
     #[account(mut, seeds = [
         user_vault_to.user.as_ref(), mint_receive.key().as_ref()
-    ], bump = user_vault_from.bump)]
+    ], bump = user_vault_from.bump, has_one = global_state)]
     pub user_vault_from: Box<Account<'info, UserCoinVault>>,
     #[account(mut, seeds = [
         user_vault_from.user.as_ref(), mint_send.key().as_ref()
-    ], bump = user_vault_to.bump)]
+    ], bump = user_vault_to.bump, has_one = global_state)]
     pub user_vault_to: Box<Account<'info, UserCoinVault>>,
+    /// CHECK: pair config of the vaults owner, validated by seeds. Empty if not created
+    #[account(seeds = [
+        b"pair_config",
+        user_vault_from.user.as_ref(),
+        mint_receive.key().as_ref(),
+        mint_send.key().as_ref(),
+    ], bump)]
+    pub pair_config: AccountInfo<'info>,
     /// CHECK:
-    #[account(mut)]
+    #[account(seeds = [b"store_auth"], bump)]
     pub token_store_authority: AccountInfo<'info>,
     // token user sends
-    pub mint_send: Account<'info, Mint>,
+    #[account(mint::token_program = token_program)]
+    pub mint_send: InterfaceAccount<'info, Mint>,
     // token user wants
-    #[account(constraint = mint_receive.key() != mint_send.key())]
+    #[account(constraint = mint_receive.key() != mint_send.key(),
+        mint::token_program = token_program_receive)]
     // Validates the tokens being swapped are differents
-    pub mint_receive: Account<'info, Mint>,
+    pub mint_receive: InterfaceAccount<'info, Mint>,
     // Account where user have tokens
-    #[account(mut, associated_token::mint = mint_send, associated_token::authority = get_token_from_authority)]
-    pub get_token_from: Box<Account<'info, TokenAccount>>,
+    #[account(mut, associated_token::mint = mint_send, associated_token::authority = get_token_from_authority,
+        associated_token::token_program = token_program)]
+    pub get_token_from: Box<InterfaceAccount<'info, TokenAccount>>,
     // owner or delegate_authority
     pub get_token_from_authority: Signer<'info>,
     // User account to send tokens
     #[account(mut)]
-    pub send_token_to: Box<Account<'info, TokenAccount>>,
+    pub send_token_to: Box<InterfaceAccount<'info, TokenAccount>>,
     // PDA to withdraw tokens
-    #[account(mut, associated_token::mint = mint_receive, associated_token::authority = token_store_authority)]
-    pub token_store_pda_from: Box<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub token_store_pda_from: Box<InterfaceAccount<'info, TokenAccount>>,
     // PDA to deposit tokens
-    #[account(mut, associated_token::mint = mint_send, associated_token::authority = token_store_authority)]
-    pub token_store_pda_to: Box<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_send.key().as_ref()
+    ], bump)]
+    pub token_store_pda_to: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Treasury collecting protocol fees in the token user wants, required with a protocol fee
+    #[account(mut, seeds = [
+        b"treasury", global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub treasury: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    // token program of mint_send
+    pub token_program: Interface<'info, TokenInterface>,
+    // token program of mint_receive, the same account if both mints share it
+    pub token_program_receive: Interface<'info, TokenInterface>,
 }
 impl<'info> Swap<'info> {
-    #[access_control(check_oracle_accounts(&self))]
+    #[access_control(check_oracle_accounts(
+        &self.get_coin_data,
+        &self.send_coin_data,
+        &self.mint_send,
+        &self.mint_receive
+    ))]
     pub fn process(&mut self, swap_amount: u64, min_amount: u64, bump: u8) -> Result<()> {
-        let get_coin_price = self.get_coin_data.price;
-        let get_coin_decimals = self.get_coin_data.decimals;
-        let send_coin_price = self.send_coin_data.price;
-        let send_coin_decimals = self.send_coin_data.decimals;
-        let user_vault_from = &mut self.user_vault_from;
-        let user_vault_to = &mut self.user_vault_to;
-
-        if !user_vault_from.provide_status {
-            return err!(ErrorCode::VaultProvideOff);
-        }
+        let quoter = self.quoter()?;
+        let quote = quoter.quote(
+            amount_after_fee(&self.mint_send.to_account_info(), swap_amount)?,
+            amount_before_fee(&self.mint_receive.to_account_info(), min_amount)?,
+        )?;
+        self.execute(&quoter, &quote, swap_amount, bump)
+    }
 
-        if !user_vault_to.receive_status {
-            return err!(ErrorCode::VaultRecieveOff);
+    #[access_control(check_oracle_accounts(
+        &self.get_coin_data,
+        &self.send_coin_data,
+        &self.mint_send,
+        &self.mint_receive
+    ))]
+    pub fn process_exact_out(
+        &mut self,
+        amount_to_send: u64,
+        max_amount_in: u64,
+        bump: u8,
+    ) -> Result<()> {
+        let quoter = self.quoter()?;
+        let quote = quoter.quote_exact_out(
+            amount_before_fee(&self.mint_receive.to_account_info(), amount_to_send)?,
+            amount_after_fee(&self.mint_send.to_account_info(), max_amount_in)?,
+        )?;
+        let amount_in = amount_before_fee(&self.mint_send.to_account_info(), quote.swap_amount)?;
+        if amount_in > max_amount_in {
+            return err!(ErrorCode::ExceedsMaxAmountIn);
         }
+        self.execute(&quoter, &quote, amount_in, bump)
+    }
 
-        if user_vault_from.limit_price_status && user_vault_from.limit_price > send_coin_price {
-            return err!(ErrorCode::PriceUnderLimitPrice);
+    fn quoter(&self) -> Result<SwapQuoter> {
+        SwapQuoter::new(
+            &self.global_state,
+            &self.get_coin_data,
+            &self.send_coin_data,
+            &self.mint_send,
+            &self.mint_receive,
+            &self.user_vault_from,
+            &self.user_vault_to,
+            self.get_token_from_authority.key(),
+        )
+        .with_pair_config(&self.pair_config)
+    }
+
+    // amount_in is sent by the user, the store receives quote.swap_amount out of it
+    fn execute(
+        &mut self,
+        quoter: &SwapQuoter,
+        quote: &SwapQuote,
+        amount_in: u64,
+        bump: u8,
+    ) -> Result<()> {
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                self.token_program.to_account_info().clone(),
+                TransferChecked {
+                    from: self.get_token_from.to_account_info(),
+                    mint: self.mint_send.to_account_info(),
+                    to: self.token_store_pda_to.to_account_info(),
+                    authority: self.get_token_from_authority.to_account_info().clone(),
+                },
+            ),
+            amount_in,
+            self.mint_send.decimals,
+        )?;
+
+        transfer_from_store(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            self.send_token_to.to_account_info(),
+            &self.token_store_authority,
+            quote.amount_to_send,
+            bump,
+        )?;
+
+        transfer_protocol_fee(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            &self.treasury,
+            &self.token_store_authority,
+            quote.protocol_fee,
+            bump,
+        )?;
+
+        quoter.settle(quote, &mut self.user_vault_from, &mut self.user_vault_to)
+    }
+}
+
+//-----------------------------------------------------
+// Quote Swap Instruction
+// Read only, runs every check of a swap and returns a SwapQuote with set_return_data.
+// Meant to be simulated by clients to estimate min_amount.
+#[derive(Accounts)]
+pub struct QuoteSwap<'info> {
+    // global state the vaults belong to
+    pub global_state: Box<Account<'info, GlobalState>>,
+    // Accounts with price from oracle
+    pub get_coin_data: Account<'info, CoinData>,
+    pub send_coin_data: Account<'info, CoinData>,
+    // user_vault_from and user_vault_to must be from the same user
+    #[account(seeds = [
+        user_vault_to.user.as_ref(), mint_receive.key().as_ref()
+    ], bump = user_vault_from.bump, has_one = global_state)]
+    pub user_vault_from: Box<Account<'info, UserCoinVault>>,
+    #[account(seeds = [
+        user_vault_from.user.as_ref(), mint_send.key().as_ref()
+    ], bump = user_vault_to.bump, has_one = global_state)]
+    pub user_vault_to: Box<Account<'info, UserCoinVault>>,
+    /// CHECK: pair config of the vaults owner, validated by seeds. Empty if not created
+    #[account(seeds = [
+        b"pair_config",
+        user_vault_from.user.as_ref(),
+        mint_receive.key().as_ref(),
+        mint_send.key().as_ref(),
+    ], bump)]
+    pub pair_config: AccountInfo<'info>,
+    // token user sends
+    pub mint_send: InterfaceAccount<'info, Mint>,
+    // token user wants
+    #[account(constraint = mint_receive.key() != mint_send.key())]
+    pub mint_receive: InterfaceAccount<'info, Mint>,
+    /// CHECK: taker the swap is quoted for, checked against the vault allowlists
+    pub taker: AccountInfo<'info>,
+}
+impl<'info> QuoteSwap<'info> {
+    #[access_control(check_oracle_accounts(
+        &self.get_coin_data,
+        &self.send_coin_data,
+        &self.mint_send,
+        &self.mint_receive
+    ))]
+    pub fn process(&self, swap_amount: u64, min_amount: u64) -> Result<()> {
+        let mint_send = self.mint_send.to_account_info();
+        let mint_receive = self.mint_receive.to_account_info();
+        let quote = SwapQuoter::new(
+            &self.global_state,
+            &self.get_coin_data,
+            &self.send_coin_data,
+            &self.mint_send,
+            &self.mint_receive,
+            &self.user_vault_from,
+            &self.user_vault_to,
+            self.taker.key(),
+        )
+        .with_pair_config(&self.pair_config)
+        .and_then(|quoter| {
+            quoter.quote(
+                amount_after_fee(&mint_send, swap_amount)?,
+                amount_before_fee(&mint_receive, min_amount)?,
+            )
+        })
+        // Amounts the taker sends and receives, as the swap arguments
+        .and_then(|quote| {
+            Ok(SwapQuote {
+                swap_amount,
+                amount_to_send: amount_after_fee(&mint_receive, quote.amount_to_send)?,
+                ..quote
+            })
+        })
+        .unwrap_or_else(|error| SwapQuote {
+            swap_amount,
+            error_code: error_code(&error),
+            ..SwapQuote::default()
+        });
+
+        anchor_lang::solana_program::program::set_return_data(&quote.try_to_vec()?);
+        Ok(())
+    }
+}
+
+/// Result of a swap quote, in amounts received and sent by the vault stores.
+/// quote_swap returns it with the amounts the taker sends and receives instead.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct SwapQuote {
+    pub swap_amount: u64,
+    pub amount_to_send: u64,
+    pub protocol_fee: u64,
+    // effective fees, in basis points
+    pub buy_fee: u32,
+    pub sell_fee: u32,
+    // 0 if the swap would succeed, otherwise the error code it would fail with
+    pub error_code: u64,
+}
+
+/// Snapshot of the accounts a swap is checked and priced with, shared by every swap
+/// instruction. The vaults are copies with the pair config overrides applied.
+pub struct SwapQuoter {
+    pub global_state: GlobalState,
+    pub get_coin_data: CoinData,
+    pub send_coin_data: CoinData,
+    // oracle prices with the decimals of mint_send and mint_receive
+    pub get_coin_price: TokenPrice,
+    pub send_coin_price: TokenPrice,
+    pub user_vault_from: UserCoinVault,
+    pub user_vault_to: UserCoinVault,
+    pub mint_send: Pubkey,
+    pub mint_receive: Pubkey,
+    pub taker: Pubkey,
+    pub pair_config: Option<PairConfig>,
+}
+impl SwapQuoter {
+    /// Quoter of a swap sending `mint_receive` out of `user_vault_from` for `mint_send`
+    /// into `user_vault_to`, without pair config
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        global_state: &GlobalState,
+        get_coin_data: &CoinData,
+        send_coin_data: &CoinData,
+        mint_send: &InterfaceAccount<Mint>,
+        mint_receive: &InterfaceAccount<Mint>,
+        user_vault_from: &UserCoinVault,
+        user_vault_to: &UserCoinVault,
+        taker: Pubkey,
+    ) -> Self {
+        SwapQuoter {
+            global_state: global_state.clone(),
+            get_coin_data: get_coin_data.clone(),
+            send_coin_data: send_coin_data.clone(),
+            get_coin_price: TokenPrice::new(get_coin_data, mint_send.decimals),
+            send_coin_price: TokenPrice::new(send_coin_data, mint_receive.decimals),
+            user_vault_from: user_vault_from.clone(),
+            user_vault_to: user_vault_to.clone(),
+            mint_send: mint_send.key(),
+            mint_receive: mint_receive.key(),
+            taker,
+            pair_config: None,
         }
+    }
 
-        if user_vault_to.amount + swap_amount > user_vault_to.max {
-            return err!(ErrorCode::ExceedsMaxAmount);
+    pub fn with_pair_config(mut self, pair_config: &AccountInfo) -> Result<Self> {
+        if let Some(pair_config) = load_pair_config(pair_config)? {
+            pair_config
+                .config
+                .apply(&mut self.user_vault_from, &mut self.user_vault_to);
+            self.pair_config = Some(pair_config.config.clone());
         }
+        Ok(self)
+    }
+
+    pub fn quote(&self, swap_amount: u64, min_amount: u64) -> Result<SwapQuote> {
+        self.check_vaults_status()?;
 
-        let token_price: u128 = (get_coin_price as u128 * (10000 - user_vault_to.buy_fee as u128)
-            / 10000)
-            * u128::pow(10, send_coin_decimals as u32)
-            / (send_coin_price as u128 * (10000 + user_vault_from.sell_fee as u128) / 10000);
         // Calculate final amount with oracle price and fees
-        let amount_to_send: u64 =
-            ((swap_amount as u128 * token_price) / u128::pow(10, get_coin_decimals as u32)) as u64;
+        let amount_to_send = self.swap_price()?.amount_out(swap_amount)?;
 
         if amount_to_send < min_amount {
             return err!(ErrorCode::InsufficientAmount);
         }
 
-        if user_vault_from.amount < amount_to_send {
-            return err!(ErrorCode::VaultInsufficientAmount);
+        self.checked_quote(swap_amount, amount_to_send)
+    }
+
+    pub fn quote_exact_out(&self, amount_to_send: u64, max_amount_in: u64) -> Result<SwapQuote> {
+        self.check_vaults_status()?;
+
+        let swap_price = self.swap_price()?;
+        if swap_price.is_zero() {
+            return err!(ErrorCode::ExceedsMaxAmountIn);
         }
+        // Calculate amount the user must send to receive exactly amount_to_send,
+        // rounded up in favour of the vault
+        let swap_amount = swap_price.amount_in(amount_to_send, Rounding::Up)?;
 
-        if user_vault_from.amount - amount_to_send < user_vault_from.min {
-            return err!(ErrorCode::ExceedsMinAmount);
+        if swap_amount > max_amount_in {
+            return err!(ErrorCode::ExceedsMaxAmountIn);
         }
 
-        anchor_spl::token::transfer(
-            CpiContext::new(
-                self.token_program.to_account_info().clone(),
-                Transfer {
-                    from: self.get_token_from.to_account_info(),
-                    to: self.token_store_pda_to.to_account_info(),
-                    authority: self.get_token_from_authority.to_account_info().clone(),
-                },
-            ),
+        self.checked_quote(swap_amount, amount_to_send)
+    }
+
+    /// Largest part of `swap_amount` the vaults can fill, None if they can't take any.
+    /// The vault status must be checked by the caller, used by swap_multi to split a trade.
+    pub fn quote_up_to(&self, swap_amount: u64) -> Result<Option<SwapQuote>> {
+        let swap_price = self.swap_price()?;
+        let price_without_fees = SwapPrice::new(&self.get_coin_price, &self.send_coin_price, 0, 0)?;
+        if swap_price.is_zero() || price_without_fees.is_zero() {
+            return Ok(None);
+        }
+
+        // Max amount the vault to can receive and max amount the vault from can provide.
+        // Priced without fees, so the amount sent plus the protocol fee always fits.
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+        let user_vault_from = &self.user_vault_from;
+        let max_amount_out = cmp::min(
+            user_vault_from.amount.saturating_sub(user_vault_from.min),
+            user_vault_from.available_outflow(now),
+        );
+        let mut amount_in = cmp::min(
             swap_amount,
+            cmp::min(
+                self.user_vault_to
+                    .max
+                    .saturating_sub(self.user_vault_to.amount),
+                price_without_fees.max_amount_in(max_amount_out),
+            ),
+        );
+        // The pair max trade size applies to the amount sent, net of fees
+        if let Some(pair_config) = &self.pair_config {
+            if pair_config.max_amount > 0 {
+                amount_in = cmp::min(amount_in, swap_price.max_amount_in(pair_config.max_amount));
+            }
+        }
+
+        let amount_to_send = swap_price.amount_out(amount_in)?;
+        if amount_to_send == 0 {
+            return Ok(None);
+        }
+        self.checked_quote(amount_in, amount_to_send).map(Some)
+    }
+
+    /// Moves the quoted amounts through the vault balances and emits the SwapEvent.
+    /// The token transfers are done by the caller.
+    pub fn settle<'info>(
+        &self,
+        quote: &SwapQuote,
+        user_vault_from: &mut Account<'info, UserCoinVault>,
+        user_vault_to: &mut Account<'info, UserCoinVault>,
+    ) -> Result<()> {
+        // The protocol fee is paid by the vault from, out of its fee spread
+        let timestamp = Clock::get().unwrap().unix_timestamp as u64;
+        let amount_from_vault = math::checked_add(quote.amount_to_send, quote.protocol_fee)?;
+        user_vault_to.credit(quote.swap_amount)?;
+        user_vault_from.debit(amount_from_vault)?;
+        user_vault_from.record_outflow(amount_from_vault, timestamp);
+        user_vault_from.timestamp = timestamp;
+
+        emit!(SwapEvent {
+            user_vault_from: user_vault_from.key(),
+            user_vault_to: user_vault_to.key(),
+            user: user_vault_from.user,
+            taker: self.taker,
+            mint_send: self.mint_send,
+            mint_receive: self.mint_receive,
+            swap_amount: quote.swap_amount,
+            amount_to_send: quote.amount_to_send,
+            get_coin_price: self.get_coin_data.price,
+            send_coin_price: self.send_coin_data.price,
+            buy_fee: quote.buy_fee,
+            sell_fee: quote.sell_fee,
+            protocol_fee: quote.protocol_fee,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Pause, oracle, pair config, status, taker, limit price and price guard checks
+    pub fn check_vaults_status(&self) -> Result<()> {
+        self.global_state
+            .check_not_paused(&[self.mint_send, self.mint_receive])?;
+        check_oracle_prices(
+            &self.get_coin_data,
+            &self.send_coin_data,
+            &self.global_state,
         )?;
 
-        let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
-        let signer = &[&seeds[..]];
+        if let Some(pair_config) = &self.pair_config {
+            if !pair_config.enabled {
+                return err!(ErrorCode::PairDisabled);
+            }
+        }
 
-        anchor_spl::token::transfer(
-            CpiContext::new_with_signer(
-                self.token_program.to_account_info().clone(),
-                Transfer {
-                    from: self.token_store_pda_from.to_account_info(),
-                    to: self.send_token_to.to_account_info(),
-                    authority: self.token_store_authority.to_account_info(),
-                },
-                signer,
-            ),
+        if !self.user_vault_from.provide_status {
+            return err!(ErrorCode::VaultProvideOff);
+        }
+
+        if !self.user_vault_to.receive_status {
+            return err!(ErrorCode::VaultRecieveOff);
+        }
+
+        self.user_vault_from.check_taker(&self.taker)?;
+        self.user_vault_to.check_taker(&self.taker)?;
+
+        check_limit_prices(
+            &self.user_vault_from,
+            &self.user_vault_to,
+            &self.get_coin_price,
+            &self.send_coin_price,
+            &self.mint_send,
+            &self.mint_receive,
+        )?;
+
+        let price_timestamp = cmp::min(
+            self.get_coin_data.last_update_timestamp,
+            self.send_coin_data.last_update_timestamp,
+        );
+        check_vault_price_guards(
+            &self.user_vault_from,
+            self.send_coin_data.price,
+            price_timestamp,
+        )?;
+        check_vault_price_guards(
+            &self.user_vault_to,
+            self.get_coin_data.price,
+            price_timestamp,
+        )
+    }
+
+    pub fn swap_price(&self) -> Result<SwapPrice> {
+        SwapPrice::new(
+            &self.get_coin_price,
+            &self.send_coin_price,
+            self.user_vault_to.effective_buy_fee(),
+            self.user_vault_from.effective_sell_fee(),
+        )
+    }
+
+    /// Checks the vault balances can take the swap and adds the protocol fee
+    fn checked_quote(&self, swap_amount: u64, amount_to_send: u64) -> Result<SwapQuote> {
+        let amount_without_fees =
+            SwapPrice::new(&self.get_coin_price, &self.send_coin_price, 0, 0)?
+                .amount_out(swap_amount)?;
+        let protocol_fee = calculate_protocol_fee(
+            amount_without_fees,
             amount_to_send,
+            self.global_state.protocol_fee_share,
         )?;
+        // The protocol fee is paid by the vault from, out of its fee spread
+        let amount_from_vault = math::checked_add(amount_to_send, protocol_fee)?;
 
-        user_vault_to.amount += swap_amount;
-        user_vault_from.amount -= amount_to_send;
-        user_vault_from.timestamp = Clock::get().unwrap().unix_timestamp as u64;
+        if let Some(pair_config) = &self.pair_config {
+            if pair_config.max_amount > 0 && amount_to_send > pair_config.max_amount {
+                return err!(ErrorCode::ExceedsPairMaxAmount);
+            }
+        }
 
-        Ok(())
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+        if amount_from_vault > self.user_vault_from.available_outflow(now) {
+            return err!(ErrorCode::ExceedsOutflowLimit);
+        }
+
+        if self.user_vault_to.amount.saturating_add(swap_amount) > self.user_vault_to.max {
+            return err!(ErrorCode::ExceedsMaxAmount);
+        }
+
+        if self.user_vault_from.amount < amount_from_vault {
+            return err!(ErrorCode::VaultInsufficientAmount);
+        }
+
+        if self.user_vault_from.amount - amount_from_vault < self.user_vault_from.min {
+            return err!(ErrorCode::ExceedsMinAmount);
+        }
+
+        Ok(SwapQuote {
+            swap_amount,
+            amount_to_send,
+            protocol_fee,
+            // Fees charged, priced on the inventory before the swap
+            buy_fee: self.user_vault_to.effective_buy_fee(),
+            sell_fee: self.user_vault_from.effective_sell_fee(),
+            error_code: 0,
+        })
+    }
+}
+
+/// Pair config of the vaults owner, None if it was not created.
+/// The account address must be validated by the caller.
+pub fn load_pair_config(pair_config: &AccountInfo) -> Result<Option<UserPairConfig>> {
+    if pair_config.data_is_empty() {
+        return Ok(None);
+    }
+    if *pair_config.owner != crate::ID {
+        return Err(anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram.into());
+    }
+    let data = pair_config.try_borrow_data()?;
+    Ok(Some(UserPairConfig::try_deserialize(&mut &data[..])?))
+}
+
+/// Sends tokens out of a token store, signed by the store authority.
+/// `to` receives `amount` net of transfer fees.
+pub fn transfer_from_store<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    token_store: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    token_store_authority: &AccountInfo<'info>,
+    amount: u64,
+    bump: u8,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
+    anchor_spl::token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: token_store.to_account_info(),
+                mint: mint.to_account_info(),
+                to,
+                authority: token_store_authority.clone(),
+            },
+            &[seeds],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+/// Sends the protocol fee out of the token store. The treasury is only required with a fee,
+/// so swaps of mints without a treasury work while the protocol fee share is zero.
+pub fn transfer_protocol_fee<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    token_store: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    treasury: &Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    token_store_authority: &AccountInfo<'info>,
+    protocol_fee: u64,
+    bump: u8,
+) -> Result<()> {
+    if protocol_fee == 0 {
+        return Ok(());
+    }
+    let treasury = treasury
+        .as_ref()
+        .ok_or_else(|| error!(ErrorCode::TreasuryRequired))?;
+    transfer_from_store(
+        token_program,
+        token_store,
+        mint,
+        treasury.to_account_info(),
+        token_store_authority,
+        protocol_fee,
+        bump,
+    )
+}
+
+/// Error code as returned by the program, custom codes for anchor and program errors
+fn error_code(error: &Error) -> u64 {
+    match error {
+        Error::AnchorError(error) => error.error_code_number as u64,
+        Error::ProgramError(error) => u64::from(error.program_error.clone()),
+    }
+}
+
+/// Rejects a stale price or a price with a confidence interval too wide
+pub fn check_oracle_price(coin_data: &CoinData, global_state: &GlobalState) -> Result<()> {
+    let now = Clock::get().unwrap().unix_timestamp as u64;
+    if now.saturating_sub(coin_data.last_update_timestamp) > global_state.max_price_age {
+        msg!(
+            "Price of {} last updated at {}, now is {}",
+            coin_data.mint,
+            coin_data.last_update_timestamp,
+            now
+        );
+        return err!(ErrorCode::PriceTooOld);
+    }
+
+    if coin_data.confidence as u128 * 10000
+        > coin_data.price as u128 * global_state.max_confidence as u128
+    {
+        msg!(
+            "Price of {} is {} with confidence {}",
+            coin_data.mint,
+            coin_data.price,
+            coin_data.confidence
+        );
+        return err!(ErrorCode::PriceConfidenceTooWide);
+    }
+    Ok(())
+}
+
+/// Checks the oracle guards set by the vault owner: max age of the prices used in the swap
+/// and max deviation of the vault token price from the vault reference price
+pub fn check_vault_price_guards(
+    user_vault: &UserCoinVault,
+    price: u64,
+    price_timestamp: u64,
+) -> Result<()> {
+    let now = Clock::get().unwrap().unix_timestamp as u64;
+    if user_vault.max_price_age > 0
+        && now.saturating_sub(price_timestamp) > user_vault.max_price_age
+    {
+        return err!(ErrorCode::VaultPriceTooOld);
+    }
+
+    if user_vault.max_price_deviation > 0 {
+        let deviation = (price as i128 - user_vault.reference_price as i128).unsigned_abs();
+        if deviation * 10000
+            > user_vault.reference_price as u128 * user_vault.max_price_deviation as u128
+        {
+            msg!(
+                "Price {} out of band {} +/- {} bps",
+                price,
+                user_vault.reference_price,
+                user_vault.max_price_deviation
+            );
+            return err!(ErrorCode::PriceOutOfBand);
+        }
     }
+    Ok(())
+}
+
+/// Checks the floor price of the vault selling and the ceiling price of the vault buying
+pub fn check_limit_prices(
+    user_vault_from: &UserCoinVault,
+    user_vault_to: &UserCoinVault,
+    get_coin_price: &TokenPrice,
+    send_coin_price: &TokenPrice,
+    mint_send: &Pubkey,
+    mint_receive: &Pubkey,
+) -> Result<()> {
+    if user_vault_from.limit_price_status {
+        // Rounded down, in favour of the vault selling
+        let price = limit_price_reference(
+            user_vault_from,
+            send_coin_price,
+            get_coin_price,
+            mint_send,
+            Rounding::Down,
+        )?;
+        if price < user_vault_from.limit_price as u128 {
+            return err!(ErrorCode::PriceUnderLimitPrice);
+        }
+    }
+
+    if user_vault_to.buy_limit_price_status {
+        // Rounded up, in favour of the vault buying
+        let price = limit_price_reference(
+            user_vault_to,
+            get_coin_price,
+            send_coin_price,
+            mint_receive,
+            Rounding::Up,
+        )?;
+        if price > user_vault_to.buy_limit_price as u128 {
+            return err!(ErrorCode::PriceOverBuyLimitPrice);
+        }
+    }
+    Ok(())
+}
+
+/// Price of the vault token compared with its limits. The oracle USD price, or the price
+/// in units of the counter token, scaled by its decimals, when the vault sets a limit_price_mint.
+fn limit_price_reference(
+    user_vault: &UserCoinVault,
+    token_price: &TokenPrice,
+    counter_price: &TokenPrice,
+    counter_mint: &Pubkey,
+    rounding: Rounding,
+) -> Result<u128> {
+    if user_vault.limit_price_mint == Pubkey::default() {
+        return Ok(token_price.price as u128);
+    }
+    if user_vault.limit_price_mint != *counter_mint {
+        msg!(
+            "Vault {} limits are in {}, swap counter token is {}",
+            user_vault.mint,
+            user_vault.limit_price_mint,
+            counter_mint
+        );
+        return err!(ErrorCode::LimitPriceMintMismatch);
+    }
+    calculate_counter_price(token_price, counter_price, rounding)
+}
+
+/// Rejects stale prices or prices with a confidence interval too wide,
+/// according to the limits set in the global state
+pub fn check_oracle_prices(
+    get_coin_data: &CoinData,
+    send_coin_data: &CoinData,
+    global_state: &GlobalState,
+) -> Result<()> {
+    check_oracle_price(get_coin_data, global_state)?;
+    check_oracle_price(send_coin_data, global_state)
 }
 
-fn check_oracle_accounts(accounts: &Swap) -> Result<()> {
+pub fn check_oracle_accounts(
+    get_coin_data: &Account<CoinData>,
+    send_coin_data: &Account<CoinData>,
+    mint_send: &InterfaceAccount<Mint>,
+    mint_receive: &InterfaceAccount<Mint>,
+) -> Result<()> {
     let (get_coin_pda, _bump_seed) = Pubkey::find_program_address(
-        &[accounts.mint_send.to_account_info().key.as_ref()],
+        &[mint_send.to_account_info().key.as_ref()],
         &delphor_oracle_aggregator::ID,
     );
 
-    if *accounts.get_coin_data.to_account_info().key != get_coin_pda {
+    if *get_coin_data.to_account_info().key != get_coin_pda {
         msg!(
             "Invalid mint_send {}. Expected {}",
-            accounts.mint_send.to_account_info().key(),
-            accounts.get_coin_data.mint,
+            mint_send.to_account_info().key(),
+            get_coin_data.mint,
         );
         return Err(ProgramError::InvalidAccountData.into());
     }
 
     let (send_coin_pda, _bump_seed) = Pubkey::find_program_address(
-        &[accounts.mint_receive.to_account_info().key.as_ref()],
+        &[mint_receive.to_account_info().key.as_ref()],
         &delphor_oracle_aggregator::ID,
     );
 
-    if *accounts.send_coin_data.to_account_info().key != send_coin_pda {
+    if *send_coin_data.to_account_info().key != send_coin_pda {
         msg!(
             "Invalid mint_receive {}. Expected {}",
-            accounts.mint_receive.to_account_info().key(),
-            accounts.send_coin_data.mint,
+            mint_receive.to_account_info().key(),
+            send_coin_data.mint,
         );
         return Err(ProgramError::InvalidAccountData.into());
     }
@@ -163,7 +793,8 @@ fn check_oracle_accounts(accounts: &Swap) -> Result<()> {
 // -- Errors --
 // ------------
 
-#[error_code]
+// Offset so the codes don't collide with DelphorError, which starts at 6000
+#[error_code(offset = 6100)]
 pub enum ErrorCode {
     #[msg("Final amount lower than min_amount.")]
     InsufficientAmount,
@@ -179,4 +810,32 @@ pub enum ErrorCode {
     VaultRecieveOff,
     #[msg("Current price for token requested is under the vault from limit price.")]
     PriceUnderLimitPrice,
+    #[msg("Amount required exceeds max_amount_in.")]
+    ExceedsMaxAmountIn,
+    #[msg("Oracle price is too old.")]
+    PriceTooOld,
+    #[msg("Oracle price confidence interval is too wide.")]
+    PriceConfidenceTooWide,
+    #[msg("Oracle price is older than the vault max price age.")]
+    VaultPriceTooOld,
+    #[msg("Oracle price is out of the vault price band.")]
+    PriceOutOfBand,
+    #[msg("Remaining accounts must be triples of user_vault_from, user_vault_to and pair_config.")]
+    InvalidVaultPair,
+    #[msg("Vault used more than once in the same swap.")]
+    DuplicatedVault,
+    #[msg("Not enough liquidity in the vaults to fill the swap.")]
+    InsufficientLiquidity,
+    #[msg("Current price for token sent is over the vault to buy limit price.")]
+    PriceOverBuyLimitPrice,
+    #[msg("Vault limit prices are set in another counter token.")]
+    LimitPriceMintMismatch,
+    #[msg("Swaps of the pair are disabled by the vault owner.")]
+    PairDisabled,
+    #[msg("Amount to send exceeds the pair max trade size.")]
+    ExceedsPairMaxAmount,
+    #[msg("Amount exceeds the vault outflow limit for the current window.")]
+    ExceedsOutflowLimit,
+    #[msg("Treasury account required to collect the protocol fee.")]
+    TreasuryRequired,
 }
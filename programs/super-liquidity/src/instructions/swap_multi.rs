@@ -0,0 +1,319 @@
+use crate::instructions::swap::{
+    check_oracle_accounts, check_oracle_prices, transfer_from_store, transfer_protocol_fee,
+    ErrorCode, SwapQuoter,
+};
+use crate::math::{self, SwapPrice};
+use crate::states::*;
+use crate::token::{amount_after_fee, amount_before_fee};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
+use delphor_oracle_aggregator::CoinData;
+
+//-----------------------------------------------------
+// Swap Multi Instruction
+// Fills one trade across many providers. Vaults are passed as remaining accounts,
+// in triples of (user_vault_from, user_vault_to, pair_config), all from the same user.
+// pair_config is the UserPairConfig PDA of the user, empty if not created.
+// As in a single swap, amounts are the ones the user sends and receives.
+#[derive(Accounts)]
+pub struct SwapMulti<'info> {
+    // global state the vaults belong to
+    pub global_state: Box<Account<'info, GlobalState>>,
+    // Accounts with price from oracle
+    pub get_coin_data: Account<'info, CoinData>,
+    pub send_coin_data: Account<'info, CoinData>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+    // token user sends
+    #[account(mint::token_program = token_program)]
+    pub mint_send: InterfaceAccount<'info, Mint>,
+    // token user wants
+    #[account(constraint = mint_receive.key() != mint_send.key(),
+        mint::token_program = token_program_receive)]
+    // Validates the tokens being swapped are differents
+    pub mint_receive: InterfaceAccount<'info, Mint>,
+    // Account where user have tokens
+    #[account(mut, associated_token::mint = mint_send, associated_token::authority = get_token_from_authority,
+        associated_token::token_program = token_program)]
+    pub get_token_from: Box<InterfaceAccount<'info, TokenAccount>>,
+    // owner or delegate_authority
+    pub get_token_from_authority: Signer<'info>,
+    // User account to send tokens
+    #[account(mut)]
+    pub send_token_to: Box<InterfaceAccount<'info, TokenAccount>>,
+    // PDA to withdraw tokens
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub token_store_pda_from: Box<InterfaceAccount<'info, TokenAccount>>,
+    // PDA to deposit tokens
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint_send.key().as_ref()
+    ], bump)]
+    pub token_store_pda_to: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Treasury collecting protocol fees in the token user wants, required with a protocol fee
+    #[account(mut, seeds = [
+        b"treasury", global_state.key().as_ref(), mint_receive.key().as_ref()
+    ], bump)]
+    pub treasury: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub system_program: Program<'info, System>,
+    // token program of mint_send
+    pub token_program: Interface<'info, TokenInterface>,
+    // token program of mint_receive, the same account if both mints share it
+    pub token_program_receive: Interface<'info, TokenInterface>,
+}
+impl<'info> SwapMulti<'info> {
+    #[access_control(check_oracle_accounts(
+        &self.get_coin_data,
+        &self.send_coin_data,
+        &self.mint_send,
+        &self.mint_receive
+    ))]
+    pub fn process(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        swap_amount: u64,
+        min_amount: u64,
+        bump: u8,
+    ) -> Result<()> {
+        self.global_state
+            .check_not_paused(&[self.mint_send.key(), self.mint_receive.key()])?;
+        check_oracle_prices(
+            &self.get_coin_data,
+            &self.send_coin_data,
+            &self.global_state,
+        )?;
+
+        let taker = self.get_token_from_authority.key();
+
+        let accounts_triples = remaining_accounts.chunks_exact(3);
+        if remaining_accounts.is_empty() || !accounts_triples.remainder().is_empty() {
+            return err!(ErrorCode::InvalidVaultPair);
+        }
+
+        let mut vault_pairs: Vec<VaultPair<'info>> =
+            Vec::with_capacity(remaining_accounts.len() / 3);
+
+        for accounts in accounts_triples {
+            let user_vault_from = Account::<UserCoinVault>::try_from(&accounts[0])?;
+            let user_vault_to = Account::<UserCoinVault>::try_from(&accounts[1])?;
+            check_vault_pair(
+                &user_vault_from,
+                &user_vault_to,
+                &self.global_state.key(),
+                &self.mint_send.key(),
+                &self.mint_receive.key(),
+            )?;
+
+            if vault_pairs.iter().any(|vault_pair| {
+                vault_pair.user_vault_from.key() == user_vault_from.key()
+                    || vault_pair.user_vault_to.key() == user_vault_to.key()
+            }) {
+                return err!(ErrorCode::DuplicatedVault);
+            }
+
+            check_pair_config_address(
+                &accounts[2],
+                &user_vault_from.user,
+                &self.mint_receive.key(),
+                &self.mint_send.key(),
+            )?;
+
+            // Same checks and pricing as a single swap, with the pair overrides
+            let quoter = SwapQuoter::new(
+                &self.global_state,
+                &self.get_coin_data,
+                &self.send_coin_data,
+                &self.mint_send,
+                &self.mint_receive,
+                &user_vault_from,
+                &user_vault_to,
+                taker,
+            )
+            .with_pair_config(&accounts[2])?;
+            let swap_price = quoter.swap_price()?;
+            let eligible = quoter.check_vaults_status().is_ok();
+            vault_pairs.push(VaultPair {
+                user_vault_from,
+                user_vault_to,
+                quoter,
+                swap_price,
+                eligible,
+            });
+        }
+
+        // Cheapest effective fee first
+        vault_pairs.sort_by(|a, b| b.swap_price.cmp_rate(&a.swap_price));
+
+        // The vaults share what the store receives
+        let mut remaining_amount =
+            amount_after_fee(&self.mint_send.to_account_info(), swap_amount)?;
+        let mut amount_to_send: u64 = 0;
+        let mut protocol_fee: u64 = 0;
+
+        for VaultPair {
+            user_vault_from,
+            user_vault_to,
+            quoter,
+            eligible,
+            ..
+        } in vault_pairs.iter_mut()
+        {
+            if remaining_amount == 0 {
+                break;
+            }
+
+            if !*eligible {
+                continue;
+            }
+
+            let quote = match quoter.quote_up_to(remaining_amount)? {
+                Some(quote) => quote,
+                None => continue,
+            };
+            quoter.settle(&quote, user_vault_from, user_vault_to)?;
+
+            remaining_amount = math::checked_sub(remaining_amount, quote.swap_amount)?;
+            amount_to_send = math::checked_add(amount_to_send, quote.amount_to_send)?;
+            protocol_fee = math::checked_add(protocol_fee, quote.protocol_fee)?;
+        }
+
+        if remaining_amount > 0 {
+            return err!(ErrorCode::InsufficientLiquidity);
+        }
+
+        if amount_to_send < amount_before_fee(&self.mint_receive.to_account_info(), min_amount)? {
+            return err!(ErrorCode::InsufficientAmount);
+        }
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                self.token_program.to_account_info().clone(),
+                TransferChecked {
+                    from: self.get_token_from.to_account_info(),
+                    mint: self.mint_send.to_account_info(),
+                    to: self.token_store_pda_to.to_account_info(),
+                    authority: self.get_token_from_authority.to_account_info().clone(),
+                },
+            ),
+            swap_amount,
+            self.mint_send.decimals,
+        )?;
+
+        transfer_from_store(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            self.send_token_to.to_account_info(),
+            &self.token_store_authority,
+            amount_to_send,
+            bump,
+        )?;
+
+        transfer_protocol_fee(
+            &self.token_program_receive,
+            &self.token_store_pda_from,
+            &self.mint_receive,
+            &self.treasury,
+            &self.token_store_authority,
+            protocol_fee,
+            bump,
+        )?;
+
+        // Vaults from remaining accounts are not persisted by anchor
+        for vault_pair in vault_pairs.iter() {
+            vault_pair.user_vault_from.exit(&crate::ID)?;
+            vault_pair.user_vault_to.exit(&crate::ID)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Vault pair with the quoter it is checked, priced and settled with
+struct VaultPair<'info> {
+    user_vault_from: Account<'info, UserCoinVault>,
+    user_vault_to: Account<'info, UserCoinVault>,
+    quoter: SwapQuoter,
+    swap_price: SwapPrice,
+    // passes the status, taker, limit price and price guard checks
+    eligible: bool,
+}
+
+fn check_vault_pair<'info>(
+    user_vault_from: &Account<'info, UserCoinVault>,
+    user_vault_to: &Account<'info, UserCoinVault>,
+    global_state: &Pubkey,
+    mint_send: &Pubkey,
+    mint_receive: &Pubkey,
+) -> Result<()> {
+    if !user_vault_from.to_account_info().is_writable
+        || !user_vault_to.to_account_info().is_writable
+    {
+        msg!(
+            "Vaults {} and {} must be writable",
+            user_vault_from.key(),
+            user_vault_to.key()
+        );
+        return err!(ErrorCode::InvalidVaultPair);
+    }
+
+    // user_vault_from and user_vault_to must be from the same user
+    if user_vault_from.user != user_vault_to.user
+        || user_vault_from.global_state != *global_state
+        || user_vault_to.global_state != *global_state
+        || user_vault_from.mint != *mint_receive
+        || user_vault_to.mint != *mint_send
+    {
+        msg!(
+            "Invalid vault pair {} {}",
+            user_vault_from.key(),
+            user_vault_to.key()
+        );
+        return err!(ErrorCode::InvalidVaultPair);
+    }
+
+    for user_vault in [user_vault_from, user_vault_to] {
+        let pda = Pubkey::create_program_address(
+            &[
+                user_vault.user.as_ref(),
+                user_vault.mint.as_ref(),
+                &[user_vault.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| error!(ErrorCode::InvalidVaultPair))?;
+        if user_vault.key() != pda {
+            msg!("Invalid user_vault {}. Expected {}", user_vault.key(), pda);
+            return err!(ErrorCode::InvalidVaultPair);
+        }
+    }
+    Ok(())
+}
+
+fn check_pair_config_address(
+    pair_config: &AccountInfo,
+    user: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Result<()> {
+    let (pda, _) = Pubkey::find_program_address(
+        &[
+            b"pair_config",
+            user.as_ref(),
+            mint_a.as_ref(),
+            mint_b.as_ref(),
+        ],
+        &crate::ID,
+    );
+    if pair_config.key() != pda {
+        msg!(
+            "Invalid pair_config {}. Expected {}",
+            pair_config.key(),
+            pda
+        );
+        return err!(ErrorCode::InvalidVaultPair);
+    }
+    Ok(())
+}
@@ -1,57 +1,145 @@
-use crate::states::UserCoinVault;
+use crate::events::*;
+use crate::states::*;
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount, Transfer, Mint};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
 
 //-----------------------------------------------------
 #[derive(Accounts)]
 pub struct Withdraw<'info> {
+    // global state the vault belongs to
+    pub global_state: Account<'info, GlobalState>,
     #[account(mut, seeds = [
         user_account.key().as_ref(), mint.key().as_ref()
-    ], bump = user_vault.bump)]
+    ], bump = user_vault.bump, has_one = global_state)]
     pub user_vault: Account<'info, UserCoinVault>,
-    pub mint: Account<'info, Mint>,
-    /// user account to receive tokens
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// user account to receive tokens, net of transfer fees
     #[account(mut)]
-    pub send_token_to: Account<'info, TokenAccount>,
+    pub send_token_to: InterfaceAccount<'info, TokenAccount>,
     /// CHECK:
-    #[account(mut)]
+    #[account(seeds = [b"store_auth"], bump)]
     pub token_store_authority: AccountInfo<'info>,
     /// store to withdraw tokens from
-    #[account(mut, associated_token::mint = mint, associated_token::authority = token_store_authority, constraint = token_store_pda.mint == send_token_to.mint)]
-    pub token_store_pda: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint.key().as_ref()
+    ], bump, constraint = token_store_pda.mint == send_token_to.mint)]
+    pub token_store_pda: InterfaceAccount<'info, TokenAccount>,
     /// burn_defi_token_from owner or delegate_authority
     pub user_account: Signer<'info>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 impl<'info> Withdraw<'info> {
-    pub fn process(&mut self, bump: u8, amount: u64) -> Result<()> {
-        if self.user_vault.amount < amount {
-            msg!(
-                "Requested to withdraw {} but you have only {}",
-                amount,
-                self.user_vault.amount
-            );
-            return Err(ProgramError::InsufficientFunds.into());
-        }
+    pub fn process(&mut self, bump: u8, amount: u64, full_exit: bool) -> Result<()> {
+        self.user_vault.check_withdraw(amount, full_exit)?;
+        self.user_vault
+            .check_withdraw_recipient(&self.send_token_to.owner)?;
 
         let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
-        let signer = &[&seeds[..]];
+        let signer = &[seeds];
 
-        anchor_spl::token::transfer(
+        anchor_spl::token_interface::transfer_checked(
             CpiContext::new_with_signer(
                 self.token_program.to_account_info().clone(),
-                Transfer {
+                TransferChecked {
+                    from: self.token_store_pda.to_account_info(),
+                    mint: self.mint.to_account_info(),
+                    to: self.send_token_to.to_account_info(),
+                    authority: self.token_store_authority.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+            self.mint.decimals,
+        )?;
+
+        self.user_vault.debit(amount)?;
+        self.user_vault.timestamp = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(WithdrawEvent {
+            user_vault: self.user_vault.key(),
+            user: self.user_vault.user,
+            mint: self.user_vault.mint,
+            send_token_to: self.send_token_to.key(),
+            amount,
+            vault_amount: self.user_vault.amount,
+            timestamp: self.user_vault.timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------
+// Close User Vault Instruction
+// Sweeps any remaining balance to the user and returns the vault rent to user_account
+#[derive(Accounts)]
+pub struct CloseUserVault<'info> {
+    // global state the vault belongs to
+    pub global_state: Account<'info, GlobalState>,
+    #[account(mut, seeds = [
+        user_account.key().as_ref(), mint.key().as_ref()
+    ], bump = user_vault.bump, has_one = global_state, close = user_account)]
+    pub user_vault: Account<'info, UserCoinVault>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// user account to receive the remaining tokens
+    #[account(mut, constraint = send_token_to.mint == mint.key())]
+    pub send_token_to: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK:
+    #[account(seeds = [b"store_auth"], bump)]
+    pub token_store_authority: AccountInfo<'info>,
+    /// store to withdraw tokens from
+    #[account(mut, seeds = [
+        global_state.key().as_ref(), mint.key().as_ref()
+    ], bump)]
+    pub token_store_pda: InterfaceAccount<'info, TokenAccount>,
+    /// vault owner, receives the rent
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+impl<'info> CloseUserVault<'info> {
+    pub fn process(&mut self, bump: u8) -> Result<()> {
+        let amount = self.user_vault.amount;
+        if amount == 0 {
+            return Ok(());
+        }
+
+        self.user_vault
+            .check_withdraw_recipient(&self.send_token_to.owner)?;
+
+        let seeds: &[&[u8]] = &[b"store_auth", &[bump]];
+        let signer = &[seeds];
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
                     from: self.token_store_pda.to_account_info(),
+                    mint: self.mint.to_account_info(),
                     to: self.send_token_to.to_account_info(),
                     authority: self.token_store_authority.to_account_info(),
                 },
                 signer,
             ),
             amount,
+            self.mint.decimals,
         )?;
 
-        self.user_vault.amount -= amount;
+        self.user_vault.amount = 0;
+
+        emit!(WithdrawEvent {
+            user_vault: self.user_vault.key(),
+            user: self.user_vault.user,
+            mint: self.user_vault.mint,
+            send_token_to: self.send_token_to.key(),
+            amount,
+            vault_amount: 0,
+            timestamp: Clock::get().unwrap().unix_timestamp as u64,
+        });
 
         Ok(())
     }
@@ -1,14 +1,23 @@
 use anchor_lang::prelude::*;
 // use anchor_lang::solana_program::pubkey::Pubkey;
 
-use instructions::{admin::*, deposit::*, swap::*, withdraw::*};
+use instructions::{
+    admin::*, deposit::*, native_sol::*, pool::*, swap::*, swap_multi::*, withdraw::*,
+};
+use states::{PairConfig, VaultConfig, VaultConfigUpdate};
 
 ///error
 pub mod error;
+///events
+pub mod events;
 ///instructions
 pub mod instructions;
+///math
+pub mod math;
 ///states
 pub mod states;
+///token
+pub mod token;
 
 #[program]
 pub mod super_liquidity {
@@ -21,46 +30,149 @@ pub mod super_liquidity {
         ctx.accounts.process(amount)
     }
 
-    ///withdraw
-    pub fn withdraw(ctx: Context<Withdraw>, bump: u8, amount: u64) -> Result<()> {
-        ctx.accounts.process(bump, amount)
+    ///withdraw, full_exit allows taking the whole balance regardless of the vault min
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64, full_exit: bool) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
+        ctx.accounts.process(bump, amount, full_exit)
     }
 
-    pub fn swap(ctx: Context<Swap>, swap_amount: u64, min_amount: u64, bump: u8) -> Result<()> {
+    ///deposit lamports into a wSOL vault, wrapped into the wSOL store
+    pub fn deposit_sol(ctx: Context<DepositSol>, amount: u64) -> Result<()> {
+        ctx.accounts.process(amount)
+    }
+
+    ///withdraw from a wSOL vault, unwrapped into lamports of send_lamports_to
+    pub fn withdraw_sol(ctx: Context<WithdrawSol>, amount: u64, full_exit: bool) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
+        ctx.accounts.process(bump, amount, full_exit)
+    }
+
+    ///close a user vault, sweeping any remaining balance to the user and returning the rent
+    pub fn close_user_vault(ctx: Context<CloseUserVault>) -> Result<()> {
+        ctx.accounts.process(ctx.bumps.token_store_authority)
+    }
+
+    pub fn swap(ctx: Context<Swap>, swap_amount: u64, min_amount: u64) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
+        ctx.accounts.process(swap_amount, min_amount, bump)
+    }
+
+    ///swap sending lamports, wrapped into the wSOL store
+    pub fn swap_sol_in(ctx: Context<SwapSolIn>, swap_amount: u64, min_amount: u64) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
         ctx.accounts.process(swap_amount, min_amount, bump)
     }
 
+    ///swap receiving lamports, unwrapped from the wSOL store
+    pub fn swap_sol_out(ctx: Context<SwapSolOut>, swap_amount: u64, min_amount: u64) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
+        ctx.accounts.process(swap_amount, min_amount, bump)
+    }
+
+    ///quote a swap without executing it, returns a SwapQuote as return data
+    pub fn quote_swap(ctx: Context<QuoteSwap>, swap_amount: u64, min_amount: u64) -> Result<()> {
+        ctx.accounts.process(swap_amount, min_amount)
+    }
+
+    ///swap receiving an exact amount of mint_receive
+    pub fn swap_exact_out(
+        ctx: Context<Swap>,
+        amount_to_send: u64,
+        max_amount_in: u64,
+    ) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
+        ctx.accounts
+            .process_exact_out(amount_to_send, max_amount_in, bump)
+    }
+
+    ///swap against many vaults, remaining accounts are
+    ///(user_vault_from, user_vault_to, pair_config) triples
+    pub fn swap_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapMulti<'info>>,
+        swap_amount: u64,
+        min_amount: u64,
+    ) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
+        ctx.accounts
+            .process(ctx.remaining_accounts, swap_amount, min_amount, bump)
+    }
+
+    // -------------
+    // ---- Pools --
+    // -------------
+    ///create pool and its LP share mint
+    pub fn init_pool(ctx: Context<InitPool>) -> Result<()> {
+        ctx.accounts.process(ctx.bumps.pool)
+    }
+
+    ///create pool vault for a token, operated by the pool manager
+    pub fn init_pool_vault(ctx: Context<InitPoolVault>) -> Result<()> {
+        ctx.accounts.process(ctx.bumps.pool_vault)
+    }
+
+    ///deposit into a pool net of the pool vault buy fee,
+    ///remaining accounts are (pool_vault, coin_data) pairs
+    pub fn deposit_pool<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositPool<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.process(ctx.remaining_accounts, amount)
+    }
+
+    ///burn pool shares, remaining accounts are (pool_vault, token_store, send_token_to) triples
+    pub fn withdraw_pool<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawPool<'info>>,
+        shares: u64,
+    ) -> Result<()> {
+        let bump = ctx.bumps.token_store_authority;
+        ctx.accounts.process(ctx.remaining_accounts, shares, bump)
+    }
+
     // -------------
     // ---- Admin --
     // -------------
     ///create global state
     pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        ctx.accounts.process(*ctx.bumps.get("global_state").unwrap())
+        ctx.accounts.process(ctx.bumps.global_state)
     }
 
     ///create user vault
-    pub fn init_user_vault(
-        ctx: Context<InitUserVault>,
-        buy_fee: u32,
-        sell_fee: u32,
-        min: u64,
-        max: u64,
-        receive_status: bool,
-        provide_status: bool,
-        limit_price_status: bool,
-        limit_price: u64,
+    pub fn init_user_vault(ctx: Context<InitUserVault>, config: VaultConfig) -> Result<()> {
+        ctx.accounts.process(ctx.bumps.user_vault, config)
+    }
+
+    ///update oracle guards used in swaps
+    pub fn update_oracle_config(
+        ctx: Context<UpdateOracleConfig>,
+        max_price_age: u64,
+        max_confidence: u32,
     ) -> Result<()> {
-        ctx.accounts.process(
-            *ctx.bumps.get("user_vault").unwrap(),
-            buy_fee,
-            sell_fee,
-            min,
-            max,
-            receive_status,
-            provide_status,
-            limit_price_status,
-            limit_price,
-        )
+        ctx.accounts.process(max_price_age, max_confidence)
+    }
+
+    ///pause or unpause deposits and swaps
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        ctx.accounts.process(paused)
+    }
+
+    ///pause or unpause deposits and swaps of a mint
+    pub fn set_mint_paused(ctx: Context<SetMintPaused>, paused: bool) -> Result<()> {
+        ctx.accounts.process(paused)
+    }
+
+    ///set share of the swap fees kept by the protocol
+    pub fn set_protocol_fee(ctx: Context<SetProtocolFee>, protocol_fee_share: u32) -> Result<()> {
+        ctx.accounts.process(protocol_fee_share)
+    }
+
+    ///initialize treasury collecting protocol fees for a token
+    pub fn init_treasury(_ctx: Context<InitTreasury>) -> Result<()> {
+        Ok(())
+    }
+
+    ///send protocol fees collected in a treasury
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        ctx.accounts.process(ctx.bumps.token_store_authority)
     }
 
     ///initialize token store
@@ -68,27 +180,72 @@ pub mod super_liquidity {
         ctx.accounts.process()
     }
 
-    ///update user state
+    ///move the tokens of a store created by previous versions to the token store
+    pub fn migrate_token_store(ctx: Context<MigrateTokenStore>) -> Result<()> {
+        ctx.accounts.process(ctx.bumps.token_store_authority)
+    }
+
+    ///bind a vault created by previous versions to the global state
+    pub fn migrate_user_vault(ctx: Context<MigrateUserVault>) -> Result<()> {
+        ctx.accounts.process()
+    }
+
+    ///set the account allowed to receive withdrawals besides the vault owner
+    pub fn set_withdraw_recipient(
+        ctx: Context<SetWithdrawRecipient>,
+        withdraw_recipient: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.process(withdraw_recipient)
+    }
+
+    ///reject or accept deposits into the vault from accounts other than the owner
+    pub fn set_reject_external_deposits(
+        ctx: Context<SetRejectExternalDeposits>,
+        reject_external_deposits: bool,
+    ) -> Result<()> {
+        ctx.accounts.process(reject_external_deposits)
+    }
+
+    ///set the account allowed to update the vault config besides the owner
+    pub fn set_vault_operator(ctx: Context<SetVaultOperator>, operator: Pubkey) -> Result<()> {
+        ctx.accounts.process(operator)
+    }
+
+    ///revoke the vault operator
+    pub fn revoke_vault_operator(ctx: Context<SetVaultOperator>) -> Result<()> {
+        ctx.accounts.process(Pubkey::default())
+    }
+
+    ///update user state, signed by the vault owner or operator.
+    ///Only the fields set in the update are changed
     pub fn update_user_vault(
         ctx: Context<UpdateUserVault>,
-        buy_fee: u32,
-        sell_fee: u32,
-        min: u64,
-        max: u64,
-        receive_status: bool,
-        provide_status: bool,
-        limit_price_status: bool,
-        limit_price: u64,
+        update: VaultConfigUpdate,
     ) -> Result<()> {
-        ctx.accounts.process(
-            buy_fee,
-            sell_fee,
-            min,
-            max,
-            receive_status,
-            provide_status,
-            limit_price_status,
-            limit_price,
-        )
+        ctx.accounts.process(update)
+    }
+
+    ///add or remove a taker from the vault allowlist, signed by the vault owner or operator
+    pub fn set_allowed_taker(
+        ctx: Context<SetAllowedTaker>,
+        taker: Pubkey,
+        allowed: bool,
+    ) -> Result<()> {
+        ctx.accounts.process(taker, allowed)
+    }
+
+    ///initialize the config overriding the user vaults for swaps selling mint_a for mint_b
+    pub fn init_pair_config(ctx: Context<InitPairConfig>, config: PairConfig) -> Result<()> {
+        ctx.accounts.process(ctx.bumps.pair_config, config)
+    }
+
+    ///update pair config, signed by the vault owner or the operator of the mint_a vault
+    pub fn update_pair_config(ctx: Context<UpdatePairConfig>, config: PairConfig) -> Result<()> {
+        ctx.accounts.process(config)
+    }
+
+    ///close pair config, swaps of the pair go back to the vaults config
+    pub fn close_pair_config(_ctx: Context<ClosePairConfig>) -> Result<()> {
+        Ok(())
     }
 }
@@ -0,0 +1,453 @@
+use crate::error::*;
+use anchor_lang::prelude::*;
+use delphor_oracle_aggregator::{CoinData, PRICE_EXPONENT};
+use std::cmp::{self, Ordering};
+use std::convert::TryFrom;
+
+// Fees are in basis points
+const BPS: u128 = 10000;
+
+/// Rounding direction of a division, always chosen in favour of the vaults
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// a * b / c with a 256 bits intermediate product, so only the final result is rounded
+pub fn mul_div(a: u128, b: u128, c: u128, rounding: Rounding) -> Result<u128> {
+    if c == 0 {
+        return err!(DelphorError::DivisionByZero);
+    }
+    let (high, low) = full_mul(a, b);
+    // the quotient must fit in 128 bits
+    if high >= c {
+        return err!(DelphorError::MathOverflow);
+    }
+    let (quotient, remainder) = if high == 0 {
+        (low / c, low % c)
+    } else {
+        div_rem_wide(high, low, c)
+    };
+    if rounding == Rounding::Up && remainder > 0 {
+        return quotient
+            .checked_add(1)
+            .ok_or_else(|| error!(DelphorError::MathOverflow));
+    }
+    Ok(quotient)
+}
+
+pub fn to_u64(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| error!(DelphorError::MathOverflow))
+}
+
+pub fn checked_add(a: u64, b: u64) -> Result<u64> {
+    a.checked_add(b)
+        .ok_or_else(|| error!(DelphorError::MathOverflow))
+}
+
+pub fn checked_sub(a: u64, b: u64) -> Result<u64> {
+    a.checked_sub(b)
+        .ok_or_else(|| error!(DelphorError::MathUnderflow))
+}
+
+fn checked_mul(a: u128, b: u128) -> Result<u128> {
+    a.checked_mul(b)
+        .ok_or_else(|| error!(DelphorError::MathOverflow))
+}
+
+fn pow10(exponent: u32) -> Result<u128> {
+    10u128
+        .checked_pow(exponent)
+        .ok_or_else(|| error!(DelphorError::MathOverflow))
+}
+
+/// Oracle price of a token, with the decimals of its mint
+#[derive(Clone, Copy)]
+pub struct TokenPrice {
+    pub price: u64,
+    pub price_exponent: u8,
+    pub decimals: u8,
+}
+impl TokenPrice {
+    pub fn new(coin_data: &CoinData, decimals: u8) -> Self {
+        TokenPrice {
+            price: coin_data.price,
+            price_exponent: coin_data.price_exponent,
+            decimals,
+        }
+    }
+
+    // 10^(price_exponent + decimals), price of one base unit is price / scale
+    fn scale(&self) -> Result<u128> {
+        pow10(self.price_exponent as u32 + self.decimals as u32)
+    }
+}
+
+/// Value of `amount` at the oracle price, in units of 10^-PRICE_EXPONENT USD, rounded down
+pub fn calculate_value(amount: u64, token_price: &TokenPrice) -> Result<u128> {
+    mul_div(
+        amount as u128 * token_price.price as u128,
+        pow10(PRICE_EXPONENT as u32)?,
+        token_price.scale()?,
+        Rounding::Down,
+    )
+}
+
+/// Oracle price of a token in units of the counter token, scaled by the counter decimals
+pub fn calculate_counter_price(
+    token_price: &TokenPrice,
+    counter_price: &TokenPrice,
+    rounding: Rounding,
+) -> Result<u128> {
+    mul_div(
+        token_price.price as u128,
+        counter_price.scale()?,
+        checked_mul(
+            counter_price.price as u128,
+            pow10(token_price.price_exponent as u32)?,
+        )?,
+        rounding,
+    )
+}
+
+/// Value left after the buy fee of the vault receiving it, rounded down like swap amounts
+pub fn value_after_buy_fee(value: u128, buy_fee: u32) -> Result<u128> {
+    let buy_fee_factor = BPS
+        .checked_sub(buy_fee as u128)
+        .ok_or_else(|| error!(DelphorError::MathUnderflow))?;
+    mul_div(value, buy_fee_factor, BPS, Rounding::Down)
+}
+
+/// Share of the fee spread kept by the protocol, rounded down.
+/// The fee spread is the difference between the amount without fees and `amount_to_send`.
+pub fn calculate_protocol_fee(
+    amount_without_fees: u64,
+    amount_to_send: u64,
+    protocol_fee_share: u32,
+) -> Result<u64> {
+    let fee_spread = amount_without_fees.saturating_sub(amount_to_send);
+    to_u64(mul_div(
+        fee_spread as u128,
+        protocol_fee_share as u128,
+        BPS,
+        Rounding::Down,
+    )?)
+}
+
+/// Exchange rate from the token the user sends to the token the user receives, kept as a
+/// fraction so no intermediate price is truncated. Applies the buy fee of the vault
+/// receiving tokens and the sell fee of the vault providing them.
+#[derive(Clone, Copy)]
+pub struct SwapPrice {
+    numerator: u128,
+    denominator: u128,
+}
+impl SwapPrice {
+    pub fn new(
+        get_coin_price: &TokenPrice,
+        send_coin_price: &TokenPrice,
+        buy_fee: u32,
+        sell_fee: u32,
+    ) -> Result<Self> {
+        // get_price * (1 - buy_fee) * send_scale / (send_price * (1 + sell_fee) * get_scale)
+        let buy_fee_factor = BPS
+            .checked_sub(buy_fee as u128)
+            .ok_or_else(|| error!(DelphorError::MathUnderflow))?;
+        let numerator = checked_mul(
+            checked_mul(get_coin_price.price as u128, buy_fee_factor)?,
+            send_coin_price.scale()?,
+        )?;
+        let denominator = checked_mul(
+            checked_mul(send_coin_price.price as u128, BPS + sell_fee as u128)?,
+            get_coin_price.scale()?,
+        )?;
+        if denominator == 0 {
+            return err!(DelphorError::DivisionByZero);
+        }
+        Ok(SwapPrice {
+            numerator,
+            denominator,
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    /// Amount of the token the user receives for `amount_in`, rounded down
+    pub fn amount_out(&self, amount_in: u64) -> Result<u64> {
+        to_u64(mul_div(
+            amount_in as u128,
+            self.numerator,
+            self.denominator,
+            Rounding::Down,
+        )?)
+    }
+
+    /// Amount of the token the user sends for `amount_out`
+    pub fn amount_in(&self, amount_out: u64, rounding: Rounding) -> Result<u64> {
+        if self.is_zero() {
+            return err!(DelphorError::DivisionByZero);
+        }
+        to_u64(mul_div(
+            amount_out as u128,
+            self.denominator,
+            self.numerator,
+            rounding,
+        )?)
+    }
+
+    /// Max amount the user can send without receiving more than `amount_out`,
+    /// rounded down and saturated at u64::MAX. The price must not be zero.
+    pub fn max_amount_in(&self, amount_out: u64) -> u64 {
+        match mul_div(
+            amount_out as u128,
+            self.denominator,
+            self.numerator,
+            Rounding::Down,
+        ) {
+            Ok(amount) => cmp::min(amount, u64::MAX as u128) as u64,
+            Err(_) => u64::MAX,
+        }
+    }
+
+    /// Compares the rates exactly, a higher rate gives more to the user
+    pub fn cmp_rate(&self, other: &SwapPrice) -> Ordering {
+        full_mul(self.numerator, other.denominator)
+            .cmp(&full_mul(other.numerator, self.denominator))
+    }
+}
+
+/// 256 bits product of a and b, as (high, low) 128 bits halves
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & mask);
+    let (b_high, b_low) = (b >> 64, b & mask);
+
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+
+    let cross = (low_low >> 64) + (high_low & mask) + (low_high & mask);
+    let low = (cross << 64) | (low_low & mask);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (cross >> 64);
+    (high, low)
+}
+
+/// (high, low) / divisor by long division, high must be lower than divisor
+fn div_rem_wide(high: u128, low: u128, divisor: u128) -> (u128, u128) {
+    let mut remainder = high;
+    let mut quotient: u128 = 0;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1;
+        }
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_name<T>(result: Result<T>) -> String {
+        match result {
+            Err(Error::AnchorError(error)) => error.error_name,
+            Err(error) => panic!("unexpected {}", error),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    fn token_price(price: u64, decimals: u8) -> TokenPrice {
+        TokenPrice {
+            price,
+            price_exponent: PRICE_EXPONENT,
+            decimals,
+        }
+    }
+
+    #[test]
+    fn full_mul_splits_the_product() {
+        assert_eq!(full_mul(u128::MAX, u128::MAX), (u128::MAX - 1, 1));
+        assert_eq!(full_mul(1 << 64, 1 << 64), (1, 0));
+        assert_eq!(full_mul(3, 5), (0, 15));
+    }
+
+    #[test]
+    fn mul_div_with_wide_product() {
+        // (2^128 + 2^64) / 2^64 = 2^64 + 1, only representable with a 256 bits product
+        assert_eq!(
+            mul_div(1 << 64, (1 << 64) + 1, 1 << 64, Rounding::Down).unwrap(),
+            (1 << 64) + 1
+        );
+        // u128::MAX * 3 / 4, remainder 1
+        let three_quarters = u128::MAX / 4 * 3 + 2;
+        assert_eq!(
+            mul_div(u128::MAX, 3, 4, Rounding::Down).unwrap(),
+            three_quarters
+        );
+        assert_eq!(
+            mul_div(u128::MAX, 3, 4, Rounding::Up).unwrap(),
+            three_quarters + 1
+        );
+        // divisor with the top bit set exercises the long division carry
+        let divisor = (1 << 127) + 12345;
+        let quotient = mul_div(u128::MAX, 1 << 126, divisor, Rounding::Down).unwrap();
+        let (high, low) = full_mul(quotient, divisor);
+        let (next_high, next_low) = full_mul(quotient + 1, divisor);
+        assert!((high, low) <= full_mul(u128::MAX, 1 << 126));
+        assert!((next_high, next_low) > full_mul(u128::MAX, 1 << 126));
+    }
+
+    #[test]
+    fn wide_division_is_exact() {
+        // q * c + r == a * b and r < c over pseudo random operands of every size
+        let mut seed: u128 = 0x2545_f491_4f6c_dd1d_9e37_79b9_7f4a_7c15;
+        let mut next = |shift: u32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed >> (shift % 128)
+        };
+        for i in 0..20_000u32 {
+            let (a, b) = (next(i), next(i / 3));
+            let c = next(i / 7).max(1);
+            let (high, low) = full_mul(a, b);
+            if high >= c {
+                continue;
+            }
+            let (quotient, remainder) = div_rem_wide(high, low, c);
+            assert!(remainder < c);
+            let (q_high, q_low) = full_mul(quotient, c);
+            let (sum_low, carry) = q_low.overflowing_add(remainder);
+            assert_eq!((q_high + carry as u128, sum_low), (high, low));
+        }
+    }
+
+    #[test]
+    fn mul_div_rounding() {
+        assert_eq!(mul_div(10, 10, 4, Rounding::Down).unwrap(), 25);
+        assert_eq!(mul_div(10, 10, 4, Rounding::Up).unwrap(), 25);
+        assert_eq!(mul_div(10, 10, 3, Rounding::Down).unwrap(), 33);
+        assert_eq!(mul_div(10, 10, 3, Rounding::Up).unwrap(), 34);
+        assert_eq!(mul_div(0, 10, 3, Rounding::Up).unwrap(), 0);
+    }
+
+    #[test]
+    fn mul_div_errors() {
+        assert_eq!(
+            error_name(mul_div(1, 1, 0, Rounding::Down)),
+            "DivisionByZero"
+        );
+        assert_eq!(
+            error_name(mul_div(u128::MAX, 2, 1, Rounding::Down)),
+            "MathOverflow"
+        );
+        // quotient is u128::MAX with a remainder, rounding up overflows
+        let (a, b, c) = (u128::MAX - (1 << 64) + 2, (1 << 64) + 2, (1 << 64) + 1);
+        assert_eq!(mul_div(a, b, c, Rounding::Down).unwrap(), u128::MAX);
+        assert_eq!(error_name(mul_div(a, b, c, Rounding::Up)), "MathOverflow");
+        assert_eq!(error_name(to_u64(u64::MAX as u128 + 1)), "MathOverflow");
+        assert_eq!(error_name(checked_add(u64::MAX, 1)), "MathOverflow");
+        assert_eq!(error_name(checked_sub(0, 1)), "MathUnderflow");
+    }
+
+    #[test]
+    fn swap_price_of_a_low_priced_token() {
+        // 0.005 USD token with 5 decimals sold for a 1 USD token with 6 decimals
+        let token = token_price(5_000_000, 5);
+        let usdc = token_price(1_000_000_000, 6);
+
+        let price = SwapPrice::new(&token, &usdc, 0, 0).unwrap();
+        // 10 tokens are worth 0.05 USD
+        assert_eq!(price.amount_out(1_000_000).unwrap(), 50_000);
+
+        // 0.3% buy fee, rounded down in favour of the vault
+        let price = SwapPrice::new(&token, &usdc, 30, 0).unwrap();
+        assert_eq!(price.amount_out(1_000_000).unwrap(), 49_850);
+        assert_eq!(price.amount_out(1).unwrap(), 0);
+        // exact out rounds the amount in up
+        assert_eq!(price.amount_in(49_850, Rounding::Up).unwrap(), 1_000_000);
+        assert_eq!(price.amount_in(49_851, Rounding::Up).unwrap(), 1_000_021);
+        assert_eq!(price.amount_in(49_851, Rounding::Down).unwrap(), 1_000_020);
+        assert_eq!(price.max_amount_in(49_851), 1_000_020);
+    }
+
+    #[test]
+    fn swap_price_with_mixed_decimals() {
+        // 150 USD token with 9 decimals for a 1 USD token with 6 decimals
+        let sol = token_price(150_000_000_000, 9);
+        let usdc = token_price(1_000_000_000, 6);
+
+        let price = SwapPrice::new(&sol, &usdc, 0, 100).unwrap();
+        // 2 SOL at 150 USD, 1% sell fee: 300 / 1.01
+        assert_eq!(price.amount_out(2_000_000_000).unwrap(), 297_029_702);
+
+        let without_fees = SwapPrice::new(&sol, &usdc, 0, 0).unwrap();
+        assert_eq!(price.cmp_rate(&without_fees), Ordering::Less);
+        assert_eq!(without_fees.cmp_rate(&without_fees), Ordering::Equal);
+        assert_eq!(
+            calculate_protocol_fee(300_000_000, 297_029_702, 5000).unwrap(),
+            1_485_149
+        );
+    }
+
+    #[test]
+    fn value_after_buy_fee_rounds_down() {
+        assert_eq!(value_after_buy_fee(1_000_000, 30).unwrap(), 997_000);
+        assert_eq!(value_after_buy_fee(999, 100).unwrap(), 989);
+        assert_eq!(value_after_buy_fee(999, 0).unwrap(), 999);
+        assert_eq!(error_name(value_after_buy_fee(999, 10001)), "MathUnderflow");
+    }
+
+    #[test]
+    fn swap_price_errors() {
+        let usdc = token_price(1_000_000_000, 6);
+        assert_eq!(
+            error_name(SwapPrice::new(&usdc, &token_price(0, 6), 0, 0)),
+            "DivisionByZero"
+        );
+        assert_eq!(
+            error_name(SwapPrice::new(&usdc, &usdc, 10001, 0)),
+            "MathUnderflow"
+        );
+
+        let zero_price = SwapPrice::new(&token_price(0, 6), &usdc, 0, 0).unwrap();
+        assert!(zero_price.is_zero());
+        assert_eq!(
+            error_name(zero_price.amount_in(1, Rounding::Up)),
+            "DivisionByZero"
+        );
+    }
+
+    #[test]
+    fn value_and_counter_price() {
+        let sol = token_price(150_000_000_000, 9);
+        let usdc = token_price(1_000_000_000, 6);
+        // 2 SOL worth 300 USD, in units of 10^-9 USD
+        assert_eq!(
+            calculate_value(2_000_000_000, &sol).unwrap(),
+            300_000_000_000
+        );
+        // 1 SOL is 150 USDC, in USDC base units
+        assert_eq!(
+            calculate_counter_price(&sol, &usdc, Rounding::Down).unwrap(),
+            150_000_000
+        );
+        // 1 USDC is 1/150 SOL, in SOL base units
+        assert_eq!(
+            calculate_counter_price(&usdc, &sol, Rounding::Down).unwrap(),
+            6_666_666
+        );
+        assert_eq!(
+            calculate_counter_price(&usdc, &sol, Rounding::Up).unwrap(),
+            6_666_667
+        );
+    }
+}
@@ -1,5 +1,18 @@
+use crate::error::*;
+use crate::math;
 use anchor_lang::prelude::*;
 
+// Default oracle guards set on initialize
+pub const DEFAULT_MAX_PRICE_AGE: u64 = 120; // seconds
+pub const DEFAULT_MAX_CONFIDENCE: u32 = 200; // basis points of the price
+
+pub const MAX_PAUSED_MINTS: usize = 32;
+
+pub const MAX_ALLOWED_TAKERS: usize = 16;
+
+pub const MAX_POOL_MINTS: usize = 8;
+pub const POOL_LP_DECIMALS: u8 = 6;
+
 //-----------------------------------------------------
 ///delphor-user-program PDA
 #[account]
@@ -8,8 +21,32 @@ pub struct GlobalState {
     pub bump: u8,
     // Authority (admin address)
     pub admin_account: Pubkey,
+    // Max age in seconds of the oracle prices used in swaps
+    pub max_price_age: u64,
+    // Max oracle confidence interval, in basis points of the price
+    pub max_confidence: u32,
+    // Deposits and swaps halted, withdrawals remain allowed
+    pub paused: bool,
+    // Mints with deposits and swaps halted
+    pub paused_mints: Vec<Pubkey>,
+    // Share of the swap fees kept by the protocol, in basis points
+    pub protocol_fee_share: u32,
+}
+impl GlobalState {
+    /// Fails if the program or any of the mints is paused
+    pub fn check_not_paused(&self, mints: &[Pubkey]) -> Result<()> {
+        if self.paused {
+            return err!(DelphorError::ProgramPaused);
+        }
+        for mint in mints {
+            if self.paused_mints.contains(mint) {
+                msg!("Mint {} paused", mint);
+                return err!(DelphorError::MintPaused);
+            }
+        }
+        Ok(())
+    }
 }
-
 
 //-----------------------------------------------------
 #[account]
@@ -28,7 +65,728 @@ pub struct UserCoinVault {
     pub provide_status: bool,
     pub limit_price_status: bool,
     pub limit_price: u64,
+    pub global_state: Pubkey,
+    // Max age in seconds of the oracle prices, 0 to use only the global state guard
+    pub max_price_age: u64,
+    // Reference price of the vault token and max deviation from it in basis points,
+    // 0 max_price_deviation to disable the price band
+    pub reference_price: u64,
+    pub max_price_deviation: u32,
+    // Account allowed to receive withdrawals besides the vault owner, default Pubkey for none
+    pub withdraw_recipient: Pubkey,
+    // Max inventory skew of the fees in basis points, 0 for flat fees.
+    // Fees move linearly around the midpoint of [min, max], up to fee_curve at the bounds.
+    pub fee_curve: u32,
+    // Account allowed to update the vault config besides the owner, default Pubkey for none.
+    // It can never withdraw or close the vault.
+    pub operator: Pubkey,
+    // Ceiling price when buying the vault token, limit_price is the floor when selling it
+    pub buy_limit_price_status: bool,
+    pub buy_limit_price: u64,
+    // Mint the limit prices are expressed in, default Pubkey for the oracle USD price.
+    // Swaps against any other token are rejected while a limit is enabled.
+    pub limit_price_mint: Pubkey,
+    // Only the allowed takers can swap against the vault while enabled
+    pub allowlist_status: bool,
+    pub allowed_takers: Vec<Pubkey>,
+    // Max amount sent by swaps in each window of outflow_window seconds, 0 for no limit
+    pub max_outflow: u64,
+    pub outflow_window: u64,
+    // Start of the current window and amount sent by swaps since
+    pub outflow_window_start: u64,
+    pub window_outflow: u64,
+    // Only the vault owner can deposit while set, otherwise any account can fund the vault
+    pub reject_external_deposits: bool,
+}
+impl UserCoinVault {
+    pub fn config(&self) -> VaultConfig {
+        VaultConfig {
+            buy_fee: self.buy_fee,
+            sell_fee: self.sell_fee,
+            min: self.min,
+            max: self.max,
+            receive_status: self.receive_status,
+            provide_status: self.provide_status,
+            limit_price_status: self.limit_price_status,
+            limit_price: self.limit_price,
+            max_price_age: self.max_price_age,
+            reference_price: self.reference_price,
+            max_price_deviation: self.max_price_deviation,
+            fee_curve: self.fee_curve,
+            buy_limit_price_status: self.buy_limit_price_status,
+            buy_limit_price: self.buy_limit_price,
+            limit_price_mint: self.limit_price_mint,
+            allowlist_status: self.allowlist_status,
+            max_outflow: self.max_outflow,
+            outflow_window: self.outflow_window,
+        }
+    }
+
+    pub fn set_config(&mut self, config: &VaultConfig) {
+        self.buy_fee = config.buy_fee;
+        self.sell_fee = config.sell_fee;
+        self.min = config.min;
+        self.max = config.max;
+        self.receive_status = config.receive_status;
+        self.provide_status = config.provide_status;
+        self.limit_price_status = config.limit_price_status;
+        self.limit_price = config.limit_price;
+        self.max_price_age = config.max_price_age;
+        self.reference_price = config.reference_price;
+        self.max_price_deviation = config.max_price_deviation;
+        self.fee_curve = config.fee_curve;
+        self.buy_limit_price_status = config.buy_limit_price_status;
+        self.buy_limit_price = config.buy_limit_price;
+        self.limit_price_mint = config.limit_price_mint;
+        self.allowlist_status = config.allowlist_status;
+        self.max_outflow = config.max_outflow;
+        self.outflow_window = config.outflow_window;
+    }
+
+    /// Fee skew in basis points, from -fee_curve with the vault at min to +fee_curve at max
+    pub fn inventory_skew(&self) -> i64 {
+        if self.fee_curve == 0 || self.max <= self.min {
+            return 0;
+        }
+        let range = (self.max - self.min) as i128;
+        let position = (self.amount.clamp(self.min, self.max) - self.min) as i128;
+        ((2 * position - range) * self.fee_curve as i128 / range) as i64
+    }
+
+    /// Buy fee rising as the vault fills up towards max
+    pub fn effective_buy_fee(&self) -> u32 {
+        skewed_fee(self.buy_fee, self.inventory_skew())
+    }
+
+    /// Sell fee rising as the vault drains towards min
+    pub fn effective_sell_fee(&self) -> u32 {
+        skewed_fee(self.sell_fee, -self.inventory_skew())
+    }
+
+    /// Amount swaps can still send in the current window, u64::MAX without limit
+    pub fn available_outflow(&self, now: u64) -> u64 {
+        if self.max_outflow == 0 {
+            return u64::MAX;
+        }
+        if now
+            >= self
+                .outflow_window_start
+                .saturating_add(self.outflow_window)
+        {
+            return self.max_outflow;
+        }
+        self.max_outflow.saturating_sub(self.window_outflow)
+    }
+
+    /// Adds an amount sent by a swap to the window, starting a new one if expired
+    pub fn record_outflow(&mut self, amount: u64, now: u64) {
+        if self.max_outflow == 0 {
+            return;
+        }
+        if now
+            >= self
+                .outflow_window_start
+                .saturating_add(self.outflow_window)
+        {
+            self.outflow_window_start = now;
+            self.window_outflow = 0;
+        }
+        self.window_outflow = self.window_outflow.saturating_add(amount);
+    }
+
+    /// Adds tokens to the vault balance
+    pub fn credit(&mut self, amount: u64) -> Result<()> {
+        self.amount = math::checked_add(self.amount, amount)?;
+        Ok(())
+    }
+
+    /// Removes tokens from the vault balance
+    pub fn debit(&mut self, amount: u64) -> Result<()> {
+        self.amount = math::checked_sub(self.amount, amount)?;
+        Ok(())
+    }
+
+    pub fn check_owner_or_operator(&self, authority: &Pubkey) -> Result<()> {
+        if *authority != self.user
+            && (self.operator == Pubkey::default() || *authority != self.operator)
+        {
+            msg!("{} is not the vault owner or operator", authority);
+            return err!(DelphorError::AccessDenied);
+        }
+        Ok(())
+    }
+
+    pub fn check_taker(&self, taker: &Pubkey) -> Result<()> {
+        if self.allowlist_status && !self.allowed_takers.contains(taker) {
+            msg!("Taker {} not allowed by vault {}", taker, self.mint);
+            return err!(DelphorError::TakerNotAllowed);
+        }
+        Ok(())
+    }
+
+    pub fn check_depositor(&self, depositor: &Pubkey) -> Result<()> {
+        if self.reject_external_deposits && *depositor != self.user {
+            msg!("Vault only accepts deposits from its owner {}", self.user);
+            return err!(DelphorError::ExternalDepositRejected);
+        }
+        Ok(())
+    }
+
+    /// A full exit must take the whole balance, otherwise the vault min is respected
+    pub fn check_withdraw(&self, amount: u64, full_exit: bool) -> Result<()> {
+        if self.amount < amount {
+            msg!(
+                "Requested to withdraw {} but you have only {}",
+                amount,
+                self.amount
+            );
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        if full_exit {
+            if amount != self.amount {
+                msg!("Full exit must withdraw the whole balance {}", self.amount);
+                return err!(DelphorError::BelowMinAmount);
+            }
+        } else if self.amount - amount < self.min {
+            msg!(
+                "Withdrawing {} leaves {} under min {}",
+                amount,
+                self.amount - amount,
+                self.min
+            );
+            return err!(DelphorError::BelowMinAmount);
+        }
+        Ok(())
+    }
+
+    pub fn check_withdraw_recipient(&self, owner: &Pubkey) -> Result<()> {
+        if *owner != self.user
+            && (self.withdraw_recipient == Pubkey::default() || *owner != self.withdraw_recipient)
+        {
+            msg!("Invalid recipient {}", owner);
+            return err!(DelphorError::InvalidRecipient);
+        }
+        Ok(())
+    }
+}
+
+fn skewed_fee(fee: u32, skew: i64) -> u32 {
+    (fee as i64 + skew).clamp(0, 10000) as u32
+}
+
+//-----------------------------------------------------
+///Vault configuration set by the vault owner or operator
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct VaultConfig {
+    // Fees in basis points
+    pub buy_fee: u32,
+    pub sell_fee: u32,
+    // Bounds of the vault balance kept by swaps
+    pub min: u64,
+    pub max: u64,
+    pub receive_status: bool,
+    pub provide_status: bool,
+    pub limit_price_status: bool,
+    pub limit_price: u64,
+    pub max_price_age: u64,
+    pub reference_price: u64,
+    pub max_price_deviation: u32,
+    pub fee_curve: u32,
+    pub buy_limit_price_status: bool,
+    pub buy_limit_price: u64,
+    pub limit_price_mint: Pubkey,
+    pub allowlist_status: bool,
+    pub max_outflow: u64,
+    pub outflow_window: u64,
+}
+impl VaultConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.buy_fee > 10000 || self.sell_fee > 10000 || self.fee_curve > 10000 {
+            return err!(DelphorError::InvalidFee);
+        }
+        if self.min > self.max {
+            msg!("min {} > max {}", self.min, self.max);
+            return err!(DelphorError::InvalidVaultBounds);
+        }
+        if (self.limit_price_status && self.limit_price == 0)
+            || (self.buy_limit_price_status && self.buy_limit_price == 0)
+        {
+            return err!(DelphorError::InvalidLimitPrice);
+        }
+        // without a reference price every swap would be out of band
+        if self.max_price_deviation > 0 && self.reference_price == 0 {
+            return err!(DelphorError::InvalidReferencePrice);
+        }
+        if self.max_outflow > 0 && self.outflow_window == 0 {
+            return err!(DelphorError::InvalidOutflowWindow);
+        }
+        Ok(())
+    }
+
+    pub fn update(&mut self, update: &VaultConfigUpdate) {
+        if let Some(buy_fee) = update.buy_fee {
+            self.buy_fee = buy_fee;
+        }
+        if let Some(sell_fee) = update.sell_fee {
+            self.sell_fee = sell_fee;
+        }
+        if let Some(min) = update.min {
+            self.min = min;
+        }
+        if let Some(max) = update.max {
+            self.max = max;
+        }
+        if let Some(receive_status) = update.receive_status {
+            self.receive_status = receive_status;
+        }
+        if let Some(provide_status) = update.provide_status {
+            self.provide_status = provide_status;
+        }
+        if let Some(limit_price_status) = update.limit_price_status {
+            self.limit_price_status = limit_price_status;
+        }
+        if let Some(limit_price) = update.limit_price {
+            self.limit_price = limit_price;
+        }
+        if let Some(max_price_age) = update.max_price_age {
+            self.max_price_age = max_price_age;
+        }
+        if let Some(reference_price) = update.reference_price {
+            self.reference_price = reference_price;
+        }
+        if let Some(max_price_deviation) = update.max_price_deviation {
+            self.max_price_deviation = max_price_deviation;
+        }
+        if let Some(fee_curve) = update.fee_curve {
+            self.fee_curve = fee_curve;
+        }
+        if let Some(buy_limit_price_status) = update.buy_limit_price_status {
+            self.buy_limit_price_status = buy_limit_price_status;
+        }
+        if let Some(buy_limit_price) = update.buy_limit_price {
+            self.buy_limit_price = buy_limit_price;
+        }
+        if let Some(limit_price_mint) = update.limit_price_mint {
+            self.limit_price_mint = limit_price_mint;
+        }
+        if let Some(allowlist_status) = update.allowlist_status {
+            self.allowlist_status = allowlist_status;
+        }
+        if let Some(max_outflow) = update.max_outflow {
+            self.max_outflow = max_outflow;
+        }
+        if let Some(outflow_window) = update.outflow_window {
+            self.outflow_window = outflow_window;
+        }
+    }
+}
+
+///Partial update of a VaultConfig, None fields are left unchanged
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct VaultConfigUpdate {
+    pub buy_fee: Option<u32>,
+    pub sell_fee: Option<u32>,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub receive_status: Option<bool>,
+    pub provide_status: Option<bool>,
+    pub limit_price_status: Option<bool>,
+    pub limit_price: Option<u64>,
+    pub max_price_age: Option<u64>,
+    pub reference_price: Option<u64>,
+    pub max_price_deviation: Option<u32>,
+    pub fee_curve: Option<u32>,
+    pub buy_limit_price_status: Option<bool>,
+    pub buy_limit_price: Option<u64>,
+    pub limit_price_mint: Option<Pubkey>,
+    pub allowlist_status: Option<bool>,
+    pub max_outflow: Option<u64>,
+    pub outflow_window: Option<u64>,
+}
+
+//-----------------------------------------------------
+///Per pair overrides of the vault config, for swaps where the user vaults
+///sell mint_a and buy mint_b
+#[account]
+#[derive(Default)]
+pub struct UserPairConfig {
+    pub bump: u8,
+    pub user: Pubkey,
+    // token the user vaults sell, sent to the taker
+    pub mint_a: Pubkey,
+    // token the user vaults buy, received from the taker
+    pub mint_b: Pubkey,
+    pub config: PairConfig,
+}
+
+///Pair configuration set by the vault owner or operator
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PairConfig {
+    // Swaps of the pair allowed
+    pub enabled: bool,
+    // Fees in basis points, replace the mint_a vault sell fee and the mint_b vault buy fee
+    pub sell_fee: u32,
+    pub buy_fee: u32,
+    // Replace the mint_a vault floor price and the mint_b vault ceiling price
+    pub limit_price_status: bool,
+    pub limit_price: u64,
+    pub buy_limit_price_status: bool,
+    pub buy_limit_price: u64,
+    // Max amount of mint_a sent in a single swap, 0 for no limit
+    pub max_amount: u64,
+}
+impl PairConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.buy_fee > 10000 || self.sell_fee > 10000 {
+            return err!(DelphorError::InvalidFee);
+        }
+        if (self.limit_price_status && self.limit_price == 0)
+            || (self.buy_limit_price_status && self.buy_limit_price == 0)
+        {
+            return err!(DelphorError::InvalidLimitPrice);
+        }
+        Ok(())
+    }
+
+    /// Applies the overrides to the copies of the vaults a swap is priced with
+    pub fn apply(&self, user_vault_from: &mut UserCoinVault, user_vault_to: &mut UserCoinVault) {
+        user_vault_from.sell_fee = self.sell_fee;
+        user_vault_from.limit_price_status = self.limit_price_status;
+        user_vault_from.limit_price = self.limit_price;
+        user_vault_to.buy_fee = self.buy_fee;
+        user_vault_to.buy_limit_price_status = self.buy_limit_price_status;
+        user_vault_to.buy_limit_price = self.buy_limit_price;
+    }
 }
-impl UserCoinVault {}
 
 //-----------------------------------------------------
+///Pooled liquidity, owner of one UserCoinVault per mint.
+///Anyone deposits receiving LP shares, the manager sets the vaults config as their operator.
+#[account]
+#[derive(Default)]
+pub struct Pool {
+    pub bump: u8,
+    pub global_state: Pubkey,
+    pub manager: Pubkey,
+    // LP share token, minted by the pool
+    pub lp_mint: Pubkey,
+    // Mints of the pool vaults, in the order their accounts are passed
+    pub mints: Vec<Pubkey>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_name<T>(result: Result<T>) -> String {
+        match result {
+            Err(Error::AnchorError(error)) => error.error_name,
+            Err(error) => panic!("unexpected {}", error),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    fn vault(amount: u64, min: u64, max: u64, fee_curve: u32) -> UserCoinVault {
+        UserCoinVault {
+            amount,
+            min,
+            max,
+            buy_fee: 300,
+            sell_fee: 100,
+            fee_curve,
+            ..UserCoinVault::default()
+        }
+    }
+
+    fn outflow_vault(max_outflow: u64, outflow_window: u64) -> UserCoinVault {
+        UserCoinVault {
+            max_outflow,
+            outflow_window,
+            outflow_window_start: 1000,
+            ..UserCoinVault::default()
+        }
+    }
+
+    #[test]
+    fn vault_config_validate() {
+        assert!(VaultConfig::default().validate().is_ok());
+        let invalid = [
+            (
+                VaultConfig {
+                    buy_fee: 10001,
+                    ..VaultConfig::default()
+                },
+                "InvalidFee",
+            ),
+            (
+                VaultConfig {
+                    sell_fee: 10001,
+                    ..VaultConfig::default()
+                },
+                "InvalidFee",
+            ),
+            (
+                VaultConfig {
+                    fee_curve: 10001,
+                    ..VaultConfig::default()
+                },
+                "InvalidFee",
+            ),
+            (
+                VaultConfig {
+                    min: 2,
+                    max: 1,
+                    ..VaultConfig::default()
+                },
+                "InvalidVaultBounds",
+            ),
+            (
+                VaultConfig {
+                    limit_price_status: true,
+                    ..VaultConfig::default()
+                },
+                "InvalidLimitPrice",
+            ),
+            (
+                VaultConfig {
+                    buy_limit_price_status: true,
+                    ..VaultConfig::default()
+                },
+                "InvalidLimitPrice",
+            ),
+            (
+                VaultConfig {
+                    max_price_deviation: 100,
+                    ..VaultConfig::default()
+                },
+                "InvalidReferencePrice",
+            ),
+            (
+                VaultConfig {
+                    max_outflow: 1,
+                    ..VaultConfig::default()
+                },
+                "InvalidOutflowWindow",
+            ),
+        ];
+        for (config, error) in invalid.iter() {
+            assert_eq!(error_name(config.validate()), *error);
+        }
+
+        let config = VaultConfig {
+            buy_fee: 10000,
+            sell_fee: 10000,
+            fee_curve: 10000,
+            min: 1,
+            max: 1,
+            limit_price_status: true,
+            limit_price: 1,
+            buy_limit_price_status: true,
+            buy_limit_price: 1,
+            reference_price: 1,
+            max_price_deviation: 100,
+            max_outflow: 1,
+            outflow_window: 1,
+            ..VaultConfig::default()
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn vault_config_update_keeps_unset_fields() {
+        let mut config = VaultConfig {
+            buy_fee: 300,
+            sell_fee: 100,
+            max: 10,
+            ..VaultConfig::default()
+        };
+        config.update(&VaultConfigUpdate {
+            sell_fee: Some(50),
+            min: Some(1),
+            ..VaultConfigUpdate::default()
+        });
+        assert_eq!(
+            (config.buy_fee, config.sell_fee, config.min, config.max),
+            (300, 50, 1, 10)
+        );
+    }
+
+    #[test]
+    fn inventory_skew() {
+        // flat fees without a curve or a range
+        assert_eq!(vault(0, 0, 100, 0).inventory_skew(), 0);
+        assert_eq!(vault(5, 10, 10, 200).inventory_skew(), 0);
+
+        assert_eq!(vault(0, 0, 100, 200).inventory_skew(), -200);
+        assert_eq!(vault(25, 0, 100, 200).inventory_skew(), -100);
+        assert_eq!(vault(50, 0, 100, 200).inventory_skew(), 0);
+        assert_eq!(vault(75, 0, 100, 200).inventory_skew(), 100);
+        assert_eq!(vault(100, 0, 100, 200).inventory_skew(), 200);
+        // truncated towards zero
+        assert_eq!(vault(1, 0, 3, 200).inventory_skew(), -66);
+        // balances out of the range count as the bounds
+        assert_eq!(vault(5, 10, 110, 200).inventory_skew(), -200);
+        assert_eq!(vault(500, 10, 110, 200).inventory_skew(), 200);
+    }
+
+    #[test]
+    fn effective_fees() {
+        let full = vault(100, 0, 100, 200);
+        assert_eq!(
+            (full.effective_buy_fee(), full.effective_sell_fee()),
+            (500, 0)
+        );
+        let empty = vault(0, 0, 100, 200);
+        assert_eq!(
+            (empty.effective_buy_fee(), empty.effective_sell_fee()),
+            (100, 300)
+        );
+
+        let mut capped = vault(100, 0, 100, 10000);
+        capped.buy_fee = 9000;
+        assert_eq!(capped.effective_buy_fee(), 10000);
+    }
+
+    #[test]
+    fn outflow_without_limit() {
+        let mut vault = outflow_vault(0, 0);
+        assert_eq!(vault.available_outflow(1000), u64::MAX);
+        vault.record_outflow(500, 1000);
+        assert_eq!(vault.window_outflow, 0);
+        assert_eq!(vault.available_outflow(1000), u64::MAX);
+    }
+
+    #[test]
+    fn outflow_within_window() {
+        let mut vault = outflow_vault(100, 60);
+        vault.record_outflow(30, 1000);
+        vault.record_outflow(50, 1059);
+        assert_eq!(vault.outflow_window_start, 1000);
+        assert_eq!(vault.available_outflow(1059), 20);
+
+        vault.record_outflow(u64::MAX, 1059);
+        assert_eq!(vault.window_outflow, u64::MAX);
+        assert_eq!(vault.available_outflow(1059), 0);
+    }
+
+    #[test]
+    fn outflow_window_expires() {
+        let mut vault = outflow_vault(100, 60);
+        vault.record_outflow(100, 1000);
+        assert_eq!(vault.available_outflow(1059), 0);
+        // the amount sent is kept until a new window starts
+        assert_eq!(vault.available_outflow(1060), 100);
+        assert_eq!(vault.window_outflow, 100);
+
+        vault.record_outflow(40, 1060);
+        assert_eq!(
+            (vault.outflow_window_start, vault.window_outflow),
+            (1060, 40)
+        );
+        assert_eq!(vault.available_outflow(1060), 60);
+    }
+
+    #[test]
+    fn check_withdraw() {
+        let vault = vault(100, 20, 200, 0);
+        assert!(vault.check_withdraw(80, false).is_ok());
+        assert_eq!(
+            error_name(vault.check_withdraw(81, false)),
+            "BelowMinAmount"
+        );
+        assert!(vault.check_withdraw(100, true).is_ok());
+        assert_eq!(error_name(vault.check_withdraw(80, true)), "BelowMinAmount");
+        match vault.check_withdraw(101, true) {
+            Err(Error::ProgramError(error)) => {
+                assert_eq!(error.program_error, ProgramError::InsufficientFunds)
+            }
+            _ => panic!("expected InsufficientFunds"),
+        }
+    }
+
+    #[test]
+    fn credit_and_debit() {
+        let mut vault = vault(100, 0, 0, 0);
+        vault.credit(50).unwrap();
+        vault.debit(150).unwrap();
+        assert_eq!(vault.amount, 0);
+        assert_eq!(error_name(vault.debit(1)), "MathUnderflow");
+        vault.amount = u64::MAX;
+        assert_eq!(error_name(vault.credit(1)), "MathOverflow");
+        assert_eq!(vault.amount, u64::MAX);
+    }
+
+    #[test]
+    fn check_owner_or_operator() {
+        let (user, operator) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut vault = UserCoinVault {
+            user,
+            ..UserCoinVault::default()
+        };
+        assert!(vault.check_owner_or_operator(&user).is_ok());
+        assert_eq!(
+            error_name(vault.check_owner_or_operator(&operator)),
+            "AccessDenied"
+        );
+        // the default operator is not a wildcard
+        assert_eq!(
+            error_name(vault.check_owner_or_operator(&Pubkey::default())),
+            "AccessDenied"
+        );
+        vault.operator = operator;
+        assert!(vault.check_owner_or_operator(&operator).is_ok());
+    }
+
+    #[test]
+    fn check_taker() {
+        let taker = Pubkey::new_unique();
+        let mut vault = UserCoinVault::default();
+        assert!(vault.check_taker(&taker).is_ok());
+        vault.allowlist_status = true;
+        assert_eq!(error_name(vault.check_taker(&taker)), "TakerNotAllowed");
+        vault.allowed_takers.push(taker);
+        assert!(vault.check_taker(&taker).is_ok());
+    }
+
+    #[test]
+    fn check_withdraw_recipient() {
+        let user = Pubkey::new_unique();
+        let vault = UserCoinVault {
+            user,
+            ..UserCoinVault::default()
+        };
+        assert!(vault.check_withdraw_recipient(&user).is_ok());
+        assert_eq!(
+            error_name(vault.check_withdraw_recipient(&Pubkey::default())),
+            "InvalidRecipient"
+        );
+    }
+
+    #[test]
+    fn pair_config_overrides_the_vaults() {
+        let config = PairConfig {
+            enabled: true,
+            sell_fee: 10,
+            buy_fee: 20,
+            limit_price_status: true,
+            limit_price: 5,
+            ..PairConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let (mut vault_from, mut vault_to) = (vault(0, 0, 0, 0), vault(0, 0, 0, 0));
+        config.apply(&mut vault_from, &mut vault_to);
+        assert_eq!(
+            (vault_from.sell_fee, vault_from.buy_fee, vault_to.buy_fee),
+            (10, 300, 20)
+        );
+        assert!(vault_from.limit_price_status && !vault_to.buy_limit_price_status);
+
+        let invalid = PairConfig {
+            buy_limit_price_status: true,
+            ..PairConfig::default()
+        };
+        assert_eq!(error_name(invalid.validate()), "InvalidLimitPrice");
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error::*;
+use crate::math;
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    extension::{
+        transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType,
+        StateWithExtensions,
+    },
+    state::Mint,
+};
+
+/// Token-2022 mint extensions a vault token can have. Rejected are the ones able to move or
+/// freeze the store balances (permanent delegate, frozen default account state), to hide them
+/// (confidential transfers), to run code on transfers (transfer hook) or to block them
+/// (non-transferable).
+const ALLOWED_MINT_EXTENSIONS: [ExtensionType; 5] = [
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::MintCloseAuthority,
+    ExtensionType::InterestBearingConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+];
+
+/// Fails if the mint has an extension out of ALLOWED_MINT_EXTENSIONS.
+/// Mints of the original token program have none.
+pub fn check_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    for extension in mint_state.get_extension_types()? {
+        if !ALLOWED_MINT_EXTENSIONS.contains(&extension) {
+            msg!(
+                "Mint {} has the unsupported extension {:?}",
+                mint.key,
+                extension
+            );
+            return err!(DelphorError::UnsupportedMintExtension);
+        }
+    }
+    Ok(())
+}
+
+/// Amount received out of a transfer of `amount`, net of the Token-2022 transfer fee
+pub fn amount_after_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    match transfer_fee_config(mint)? {
+        Some(config) => after_fee(&config, Clock::get()?.epoch, amount),
+        None => Ok(amount),
+    }
+}
+
+/// Amount to transfer for `amount` to be received, adding the Token-2022 transfer fee
+pub fn amount_before_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    match transfer_fee_config(mint)? {
+        Some(config) => before_fee(&config, Clock::get()?.epoch, amount),
+        None => Ok(amount),
+    }
+}
+
+fn transfer_fee_config(mint: &AccountInfo) -> Result<Option<TransferFeeConfig>> {
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    Ok(mint_state
+        .get_extension::<TransferFeeConfig>()
+        .ok()
+        .copied())
+}
+
+fn after_fee(config: &TransferFeeConfig, epoch: u64, amount: u64) -> Result<u64> {
+    let fee = config
+        .calculate_epoch_fee(epoch, amount)
+        .ok_or_else(|| error!(DelphorError::MathOverflow))?;
+    math::checked_sub(amount, fee)
+}
+
+fn before_fee(config: &TransferFeeConfig, epoch: u64, amount: u64) -> Result<u64> {
+    let fee = config
+        .calculate_inverse_epoch_fee(epoch, amount)
+        .ok_or_else(|| error!(DelphorError::MathOverflow))?;
+    math::checked_add(amount, fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_spl::token_2022::spl_token_2022::extension::{
+        permanent_delegate::PermanentDelegate, transfer_fee::TransferFee, StateWithExtensionsMut,
+    };
+    use anchor_spl::token_2022::spl_token_2022::solana_program::program_pack::Pack;
+
+    // 1% fee capped at 50 tokens, from epoch 10
+    fn fee_config() -> TransferFeeConfig {
+        let fee = TransferFee {
+            epoch: 10.into(),
+            maximum_fee: 50.into(),
+            transfer_fee_basis_points: 100.into(),
+        };
+        TransferFeeConfig {
+            newer_transfer_fee: fee,
+            ..TransferFeeConfig::default()
+        }
+    }
+
+    // Mint with the transfer fee and permanent delegate extensions when enabled
+    fn mint_data(transfer_fee: bool, permanent_delegate: bool) -> Vec<u8> {
+        let extensions: Vec<ExtensionType> = [
+            (transfer_fee, ExtensionType::TransferFeeConfig),
+            (permanent_delegate, ExtensionType::PermanentDelegate),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, extension)| *extension)
+        .collect();
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&extensions).unwrap();
+        let mut data = vec![0; len];
+        if extensions.is_empty() {
+            Mint {
+                is_initialized: true,
+                ..Mint::default()
+            }
+            .pack_into_slice(&mut data);
+            return data;
+        }
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        if transfer_fee {
+            state.init_extension::<TransferFeeConfig>(true).unwrap();
+        }
+        if permanent_delegate {
+            state.init_extension::<PermanentDelegate>(true).unwrap();
+        }
+        state.base.is_initialized = true;
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    fn check(transfer_fee: bool, permanent_delegate: bool) -> Result<()> {
+        let key = Pubkey::new_unique();
+        let owner = anchor_spl::token_2022::ID;
+        let mut lamports = 0;
+        let mut data = mint_data(transfer_fee, permanent_delegate);
+        let mint = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        check_mint_extensions(&mint)
+    }
+
+    #[test]
+    fn transfer_fee_is_charged_from_its_epoch() {
+        let config = fee_config();
+        assert_eq!(after_fee(&config, 9, 1000).unwrap(), 1000);
+        assert_eq!(after_fee(&config, 10, 1000).unwrap(), 990);
+        // capped at the maximum fee
+        assert_eq!(after_fee(&config, 10, 1_000_000).unwrap(), 999_950);
+    }
+
+    #[test]
+    fn amount_before_fee_is_received_after_fee() {
+        let config = fee_config();
+        for amount in [1, 99, 990, 1001, 999_950, 1_000_000] {
+            let gross = before_fee(&config, 10, amount).unwrap();
+            assert_eq!(after_fee(&config, 10, gross).unwrap(), amount);
+        }
+        assert_eq!(before_fee(&config, 9, 990).unwrap(), 990);
+    }
+
+    #[test]
+    fn mints_without_extensions_are_supported() {
+        assert!(check(false, false).is_ok());
+    }
+
+    #[test]
+    fn transfer_fee_mints_are_supported() {
+        assert!(check(true, false).is_ok());
+    }
+
+    #[test]
+    fn permanent_delegate_mints_are_rejected() {
+        match check(true, true) {
+            Err(Error::AnchorError(error)) => {
+                assert_eq!(error.error_name, "UnsupportedMintExtension")
+            }
+            _ => panic!("expected UnsupportedMintExtension"),
+        }
+    }
+}
@@ -1,5 +1,5 @@
-const anchor = require("@project-serum/anchor");
-const BN = require("@project-serum/anchor").BN;
+const anchor = require("@coral-xyz/anchor");
+const BN = require("@coral-xyz/anchor").BN;
 const PublicKey = require("@solana/web3.js").PublicKey;
 const assert = require("assert");
 const {
@@ -9,7 +9,7 @@ const {
 } = require("./utils");
 
 describe("delphor-oracle", () => {
-  const provider = anchor.Provider.env();
+  const provider = anchor.AnchorProvider.env();
 
   // Configure the client to use the local cluster.
   anchor.setProvider(provider);
@@ -1,5 +1,5 @@
-const anchor = require("@project-serum/anchor");
-const BN = require("@project-serum/anchor").BN;
+const anchor = require("@coral-xyz/anchor");
+const BN = require("@coral-xyz/anchor").BN;
 const PublicKey = require("@solana/web3.js").PublicKey;
 const assert = require("assert");
 const {
@@ -11,7 +11,7 @@ const {
 } = require("./utils");
 
 describe("delphor-oracle-aggregator", () => {
-  const provider = anchor.Provider.env();
+  const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
 
   const delphorOracleProgram = anchor.workspace.DelphorOracle;
@@ -49,7 +49,7 @@ describe("delphor-oracle-aggregator", () => {
   }
 
   it("Create MockSOL", async () => {
-    mockSOLMint = await createMint(provider, adminAccount);
+    mockSOLMint = await createMint(provider, adminAccount, mockSOL.decimals);
   });
 
   it("DelphorOracle create coin", async () => {
@@ -87,13 +87,34 @@ describe("delphor-oracle-aggregator", () => {
     );
   });
 
-  it("DelphorOracleAggregator init coin", async () => {
+  it("DelphorOracleAggregator reject init coin with wrong decimals", async () => {
     [delphorAggregatorMockSOLPDA] =
       await PublicKey.findProgramAddress(
         [mockSOLMint.toBuffer()],
         delphorAggregatorProgram.programId
       );
 
+    assert.ok(
+      await expectProgramCallRevert(
+        delphorAggregatorProgram,
+        "initCoin",
+        [6, mockSOL.symbol],
+        {
+          switchboardOptimizedFeedAccount: switchboardOptimizedFeedAccount,
+          pythProductAccount: pythProductAccount,
+          coinData: delphorAggregatorMockSOLPDA,
+          mint: mockSOLMint,
+          authority: adminAccount,
+          payer,
+          systemProgram,
+        },
+        "Decimals don't match the mint."
+      )
+    );
+  });
+
+  it("DelphorOracleAggregator init coin", async () => {
+
     await programCall(
       delphorAggregatorProgram,
       "initCoin",
@@ -115,8 +136,14 @@ describe("delphor-oracle-aggregator", () => {
 
     assert.ok(
       checkEqualValues(
-        [mockSOLMint, adminAccount, mockSOL.symbol, mockSOL.decimals],
-        [pdaData.mint, pdaData.authority, pdaData.symbol, pdaData.decimals]
+        [mockSOLMint, adminAccount, mockSOL.symbol, mockSOL.decimals, 9],
+        [
+          pdaData.mint,
+          pdaData.authority,
+          pdaData.symbol,
+          pdaData.decimals,
+          pdaData.priceExponent,
+        ]
       )
     );
   });
@@ -223,6 +250,9 @@ describe("delphor-oracle-aggregator", () => {
     const pdaData = await delphorAggregatorProgram.account.coinData.fetch(
       delphorAggregatorMockSOLPDA
     );
+    const coinInfo = await delphorOracleProgram.account.coinInfo.fetch(
+      delphorOracleMockSOLPDA
+    );
 
     assert.ok(
       checkEqualValues(
@@ -230,5 +260,7 @@ describe("delphor-oracle-aggregator", () => {
         [pdaData.mint, pdaData.authority, pdaData.symbol, pdaData.decimals]
       )
     );
+    assert.ok(pdaData.lastUpdateTimestamp.eq(coinInfo.lastUpdateTimestamp));
+    assert.ok(pdaData.lastUpdateSlot.gtn(0));
   });
 });
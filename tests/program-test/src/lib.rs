@@ -0,0 +1,1094 @@
+//! Fixtures to run the Delphor programs in `solana-program-test`.
+//!
+//! The three programs run natively, linked into the test binary, so `cargo test` at the
+//! workspace root runs them without a BPF build. spl-token, Token-2022 and the associated
+//! token program are the BPF builds bundled with solana-program-test.
+
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{ExtensionType, StateWithExtensions},
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program, sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use super_liquidity::states::{UserCoinVault, VaultConfig, VaultConfigUpdate};
+
+pub use delphor_oracle::ID as DELPHOR_ORACLE_ID;
+pub use delphor_oracle_aggregator::ID as DELPHOR_ORACLE_AGGREGATOR_ID;
+pub use super_liquidity::super_liquidity::ID as SUPER_LIQUIDITY_ID;
+
+// Owners of the oracle feed accounts on mainnet, the aggregator reads them regardless
+pub const PYTH_PROGRAM_ID: Pubkey = pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+pub const SWITCHBOARD_PROGRAM_ID: Pubkey = pubkey!("DtmE9D2CSB4L5D6A15mraeEjrGMm6auWVzgaD8hK2tZM");
+
+// Oracle prices are in units of 10^-9 USD
+pub const USD: u64 = 1_000_000_000;
+
+// mockSOL has 9 decimals and mockUSDC 6, as in the mocha tests
+pub const SOL: u64 = 1_000_000_000;
+pub const USDC: u64 = 1_000_000;
+
+// The anchor entrypoints borrow the accounts for the 'info lifetime of the instruction,
+// longer than the processor! signature does, the copies are leaked for the test run
+macro_rules! native_entry {
+    ($name:ident, $program:ident) => {
+        fn $name(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+            let accounts = Box::leak(Box::new(accounts.to_vec()));
+            $program::entry(program_id, accounts, data)
+        }
+    };
+}
+native_entry!(super_liquidity_entry, super_liquidity);
+native_entry!(delphor_oracle_entry, delphor_oracle);
+native_entry!(delphor_oracle_aggregator_entry, delphor_oracle_aggregator);
+
+/// ProgramTest with the three programs
+pub fn program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::new(
+        "super_liquidity",
+        SUPER_LIQUIDITY_ID,
+        processor!(super_liquidity_entry),
+    );
+    program_test.add_program(
+        "delphor_oracle",
+        DELPHOR_ORACLE_ID,
+        processor!(delphor_oracle_entry),
+    );
+    program_test.add_program(
+        "delphor_oracle_aggregator",
+        DELPHOR_ORACLE_AGGREGATOR_ID,
+        processor!(delphor_oracle_aggregator_entry),
+    );
+    program_test
+}
+
+/// Sends the instructions in one transaction paid by the context payer
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let transaction = transaction(context, instructions, signers).await;
+    context.banks_client.process_transaction(transaction).await
+}
+
+/// Transaction paid by the context payer, signed by it and `signers`
+pub async fn transaction(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Transaction {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    )
+}
+
+/// Custom error code a transaction failed with, panics if it failed otherwise
+pub fn custom_error(error: BanksClientError) -> u32 {
+    match error.unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => code,
+        error => panic!("expected a custom error, got {:?}", error),
+    }
+}
+
+/// Moves the clock `seconds` forward
+pub async fn advance_clock(context: &mut ProgramTestContext, seconds: i64) {
+    let mut clock: sysvar::clock::Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+/// New account funded by the payer with `lamports`
+pub async fn create_user(context: &mut ProgramTestContext, lamports: u64) -> Keypair {
+    let user = Keypair::new();
+    let instruction =
+        system_instruction::transfer(&context.payer.pubkey(), &user.pubkey(), lamports);
+    process(context, &[instruction], &[]).await.unwrap();
+    user
+}
+
+/// Mint and the token program owning it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Token {
+    pub mint: Pubkey,
+    pub program: Pubkey,
+}
+impl Token {
+    pub fn associated_token_address(&self, owner: &Pubkey) -> Pubkey {
+        spl_associated_token_account::get_associated_token_address_with_program_id(
+            owner,
+            &self.mint,
+            &self.program,
+        )
+    }
+}
+
+/// New spl-token mint with the payer as mint authority
+pub async fn create_mint(context: &mut ProgramTestContext, decimals: u8) -> Token {
+    let mint = Keypair::new();
+    let token = Token {
+        mint: mint.pubkey(),
+        program: spl_token::ID,
+    };
+    let instructions = [
+        create_mint_account(context, &token, &[]).await,
+        spl_token::instruction::initialize_mint(
+            &spl_token::ID,
+            &token.mint,
+            &context.payer.pubkey(),
+            None,
+            decimals,
+        )
+        .unwrap(),
+    ];
+    process(context, &instructions, &[&mint]).await.unwrap();
+    token
+}
+
+/// New Token-2022 mint charging `fee_basis_points` on transfers, up to `maximum_fee`
+pub async fn create_transfer_fee_mint(
+    context: &mut ProgramTestContext,
+    decimals: u8,
+    fee_basis_points: u16,
+    maximum_fee: u64,
+) -> Token {
+    let mint = Keypair::new();
+    let token = Token {
+        mint: mint.pubkey(),
+        program: spl_token_2022::ID,
+    };
+    let payer = context.payer.pubkey();
+    let instructions = [
+        create_mint_account(context, &token, &[ExtensionType::TransferFeeConfig]).await,
+        spl_token_2022::extension::transfer_fee::instruction::initialize_transfer_fee_config(
+            &spl_token_2022::ID,
+            &token.mint,
+            Some(&payer),
+            Some(&payer),
+            fee_basis_points,
+            maximum_fee,
+        )
+        .unwrap(),
+        spl_token_2022::instruction::initialize_mint(
+            &spl_token_2022::ID,
+            &token.mint,
+            &payer,
+            None,
+            decimals,
+        )
+        .unwrap(),
+    ];
+    process(context, &instructions, &[&mint]).await.unwrap();
+    token
+}
+
+async fn create_mint_account(
+    context: &mut ProgramTestContext,
+    token: &Token,
+    extensions: &[ExtensionType],
+) -> Instruction {
+    let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(extensions)
+        .unwrap();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    system_instruction::create_account(
+        &context.payer.pubkey(),
+        &token.mint,
+        rent.minimum_balance(space),
+        space as u64,
+        &token.program,
+    )
+}
+
+/// Associated token account of `owner`, created with `amount` minted to it
+pub async fn create_token_account(
+    context: &mut ProgramTestContext,
+    token: &Token,
+    owner: &Pubkey,
+    amount: u64,
+) -> Pubkey {
+    let address = token.associated_token_address(owner);
+    let mut instructions = vec![
+        spl_associated_token_account::instruction::create_associated_token_account(
+            &context.payer.pubkey(),
+            owner,
+            &token.mint,
+            &token.program,
+        ),
+    ];
+    if amount > 0 {
+        instructions.push(
+            spl_token_2022::instruction::mint_to(
+                &token.program,
+                &token.mint,
+                &address,
+                &context.payer.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap(),
+        );
+    }
+    process(context, &instructions, &[]).await.unwrap();
+    address
+}
+
+/// Balance of a token account of either token program
+pub async fn token_balance(context: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    let account = get_account(context, address).await;
+    StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .unwrap()
+        .base
+        .amount
+}
+
+pub async fn get_account(context: &mut ProgramTestContext, address: &Pubkey) -> Account {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .unwrap_or_else(|| panic!("account {} not found", address))
+}
+
+/// Anchor account at `address`, checked against its discriminator
+pub async fn get_anchor_account<T: AccountDeserialize>(
+    context: &mut ProgramTestContext,
+    address: &Pubkey,
+) -> T {
+    let account = get_account(context, address).await;
+    T::try_deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+//-----------------------------------------------------
+// Oracle feeds
+
+/// Pyth product account of `symbol`/USD, pointing to `price_account`
+pub fn set_pyth_product(
+    context: &mut ProgramTestContext,
+    address: &Pubkey,
+    symbol: &str,
+    price_account: &Pubkey,
+) {
+    let mut product: pyth_client::Product = bytemuck::Zeroable::zeroed();
+    product.magic = pyth_client::MAGIC;
+    product.ver = pyth_client::VERSION;
+    product.atype = pyth_client::AccountType::Product as u32;
+    product.px_acc.val = price_account.to_bytes();
+    // length prefixed key value pairs
+    let mut attr = Vec::new();
+    for value in ["symbol", &format!("Crypto.{}/USD", symbol)] {
+        attr.push(value.len() as u8);
+        attr.extend_from_slice(value.as_bytes());
+    }
+    product.attr[..attr.len()].copy_from_slice(&attr);
+    set_feed(
+        context,
+        address,
+        &PYTH_PROGRAM_ID,
+        bytemuck::bytes_of(&product),
+    );
+}
+
+/// Trading Pyth price account, price and confidence in units of 10^expo USD
+pub fn set_pyth_price(
+    context: &mut ProgramTestContext,
+    address: &Pubkey,
+    price: i64,
+    confidence: u64,
+    expo: i32,
+) {
+    let mut price_account: pyth_client::Price = bytemuck::Zeroable::zeroed();
+    price_account.magic = pyth_client::MAGIC;
+    price_account.ver = pyth_client::VERSION;
+    price_account.atype = pyth_client::AccountType::Price as u32;
+    price_account.ptype = pyth_client::PriceType::Price;
+    price_account.expo = expo;
+    price_account.agg.price = price;
+    price_account.agg.conf = confidence;
+    price_account.agg.status = pyth_client::PriceStatus::Trading;
+    set_feed(
+        context,
+        address,
+        &PYTH_PROGRAM_ID,
+        bytemuck::bytes_of(&price_account),
+    );
+}
+
+/// Switchboard v1 optimized result account, `result` in USD
+pub fn set_switchboard_result(context: &mut ProgramTestContext, address: &Pubkey, result: f64) {
+    let mut feed = switchboard_program::FastRoundResultAccountData::default();
+    feed.result.result = result;
+    let mut data = Vec::new();
+    feed.serialize(&mut data).unwrap();
+    set_feed(context, address, &SWITCHBOARD_PROGRAM_ID, &data);
+}
+
+fn set_feed(context: &mut ProgramTestContext, address: &Pubkey, owner: &Pubkey, data: &[u8]) {
+    let account = Account {
+        lamports: SOL,
+        data: data.to_vec(),
+        owner: *owner,
+        executable: false,
+        rent_epoch: 0,
+    };
+    context.set_account(address, &AccountSharedData::from(account));
+}
+
+/// Instructions and accounts of delphor-oracle and delphor-oracle-aggregator
+pub mod oracle {
+    use super::*;
+
+    pub fn coin_info_address(symbol: &str) -> Pubkey {
+        Pubkey::find_program_address(&[symbol.as_bytes()], &DELPHOR_ORACLE_ID).0
+    }
+
+    pub fn coin_data_address(mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[mint.as_ref()], &DELPHOR_ORACLE_AGGREGATOR_ID).0
+    }
+
+    /// delphor-oracle CoinInfo of `symbol`, with both feeder prices set to `price`
+    pub fn create_coin(
+        authority: &Pubkey,
+        payer: &Pubkey,
+        symbol: &str,
+        price: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: DELPHOR_ORACLE_ID,
+            accounts: delphor_oracle::accounts::CreateCoin {
+                coin: coin_info_address(symbol),
+                authority: *authority,
+                payer: *payer,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: delphor_oracle::instruction::CreateCoin {
+                coin_gecko_price: price,
+                orca_price: price,
+                symbol: symbol.to_string(),
+            }
+            .data(),
+        }
+    }
+
+    pub fn update_coin(authority: &Pubkey, symbol: &str, price: u64) -> Instruction {
+        Instruction {
+            program_id: DELPHOR_ORACLE_ID,
+            accounts: delphor_oracle::accounts::UpdateCoin {
+                coin: coin_info_address(symbol),
+                authority: *authority,
+            }
+            .to_account_metas(None),
+            data: delphor_oracle::instruction::UpdateCoin {
+                coin_gecko_price: price,
+                orca_price: price,
+            }
+            .data(),
+        }
+    }
+
+    /// Aggregator CoinData of `mint`, default pubkeys for the feeds not used
+    pub fn init_coin(
+        authority: &Pubkey,
+        payer: &Pubkey,
+        mint: &Pubkey,
+        decimals: u8,
+        symbol: &str,
+        switchboard_feed: &Pubkey,
+        pyth_product: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: DELPHOR_ORACLE_AGGREGATOR_ID,
+            accounts: delphor_oracle_aggregator::accounts::InitCoinPrice {
+                coin_data: coin_data_address(mint),
+                mint: *mint,
+                authority: *authority,
+                switchboard_optimized_feed_account: *switchboard_feed,
+                pyth_product_account: *pyth_product,
+                payer: *payer,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: delphor_oracle_aggregator::instruction::InitCoin {
+                decimals,
+                symbol: symbol.to_string(),
+            }
+            .data(),
+        }
+    }
+
+    /// Aggregates `coin_info` and the feeds into the CoinData of `mint`
+    pub fn update_coin_price(
+        payer: &Pubkey,
+        mint: &Pubkey,
+        coin_info: &Pubkey,
+        switchboard_feed: &Pubkey,
+        pyth_price: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: DELPHOR_ORACLE_AGGREGATOR_ID,
+            accounts: delphor_oracle_aggregator::accounts::UpdateCoinPrice {
+                switchboard_optimized_feed_account: *switchboard_feed,
+                pyth_price_account: *pyth_price,
+                delphor_oracle: *coin_info,
+                coin_data: coin_data_address(mint),
+                payer: *payer,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: delphor_oracle_aggregator::instruction::UpdateCoinPrice {}.data(),
+        }
+    }
+}
+
+/// Publishes a coin price in delphor-oracle and aggregates it into the CoinData of `mint`,
+/// without pyth or switchboard feeds the aggregated price is the delphor-oracle one
+pub async fn create_coin(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    decimals: u8,
+    symbol: &str,
+    price: u64,
+) -> Pubkey {
+    let payer = context.payer.pubkey();
+    let no_feed = Pubkey::default();
+    let coin_info = oracle::coin_info_address(symbol);
+    let instructions = [
+        oracle::create_coin(&payer, &payer, symbol, price),
+        oracle::init_coin(&payer, &payer, mint, decimals, symbol, &no_feed, &no_feed),
+        oracle::update_coin_price(&payer, mint, &coin_info, &no_feed, &no_feed),
+    ];
+    process(context, &instructions, &[]).await.unwrap();
+    oracle::coin_data_address(mint)
+}
+
+/// Instructions and accounts of the super-liquidity program
+pub mod ix {
+    use super::*;
+    use super_liquidity::{accounts, instruction};
+
+    pub fn global_state_address(admin: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[admin.as_ref()], &SUPER_LIQUIDITY_ID).0
+    }
+
+    pub fn token_store_authority() -> Pubkey {
+        Pubkey::find_program_address(&[b"store_auth"], &SUPER_LIQUIDITY_ID).0
+    }
+
+    pub fn token_store_address(global_state: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[global_state.as_ref(), mint.as_ref()], &SUPER_LIQUIDITY_ID).0
+    }
+
+    pub fn treasury_address(global_state: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"treasury", global_state.as_ref(), mint.as_ref()],
+            &SUPER_LIQUIDITY_ID,
+        )
+        .0
+    }
+
+    pub fn user_vault_address(user: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[user.as_ref(), mint.as_ref()], &SUPER_LIQUIDITY_ID).0
+    }
+
+    pub fn pair_config_address(user: &Pubkey, mint_from: &Pubkey, mint_to: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"pair_config",
+                user.as_ref(),
+                mint_from.as_ref(),
+                mint_to.as_ref(),
+            ],
+            &SUPER_LIQUIDITY_ID,
+        )
+        .0
+    }
+
+    fn instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: SUPER_LIQUIDITY_ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    pub fn initialize(admin: &Pubkey) -> Instruction {
+        instruction(
+            accounts::Initialize {
+                admin_account: *admin,
+                global_state: global_state_address(admin),
+                system_program: system_program::ID,
+            },
+            instruction::Initialize {},
+        )
+    }
+
+    pub fn init_token_store(admin: &Pubkey, token: &Token) -> Instruction {
+        let global_state = global_state_address(admin);
+        instruction(
+            accounts::InitTokenStore {
+                global_state,
+                admin_account: *admin,
+                mint: token.mint,
+                token_store_authority: token_store_authority(),
+                token_store: token_store_address(&global_state, &token.mint),
+                system_program: system_program::ID,
+                token_program: token.program,
+                rent: sysvar::rent::ID,
+            },
+            instruction::InitTokenStore {},
+        )
+    }
+
+    pub fn init_treasury(admin: &Pubkey, token: &Token) -> Instruction {
+        let global_state = global_state_address(admin);
+        instruction(
+            accounts::InitTreasury {
+                global_state,
+                admin_account: *admin,
+                mint: token.mint,
+                token_store_authority: token_store_authority(),
+                treasury: treasury_address(&global_state, &token.mint),
+                system_program: system_program::ID,
+                token_program: token.program,
+                rent: sysvar::rent::ID,
+            },
+            instruction::InitTreasury {},
+        )
+    }
+
+    pub fn migrate_token_store(admin: &Pubkey, token: &Token) -> Instruction {
+        let global_state = global_state_address(admin);
+        instruction(
+            accounts::MigrateTokenStore {
+                global_state,
+                admin_account: *admin,
+                mint: token.mint,
+                token_store_authority: token_store_authority(),
+                legacy_token_store: token.associated_token_address(&token_store_authority()),
+                token_store: token_store_address(&global_state, &token.mint),
+                token_program: token.program,
+            },
+            instruction::MigrateTokenStore {},
+        )
+    }
+
+    pub fn migrate_user_vault(admin: &Pubkey, user: &Pubkey, mint: &Pubkey) -> Instruction {
+        instruction(
+            accounts::MigrateUserVault {
+                global_state: global_state_address(admin),
+                admin_account: *admin,
+                user_vault: user_vault_address(user, mint),
+            },
+            instruction::MigrateUserVault {},
+        )
+    }
+
+    pub fn set_paused(admin: &Pubkey, paused: bool) -> Instruction {
+        instruction(
+            accounts::SetPaused {
+                global_state: global_state_address(admin),
+                admin_account: *admin,
+            },
+            instruction::SetPaused { paused },
+        )
+    }
+
+    pub fn set_mint_paused(admin: &Pubkey, mint: &Pubkey, paused: bool) -> Instruction {
+        instruction(
+            accounts::SetMintPaused {
+                global_state: global_state_address(admin),
+                admin_account: *admin,
+                mint: *mint,
+            },
+            instruction::SetMintPaused { paused },
+        )
+    }
+
+    pub fn set_protocol_fee(admin: &Pubkey, protocol_fee_share: u32) -> Instruction {
+        instruction(
+            accounts::SetProtocolFee {
+                global_state: global_state_address(admin),
+                admin_account: *admin,
+            },
+            instruction::SetProtocolFee { protocol_fee_share },
+        )
+    }
+
+    pub fn init_user_vault(
+        global_state: &Pubkey,
+        user: &Pubkey,
+        mint: &Pubkey,
+        config: VaultConfig,
+    ) -> Instruction {
+        instruction(
+            accounts::InitUserVault {
+                global_state: *global_state,
+                user_account: *user,
+                mint: *mint,
+                user_vault: user_vault_address(user, mint),
+                system_program: system_program::ID,
+            },
+            instruction::InitUserVault { config },
+        )
+    }
+
+    /// `depositor` funds the vault of `user` from its associated token account
+    pub fn deposit(
+        global_state: &Pubkey,
+        user: &Pubkey,
+        depositor: &Pubkey,
+        token: &Token,
+        amount: u64,
+    ) -> Instruction {
+        instruction(
+            accounts::Deposit {
+                global_state: *global_state,
+                user_account: *user,
+                user_vault: user_vault_address(user, &token.mint),
+                mint: token.mint,
+                get_token_from: token.associated_token_address(depositor),
+                get_token_from_authority: *depositor,
+                token_store_pda: token_store_address(global_state, &token.mint),
+                system_program: system_program::ID,
+                token_program: token.program,
+            },
+            instruction::Deposit { amount },
+        )
+    }
+
+    /// Withdraw from the vault of `user` to the associated token account of `recipient`
+    pub fn withdraw(
+        global_state: &Pubkey,
+        user: &Pubkey,
+        recipient: &Pubkey,
+        token: &Token,
+        amount: u64,
+        full_exit: bool,
+    ) -> Instruction {
+        instruction(
+            accounts::Withdraw {
+                global_state: *global_state,
+                user_vault: user_vault_address(user, &token.mint),
+                mint: token.mint,
+                send_token_to: token.associated_token_address(recipient),
+                token_store_authority: token_store_authority(),
+                token_store_pda: token_store_address(global_state, &token.mint),
+                user_account: *user,
+                system_program: system_program::ID,
+                token_program: token.program,
+            },
+            instruction::Withdraw { amount, full_exit },
+        )
+    }
+
+    /// `taker` swaps `send` for `receive` against the vaults of `vault_owner`,
+    /// from and to the associated token accounts of the taker
+    pub fn swap(
+        global_state: &Pubkey,
+        vault_owner: &Pubkey,
+        taker: &Pubkey,
+        send: &Token,
+        receive: &Token,
+        swap_amount: u64,
+        min_amount: u64,
+    ) -> Instruction {
+        instruction(
+            swap_accounts(global_state, vault_owner, taker, send, receive),
+            instruction::Swap {
+                swap_amount,
+                min_amount,
+            },
+        )
+    }
+
+    /// Swap without the treasury account, accepted while there is no protocol fee
+    pub fn swap_without_treasury(
+        global_state: &Pubkey,
+        vault_owner: &Pubkey,
+        taker: &Pubkey,
+        send: &Token,
+        receive: &Token,
+        swap_amount: u64,
+        min_amount: u64,
+    ) -> Instruction {
+        instruction(
+            accounts::Swap {
+                treasury: None,
+                ..swap_accounts(global_state, vault_owner, taker, send, receive)
+            },
+            instruction::Swap {
+                swap_amount,
+                min_amount,
+            },
+        )
+    }
+
+    pub fn swap_exact_out(
+        global_state: &Pubkey,
+        vault_owner: &Pubkey,
+        taker: &Pubkey,
+        send: &Token,
+        receive: &Token,
+        amount_to_send: u64,
+        max_amount_in: u64,
+    ) -> Instruction {
+        instruction(
+            swap_accounts(global_state, vault_owner, taker, send, receive),
+            instruction::SwapExactOut {
+                amount_to_send,
+                max_amount_in,
+            },
+        )
+    }
+
+    fn swap_accounts(
+        global_state: &Pubkey,
+        vault_owner: &Pubkey,
+        taker: &Pubkey,
+        send: &Token,
+        receive: &Token,
+    ) -> accounts::Swap {
+        accounts::Swap {
+            global_state: *global_state,
+            get_coin_data: oracle::coin_data_address(&send.mint),
+            send_coin_data: oracle::coin_data_address(&receive.mint),
+            user_vault_from: user_vault_address(vault_owner, &receive.mint),
+            user_vault_to: user_vault_address(vault_owner, &send.mint),
+            pair_config: pair_config_address(vault_owner, &receive.mint, &send.mint),
+            token_store_authority: token_store_authority(),
+            mint_send: send.mint,
+            mint_receive: receive.mint,
+            get_token_from: send.associated_token_address(taker),
+            get_token_from_authority: *taker,
+            send_token_to: receive.associated_token_address(taker),
+            token_store_pda_from: token_store_address(global_state, &receive.mint),
+            token_store_pda_to: token_store_address(global_state, &send.mint),
+            treasury: Some(treasury_address(global_state, &receive.mint)),
+            system_program: system_program::ID,
+            token_program: send.program,
+            token_program_receive: receive.program,
+        }
+    }
+
+    /// Swap filled by the vaults of `vault_owners`
+    pub fn swap_multi(
+        global_state: &Pubkey,
+        vault_owners: &[Pubkey],
+        taker: &Pubkey,
+        send: &Token,
+        receive: &Token,
+        swap_amount: u64,
+        min_amount: u64,
+    ) -> Instruction {
+        let mut swap = instruction(
+            accounts::SwapMulti {
+                global_state: *global_state,
+                get_coin_data: oracle::coin_data_address(&send.mint),
+                send_coin_data: oracle::coin_data_address(&receive.mint),
+                token_store_authority: token_store_authority(),
+                mint_send: send.mint,
+                mint_receive: receive.mint,
+                get_token_from: send.associated_token_address(taker),
+                get_token_from_authority: *taker,
+                send_token_to: receive.associated_token_address(taker),
+                token_store_pda_from: token_store_address(global_state, &receive.mint),
+                token_store_pda_to: token_store_address(global_state, &send.mint),
+                treasury: Some(treasury_address(global_state, &receive.mint)),
+                system_program: system_program::ID,
+                token_program: send.program,
+                token_program_receive: receive.program,
+            },
+            instruction::SwapMulti {
+                swap_amount,
+                min_amount,
+            },
+        );
+        // (user_vault_from, user_vault_to, pair_config) of each owner
+        for owner in vault_owners {
+            swap.accounts.extend([
+                AccountMeta::new(user_vault_address(owner, &receive.mint), false),
+                AccountMeta::new(user_vault_address(owner, &send.mint), false),
+                AccountMeta::new_readonly(
+                    pair_config_address(owner, &receive.mint, &send.mint),
+                    false,
+                ),
+            ]);
+        }
+        swap
+    }
+
+    pub fn quote_swap(
+        global_state: &Pubkey,
+        vault_owner: &Pubkey,
+        taker: &Pubkey,
+        send: &Token,
+        receive: &Token,
+        swap_amount: u64,
+        min_amount: u64,
+    ) -> Instruction {
+        instruction(
+            accounts::QuoteSwap {
+                global_state: *global_state,
+                get_coin_data: oracle::coin_data_address(&send.mint),
+                send_coin_data: oracle::coin_data_address(&receive.mint),
+                user_vault_from: user_vault_address(vault_owner, &receive.mint),
+                user_vault_to: user_vault_address(vault_owner, &send.mint),
+                pair_config: pair_config_address(vault_owner, &receive.mint, &send.mint),
+                mint_send: send.mint,
+                mint_receive: receive.mint,
+                taker: *taker,
+            },
+            instruction::QuoteSwap {
+                swap_amount,
+                min_amount,
+            },
+        )
+    }
+
+    /// Update of the vault of `user` by its owner or operator `authority`
+    pub fn update_user_vault(
+        authority: &Pubkey,
+        user: &Pubkey,
+        mint: &Pubkey,
+        update: VaultConfigUpdate,
+    ) -> Instruction {
+        instruction(
+            accounts::UpdateUserVault {
+                authority: *authority,
+                user_account: *user,
+                mint: *mint,
+                user_vault: user_vault_address(user, mint),
+            },
+            instruction::UpdateUserVault { update },
+        )
+    }
+
+    pub fn pool_address(global_state: &Pubkey, manager: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"pool", global_state.as_ref(), manager.as_ref()],
+            &SUPER_LIQUIDITY_ID,
+        )
+        .0
+    }
+
+    pub fn lp_mint_address(pool: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"lp_mint", pool.as_ref()], &SUPER_LIQUIDITY_ID).0
+    }
+
+    pub fn init_pool(global_state: &Pubkey, manager: &Pubkey) -> Instruction {
+        let pool = pool_address(global_state, manager);
+        instruction(
+            accounts::InitPool {
+                global_state: *global_state,
+                manager: *manager,
+                pool,
+                lp_mint: lp_mint_address(&pool),
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::InitPool {},
+        )
+    }
+
+    pub fn init_pool_vault(global_state: &Pubkey, manager: &Pubkey, mint: &Pubkey) -> Instruction {
+        let pool = pool_address(global_state, manager);
+        instruction(
+            accounts::InitPoolVault {
+                global_state: *global_state,
+                manager: *manager,
+                pool,
+                mint: *mint,
+                pool_vault: user_vault_address(&pool, mint),
+                system_program: system_program::ID,
+            },
+            instruction::InitPoolVault {},
+        )
+    }
+
+    /// `depositor` deposits into the pool of `manager` from its associated token accounts,
+    /// `pool_mints` are the mints of the pool vaults in the order they were created
+    pub fn deposit_pool(
+        global_state: &Pubkey,
+        manager: &Pubkey,
+        depositor: &Pubkey,
+        token: &Token,
+        amount: u64,
+        pool_mints: &[Pubkey],
+    ) -> Instruction {
+        let pool = pool_address(global_state, manager);
+        let lp_mint = lp_mint_address(&pool);
+        let mut deposit = instruction(
+            accounts::DepositPool {
+                global_state: *global_state,
+                pool,
+                mint: token.mint,
+                pool_vault: user_vault_address(&pool, &token.mint),
+                get_token_from: token.associated_token_address(depositor),
+                get_token_from_authority: *depositor,
+                token_store_pda: token_store_address(global_state, &token.mint),
+                lp_mint,
+                lp_token_to: spl_associated_token_account::get_associated_token_address(
+                    depositor, &lp_mint,
+                ),
+                token_program: token.program,
+            },
+            instruction::DepositPool { amount },
+        );
+        // (pool_vault, coin_data) of each pool mint
+        for mint in pool_mints {
+            deposit.accounts.extend([
+                AccountMeta::new_readonly(user_vault_address(&pool, mint), false),
+                AccountMeta::new_readonly(oracle::coin_data_address(mint), false),
+            ]);
+        }
+        deposit
+    }
+}
+
+/// Alice provides 1000 mockUSDC for mockSOL with a 1% sell fee, bob holds 10 mockSOL.
+/// The oracle prices mockSOL at 100 USD and mockUSDC at 1 USD.
+pub struct Market {
+    pub context: ProgramTestContext,
+    pub admin: Pubkey,
+    pub global_state: Pubkey,
+    pub mock_sol: Token,
+    pub mock_usdc: Token,
+    pub alice: Keypair,
+    pub bob: Keypair,
+}
+impl Market {
+    pub async fn new() -> Self {
+        let mut context = program_test().start_with_context().await;
+        let mock_sol = create_mint(&mut context, 9).await;
+        let mock_usdc = create_mint(&mut context, 6).await;
+        Self::with_mints(context, mock_sol, mock_usdc).await
+    }
+
+    /// Market of the given mints, of 9 and 6 decimals
+    pub async fn with_mints(
+        mut context: ProgramTestContext,
+        mock_sol: Token,
+        mock_usdc: Token,
+    ) -> Self {
+        let admin = context.payer.pubkey();
+        let global_state = ix::global_state_address(&admin);
+
+        create_coin(&mut context, &mock_sol.mint, 9, "mockSOL", 100 * USD).await;
+        create_coin(&mut context, &mock_usdc.mint, 6, "mockUSDC", USD).await;
+
+        let instructions = [
+            ix::initialize(&admin),
+            ix::init_token_store(&admin, &mock_sol),
+            ix::init_token_store(&admin, &mock_usdc),
+            ix::init_treasury(&admin, &mock_sol),
+            ix::init_treasury(&admin, &mock_usdc),
+        ];
+        process(&mut context, &instructions, &[]).await.unwrap();
+
+        let alice = create_user(&mut context, 10 * SOL).await;
+        let bob = create_user(&mut context, 10 * SOL).await;
+        create_token_account(&mut context, &mock_usdc, &alice.pubkey(), 1000 * USDC).await;
+        create_token_account(&mut context, &mock_sol, &bob.pubkey(), 10 * SOL).await;
+        create_token_account(&mut context, &mock_usdc, &bob.pubkey(), 0).await;
+
+        let mut market = Self {
+            context,
+            admin,
+            global_state,
+            mock_sol,
+            mock_usdc,
+            alice,
+            bob,
+        };
+        let alice = market.alice.insecure_clone();
+        market.provide_usdc(&alice, 1000 * USDC, 100).await;
+        market
+    }
+
+    /// Vaults of `provider` selling `amount` mockUSDC for mockSOL with `sell_fee`
+    pub async fn provide_usdc(&mut self, provider: &Keypair, amount: u64, sell_fee: u32) {
+        let provide = VaultConfig {
+            sell_fee,
+            max: u64::MAX,
+            provide_status: true,
+            ..VaultConfig::default()
+        };
+        let receive = VaultConfig {
+            max: u64::MAX,
+            receive_status: true,
+            ..VaultConfig::default()
+        };
+        let owner = provider.pubkey();
+        let instructions = [
+            ix::init_user_vault(&self.global_state, &owner, &self.mock_usdc.mint, provide),
+            ix::init_user_vault(&self.global_state, &owner, &self.mock_sol.mint, receive),
+            ix::deposit(&self.global_state, &owner, &owner, &self.mock_usdc, amount),
+        ];
+        process(&mut self.context, &instructions, &[provider])
+            .await
+            .unwrap();
+    }
+
+    /// Bob swaps mockSOL for mockUSDC against the vaults of alice
+    pub fn swap(&self, swap_amount: u64, min_amount: u64) -> Instruction {
+        ix::swap(
+            &self.global_state,
+            &self.alice.pubkey(),
+            &self.bob.pubkey(),
+            &self.mock_sol,
+            &self.mock_usdc,
+            swap_amount,
+            min_amount,
+        )
+    }
+
+    /// Sends the instructions signed by `signer`
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signer: &Keypair,
+    ) -> Result<(), BanksClientError> {
+        process(&mut self.context, instructions, &[signer]).await
+    }
+
+    pub async fn vault(&mut self, owner: &Pubkey, mint: &Pubkey) -> UserCoinVault {
+        let address = ix::user_vault_address(owner, mint);
+        get_anchor_account(&mut self.context, &address).await
+    }
+
+    /// Balance of the associated token account of `owner`
+    pub async fn balance(&mut self, token: &Token, owner: &Pubkey) -> u64 {
+        let address = token.associated_token_address(owner);
+        token_balance(&mut self.context, &address).await
+    }
+
+    /// Bob mockSOL and mockUSDC, alice mockUSDC and mockSOL vaults
+    pub async fn balances(&mut self) -> [u64; 4] {
+        let (alice, bob) = (self.alice.pubkey(), self.bob.pubkey());
+        let (mock_sol, mock_usdc) = (self.mock_sol, self.mock_usdc);
+        [
+            self.balance(&mock_sol, &bob).await,
+            self.balance(&mock_usdc, &bob).await,
+            self.vault(&alice, &mock_usdc.mint).await.amount,
+            self.vault(&alice, &mock_sol.mint).await.amount,
+        ]
+    }
+}
@@ -0,0 +1,266 @@
+use delphor_oracle_aggregator::CoinData;
+use delphor_program_test::*;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    instruction::InstructionError, pubkey::Pubkey, signature::Signer, sysvar::clock::Clock,
+    transaction::TransactionError,
+};
+
+// Feeds of a mockSOL priced at 100 USD by delphor-oracle
+struct Feeds {
+    context: ProgramTestContext,
+    mock_sol: Token,
+    pyth_product: Pubkey,
+    pyth_price: Pubkey,
+    switchboard: Pubkey,
+}
+
+async fn setup() -> Feeds {
+    let mut context = program_test().start_with_context().await;
+    let mock_sol = create_mint(&mut context, 9).await;
+    let payer = context.payer.pubkey();
+    let instruction = oracle::create_coin(&payer, &payer, "mockSOL", 100 * USD);
+    process(&mut context, &[instruction], &[]).await.unwrap();
+    Feeds {
+        context,
+        mock_sol,
+        pyth_product: Pubkey::new_unique(),
+        pyth_price: Pubkey::new_unique(),
+        switchboard: Pubkey::new_unique(),
+    }
+}
+
+impl Feeds {
+    async fn init_coin(&mut self, switchboard: &Pubkey, pyth_product: &Pubkey) -> Result<(), u32> {
+        let payer = self.context.payer.pubkey();
+        let instruction = oracle::init_coin(
+            &payer,
+            &payer,
+            &self.mock_sol.mint,
+            9,
+            "mockSOL",
+            switchboard,
+            pyth_product,
+        );
+        process(&mut self.context, &[instruction], &[])
+            .await
+            .map_err(custom_error)
+    }
+
+    async fn update_coin_price(&mut self, switchboard: &Pubkey, pyth_price: &Pubkey) {
+        let instruction = oracle::update_coin_price(
+            &self.context.payer.pubkey(),
+            &self.mock_sol.mint,
+            &oracle::coin_info_address("mockSOL"),
+            switchboard,
+            pyth_price,
+        );
+        process(&mut self.context, &[instruction], &[])
+            .await
+            .unwrap();
+    }
+
+    async fn coin_data(&mut self) -> CoinData {
+        let address = oracle::coin_data_address(&self.mock_sol.mint);
+        get_anchor_account(&mut self.context, &address).await
+    }
+}
+
+#[tokio::test]
+async fn delphor_oracle_update_refreshes_the_price() {
+    let mut feeds = setup().await;
+    let no_feed = Pubkey::default();
+    feeds.init_coin(&no_feed, &no_feed).await.unwrap();
+    feeds.update_coin_price(&no_feed, &no_feed).await;
+
+    advance_clock(&mut feeds.context, 60).await;
+    let payer = feeds.context.payer.pubkey();
+    let instruction = oracle::update_coin(&payer, "mockSOL", 105 * USD);
+    process(&mut feeds.context, &[instruction], &[])
+        .await
+        .unwrap();
+    feeds.update_coin_price(&no_feed, &no_feed).await;
+
+    let clock: Clock = feeds.context.banks_client.get_sysvar().await.unwrap();
+    let coin_data = feeds.coin_data().await;
+    assert_eq!(coin_data.price, 105 * USD);
+    assert_eq!(coin_data.last_update_timestamp, clock.unix_timestamp as u64);
+}
+
+#[tokio::test]
+async fn delphor_oracle_update_is_authority_only() {
+    let mut feeds = setup().await;
+    let mallory = create_user(&mut feeds.context, SOL).await;
+
+    let instruction = oracle::update_coin(&mallory.pubkey(), "mockSOL", USD);
+    let error = process(&mut feeds.context, &[instruction], &[&mallory])
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(delphor_oracle::ErrorCode::Unauthorized)
+    );
+}
+
+#[tokio::test]
+async fn pyth_and_switchboard_prices_are_aggregated() {
+    let mut feeds = setup().await;
+    let (product, price, switchboard) = (feeds.pyth_product, feeds.pyth_price, feeds.switchboard);
+    set_pyth_product(&mut feeds.context, &product, "mockSOL", &price);
+    // 104 +/- 0.05 USD with 8 decimals
+    set_pyth_price(&mut feeds.context, &price, 10_400_000_000, 5_000_000, -8);
+    set_switchboard_result(&mut feeds.context, &switchboard, 101.0);
+
+    feeds.init_coin(&switchboard, &product).await.unwrap();
+    let coin_data = feeds.coin_data().await;
+    assert_eq!(coin_data.pyth_price_account, price);
+    assert_eq!(coin_data.switchboard_optimized_feed_account, switchboard);
+
+    feeds.update_coin_price(&switchboard, &price).await;
+
+    // 104 is the furthest of 100, 101 and 104, the other two are averaged
+    let coin_data = feeds.coin_data().await;
+    assert_eq!(coin_data.price, 100_500_000_000);
+    // pyth confidence scaled to the 9 decimals of the price
+    assert_eq!(coin_data.confidence, 50_000_000);
+}
+
+#[tokio::test]
+async fn pyth_product_of_another_symbol_is_rejected() {
+    let mut feeds = setup().await;
+    let (product, price) = (feeds.pyth_product, feeds.pyth_price);
+    set_pyth_product(&mut feeds.context, &product, "mockBTC", &price);
+
+    let error = feeds
+        .init_coin(&Pubkey::default(), &product)
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        error,
+        u32::from(delphor_oracle_aggregator::ErrorCode::PythProductAccountError)
+    );
+}
+
+#[tokio::test]
+async fn switchboard_account_of_another_type_is_rejected() {
+    let mut feeds = setup().await;
+    let switchboard = feeds.switchboard;
+    set_switchboard_result(&mut feeds.context, &switchboard, 101.0);
+    // overwrite the account type
+    let mut account = get_account(&mut feeds.context, &switchboard).await;
+    account.data[0] = 0;
+    feeds.context.set_account(&switchboard, &account.into());
+
+    let payer = feeds.context.payer.pubkey();
+    let instruction = oracle::init_coin(
+        &payer,
+        &payer,
+        &feeds.mock_sol.mint,
+        9,
+        "mockSOL",
+        &switchboard,
+        &Pubkey::default(),
+    );
+    let error = process(&mut feeds.context, &[instruction], &[])
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        error.unwrap(),
+        TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+    );
+}
+
+#[tokio::test]
+async fn update_with_another_pyth_price_account_fails() {
+    let mut feeds = setup().await;
+    let (product, price) = (feeds.pyth_product, feeds.pyth_price);
+    set_pyth_product(&mut feeds.context, &product, "mockSOL", &price);
+    set_pyth_price(&mut feeds.context, &price, 10_400_000_000, 0, -8);
+    feeds.init_coin(&Pubkey::default(), &product).await.unwrap();
+
+    // a price account of another product
+    let other_price = Pubkey::new_unique();
+    set_pyth_price(&mut feeds.context, &other_price, 1, 0, -8);
+    let instruction = oracle::update_coin_price(
+        &feeds.context.payer.pubkey(),
+        &feeds.mock_sol.mint,
+        &oracle::coin_info_address("mockSOL"),
+        &Pubkey::default(),
+        &other_price,
+    );
+    let error = process(&mut feeds.context, &[instruction], &[])
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(anchor_lang::error::ErrorCode::ConstraintRaw)
+    );
+}
+
+#[tokio::test]
+async fn update_with_the_coin_info_of_another_symbol_fails() {
+    let mut feeds = setup().await;
+    let payer = feeds.context.payer.pubkey();
+    let instruction = oracle::create_coin(&payer, &payer, "mockBTC", 20_000 * USD);
+    process(&mut feeds.context, &[instruction], &[])
+        .await
+        .unwrap();
+    let no_feed = Pubkey::default();
+    feeds.init_coin(&no_feed, &no_feed).await.unwrap();
+
+    let instruction = oracle::update_coin_price(
+        &payer,
+        &feeds.mock_sol.mint,
+        &oracle::coin_info_address("mockBTC"),
+        &no_feed,
+        &no_feed,
+    );
+    let error = process(&mut feeds.context, &[instruction], &[])
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(anchor_lang::error::ErrorCode::ConstraintSeeds)
+    );
+}
+
+#[tokio::test]
+async fn update_with_the_coin_info_of_another_authority_fails() {
+    let mut feeds = setup().await;
+    // the coin data is registered for mallory, the mockSOL coin info is the payer's
+    let mallory = Pubkey::new_unique();
+    let (payer, no_feed) = (feeds.context.payer.pubkey(), Pubkey::default());
+    let instruction = oracle::init_coin(
+        &mallory,
+        &payer,
+        &feeds.mock_sol.mint,
+        9,
+        "mockSOL",
+        &no_feed,
+        &no_feed,
+    );
+    process(&mut feeds.context, &[instruction], &[])
+        .await
+        .unwrap();
+
+    let instruction = oracle::update_coin_price(
+        &payer,
+        &feeds.mock_sol.mint,
+        &oracle::coin_info_address("mockSOL"),
+        &no_feed,
+        &no_feed,
+    );
+    let error = process(&mut feeds.context, &[instruction], &[])
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(delphor_oracle_aggregator::ErrorCode::CoinAuthorityMismatch)
+    );
+}
@@ -0,0 +1,130 @@
+use delphor_program_test::*;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signer};
+use super_liquidity::instructions::swap::ErrorCode;
+use super_liquidity::states::VaultConfigUpdate;
+
+/// Pool of the admin with mockSOL and mockUSDC vaults, bob holds its LP token account
+async fn setup() -> (Market, Pubkey) {
+    let mut market = Market::new().await;
+    let (admin, global_state) = (market.admin, market.global_state);
+    let instructions = [
+        ix::init_pool(&global_state, &admin),
+        ix::init_pool_vault(&global_state, &admin, &market.mock_sol.mint),
+        ix::init_pool_vault(&global_state, &admin, &market.mock_usdc.mint),
+    ];
+    process(&mut market.context, &instructions, &[])
+        .await
+        .unwrap();
+
+    let pool = ix::pool_address(&global_state, &admin);
+    let lp_token = Token {
+        mint: ix::lp_mint_address(&pool),
+        program: spl_token::ID,
+    };
+    let bob = market.bob.pubkey();
+    let lp_token_to = create_token_account(&mut market.context, &lp_token, &bob, 0).await;
+    (market, lp_token_to)
+}
+
+/// Pool mockSOL vault receiving up to 10 mockSOL with a 1% buy fee
+fn receiving_sol() -> VaultConfigUpdate {
+    VaultConfigUpdate {
+        buy_fee: Some(100),
+        max: Some(10 * SOL),
+        receive_status: Some(true),
+        ..Default::default()
+    }
+}
+
+fn update_pool_vault(market: &Market, mint: &Pubkey, update: VaultConfigUpdate) -> Instruction {
+    let pool = ix::pool_address(&market.global_state, &market.admin);
+    ix::update_user_vault(&market.admin, &pool, mint, update)
+}
+
+fn bob_deposits_sol(market: &Market, amount: u64) -> Instruction {
+    ix::deposit_pool(
+        &market.global_state,
+        &market.admin,
+        &market.bob.pubkey(),
+        &market.mock_sol,
+        amount,
+        &[market.mock_sol.mint, market.mock_usdc.mint],
+    )
+}
+
+async fn deposit_error(market: &mut Market, update: VaultConfigUpdate) -> u32 {
+    let instruction = update_pool_vault(market, &market.mock_sol.mint, update);
+    process(&mut market.context, &[instruction], &[])
+        .await
+        .unwrap();
+
+    let bob = market.bob.insecure_clone();
+    let instruction = bob_deposits_sol(market, SOL);
+    custom_error(market.process(&[instruction], &bob).await.unwrap_err())
+}
+
+#[tokio::test]
+async fn deposit_mints_shares_net_of_the_buy_fee() {
+    let (mut market, lp_token_to) = setup().await;
+    let instruction = update_pool_vault(&market, &market.mock_sol.mint, receiving_sol());
+    process(&mut market.context, &[instruction], &[])
+        .await
+        .unwrap();
+
+    let bob = market.bob.insecure_clone();
+    let instruction = bob_deposits_sol(&market, SOL);
+    market.process(&[instruction], &bob).await.unwrap();
+
+    // 1 mockSOL is worth 100 USD, 1% of it is kept by the pool
+    assert_eq!(
+        token_balance(&mut market.context, &lp_token_to).await,
+        99 * USD
+    );
+    let pool = ix::pool_address(&market.global_state, &market.admin);
+    let mock_sol = market.mock_sol.mint;
+    assert_eq!(market.vault(&pool, &mock_sol).await.amount, SOL);
+}
+
+#[tokio::test]
+async fn deposit_to_a_pool_vault_not_receiving_fails() {
+    let (mut market, _) = setup().await;
+    let update = VaultConfigUpdate {
+        receive_status: Some(false),
+        ..receiving_sol()
+    };
+
+    assert_eq!(
+        deposit_error(&mut market, update).await,
+        u32::from(ErrorCode::VaultRecieveOff)
+    );
+}
+
+#[tokio::test]
+async fn deposit_above_the_pool_vault_max_fails() {
+    let (mut market, _) = setup().await;
+    let update = VaultConfigUpdate {
+        max: Some(SOL / 2),
+        ..receiving_sol()
+    };
+
+    assert_eq!(
+        deposit_error(&mut market, update).await,
+        u32::from(ErrorCode::ExceedsMaxAmount)
+    );
+}
+
+#[tokio::test]
+async fn deposit_out_of_the_pool_vault_price_band_fails() {
+    let (mut market, _) = setup().await;
+    // mockSOL at 100 USD, out of 110 +/- 5%
+    let update = VaultConfigUpdate {
+        reference_price: Some(110 * USD),
+        max_price_deviation: Some(500),
+        ..receiving_sol()
+    };
+
+    assert_eq!(
+        deposit_error(&mut market, update).await,
+        u32::from(ErrorCode::PriceOutOfBand)
+    );
+}
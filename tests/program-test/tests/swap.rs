@@ -0,0 +1,255 @@
+use anchor_lang::AnchorDeserialize;
+use delphor_program_test::*;
+use solana_sdk::signature::Signer;
+use super_liquidity::instructions::swap::{ErrorCode, SwapQuote};
+
+#[tokio::test]
+async fn oracle_price_is_aggregated_from_delphor_oracle() {
+    let mut market = Market::new().await;
+    let coin_data: delphor_oracle_aggregator::CoinData = get_anchor_account(
+        &mut market.context,
+        &oracle::coin_data_address(&market.mock_sol.mint),
+    )
+    .await;
+    assert_eq!(coin_data.price, 100 * USD);
+    assert_eq!(coin_data.decimals, 9);
+}
+
+#[tokio::test]
+async fn swap_at_the_oracle_price_net_of_the_sell_fee() {
+    let mut market = Market::new().await;
+
+    // 1 mockSOL is 100 mockUSDC, 1% of it kept by alice: 100 / 1.01
+    let amount_out = 99_009_900;
+    let bob = market.bob.insecure_clone();
+    let instruction = market.swap(SOL, amount_out);
+    market.process(&[instruction], &bob).await.unwrap();
+
+    assert_eq!(
+        market.balances().await,
+        [9 * SOL, amount_out, 1000 * USDC - amount_out, SOL]
+    );
+    let treasury = ix::treasury_address(&market.global_state, &market.mock_usdc.mint);
+    assert_eq!(token_balance(&mut market.context, &treasury).await, 0);
+}
+
+#[tokio::test]
+async fn swap_below_min_amount_fails() {
+    let mut market = Market::new().await;
+    let before = market.balances().await;
+
+    let bob = market.bob.insecure_clone();
+    let instruction = market.swap(SOL, 99_009_901);
+    let error = market.process(&[instruction], &bob).await.unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(ErrorCode::InsufficientAmount)
+    );
+    assert_eq!(market.balances().await, before);
+}
+
+#[tokio::test]
+async fn swap_with_a_stale_oracle_price_fails() {
+    let mut market = Market::new().await;
+
+    // default max price age of 120 seconds
+    advance_clock(&mut market.context, 121).await;
+    let bob = market.bob.insecure_clone();
+    let instruction = market.swap(SOL, 0);
+    let error = market.process(&[instruction], &bob).await.unwrap_err();
+
+    assert_eq!(custom_error(error), u32::from(ErrorCode::PriceTooOld));
+}
+
+#[tokio::test]
+async fn swap_exact_out_charges_the_amount_rounded_up() {
+    let mut market = Market::new().await;
+
+    // 50 mockUSDC at 100 / 1.01 mockUSDC per mockSOL: 0.505 mockSOL
+    let amount_in = 505_000_000;
+    let instruction = ix::swap_exact_out(
+        &market.global_state,
+        &market.alice.pubkey(),
+        &market.bob.pubkey(),
+        &market.mock_sol,
+        &market.mock_usdc,
+        50 * USDC,
+        amount_in,
+    );
+    let bob = market.bob.insecure_clone();
+    market.process(&[instruction], &bob).await.unwrap();
+
+    assert_eq!(
+        market.balances().await,
+        [10 * SOL - amount_in, 50 * USDC, 950 * USDC, amount_in]
+    );
+}
+
+#[tokio::test]
+async fn protocol_fee_is_sent_to_the_treasury() {
+    let mut market = Market::new().await;
+    // half the fee spread
+    let instruction = ix::set_protocol_fee(&market.admin, 5000);
+    process(&mut market.context, &[instruction], &[])
+        .await
+        .unwrap();
+
+    let amount_out = 99_009_900;
+    let bob = market.bob.insecure_clone();
+    let instruction = market.swap(SOL, amount_out);
+    market.process(&[instruction], &bob).await.unwrap();
+
+    // half of 100 - 99.0099 mockUSDC
+    let protocol_fee = 495_050;
+    let treasury = ix::treasury_address(&market.global_state, &market.mock_usdc.mint);
+    assert_eq!(
+        token_balance(&mut market.context, &treasury).await,
+        protocol_fee
+    );
+    assert_eq!(
+        market.balances().await,
+        [
+            9 * SOL,
+            amount_out,
+            1000 * USDC - amount_out - protocol_fee,
+            SOL
+        ]
+    );
+}
+
+#[tokio::test]
+async fn quote_swap_returns_the_swap_amounts() {
+    let mut market = Market::new().await;
+    let before = market.balances().await;
+
+    let quote = |min_amount| {
+        ix::quote_swap(
+            &market.global_state,
+            &market.alice.pubkey(),
+            &market.bob.pubkey(),
+            &market.mock_sol,
+            &market.mock_usdc,
+            SOL,
+            min_amount,
+        )
+    };
+    let (quote, failing_quote) = (quote(0), quote(99_009_901));
+
+    let mut quotes = Vec::new();
+    for instruction in [quote, failing_quote] {
+        let transaction = transaction(&mut market.context, &[instruction], &[]).await;
+        let simulation = market
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        simulation.result.unwrap().unwrap();
+        let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+        assert_eq!(return_data.program_id, SUPER_LIQUIDITY_ID);
+        quotes.push(SwapQuote::try_from_slice(&return_data.data).unwrap());
+    }
+
+    assert_eq!(quotes[0].swap_amount, SOL);
+    assert_eq!(quotes[0].amount_to_send, 99_009_900);
+    assert_eq!(quotes[0].sell_fee, 100);
+    assert_eq!(quotes[0].error_code, 0);
+    // a quote that would fail is returned with the error code, not as an error
+    assert_eq!(quotes[1].amount_to_send, 0);
+    assert_eq!(
+        quotes[1].error_code,
+        u32::from(ErrorCode::InsufficientAmount) as u64
+    );
+    assert_eq!(market.balances().await, before);
+}
+
+#[tokio::test]
+async fn swap_multi_fills_the_cheapest_vault_first() {
+    let mut market = Market::new().await;
+    // carol provides 60 mockUSDC with a 0.5% sell fee
+    let carol = create_user(&mut market.context, 10 * SOL).await;
+    let (mock_sol, mock_usdc) = (market.mock_sol, market.mock_usdc);
+    create_token_account(&mut market.context, &mock_usdc, &carol.pubkey(), 60 * USDC).await;
+    market.provide_usdc(&carol, 60 * USDC, 50).await;
+
+    let instruction = ix::swap_multi(
+        &market.global_state,
+        &[market.alice.pubkey(), carol.pubkey()],
+        &market.bob.pubkey(),
+        &market.mock_sol,
+        &market.mock_usdc,
+        SOL,
+        0,
+    );
+    let bob = market.bob.insecure_clone();
+    market.process(&[instruction], &bob).await.unwrap();
+
+    // carol fills up to her 60 mockUSDC priced without fees, 0.6 mockSOL for 60 / 1.005,
+    // alice fills the remaining 0.4 mockSOL for 40 / 1.01
+    let carol_amount_out = 59_701_492;
+    let alice_amount_out = 39_603_960;
+    let carol_usdc = market.vault(&carol.pubkey(), &mock_usdc.mint).await;
+    let carol_sol = market.vault(&carol.pubkey(), &mock_sol.mint).await;
+    assert_eq!(
+        [carol_usdc.amount, carol_sol.amount],
+        [60 * USDC - carol_amount_out, 600_000_000]
+    );
+    assert_eq!(
+        market.balances().await,
+        [
+            9 * SOL,
+            carol_amount_out + alice_amount_out,
+            1000 * USDC - alice_amount_out,
+            400_000_000
+        ]
+    );
+}
+
+#[tokio::test]
+async fn swap_without_a_protocol_fee_needs_no_treasury() {
+    let mut market = Market::new().await;
+
+    let amount_out = 99_009_900;
+    let instruction = ix::swap_without_treasury(
+        &market.global_state,
+        &market.alice.pubkey(),
+        &market.bob.pubkey(),
+        &market.mock_sol,
+        &market.mock_usdc,
+        SOL,
+        amount_out,
+    );
+    let bob = market.bob.insecure_clone();
+    market.process(&[instruction], &bob).await.unwrap();
+
+    assert_eq!(
+        market.balances().await,
+        [9 * SOL, amount_out, 1000 * USDC - amount_out, SOL]
+    );
+}
+
+#[tokio::test]
+async fn swap_with_a_protocol_fee_requires_the_treasury() {
+    let mut market = Market::new().await;
+    let instruction = ix::set_protocol_fee(&market.admin, 5000);
+    process(&mut market.context, &[instruction], &[])
+        .await
+        .unwrap();
+    let before = market.balances().await;
+
+    let instruction = ix::swap_without_treasury(
+        &market.global_state,
+        &market.alice.pubkey(),
+        &market.bob.pubkey(),
+        &market.mock_sol,
+        &market.mock_usdc,
+        SOL,
+        0,
+    );
+    let bob = market.bob.insecure_clone();
+    let error = market.process(&[instruction], &bob).await.unwrap_err();
+
+    assert_eq!(custom_error(error), u32::from(ErrorCode::TreasuryRequired));
+    assert_eq!(market.balances().await, before);
+}
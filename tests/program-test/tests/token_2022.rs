@@ -0,0 +1,117 @@
+use delphor_program_test::*;
+use solana_sdk::signature::Signer;
+
+/// Market where mockUSDC is a Token-2022 mint charging 1% on transfers, up to 1 mockUSDC
+async fn setup() -> Market {
+    let mut context = program_test().start_with_context().await;
+    let mock_sol = create_mint(&mut context, 9).await;
+    let mock_usdc = create_transfer_fee_mint(&mut context, 6, 100, USDC).await;
+    Market::with_mints(context, mock_sol, mock_usdc).await
+}
+
+#[tokio::test]
+async fn deposit_credits_the_amount_net_of_the_transfer_fee() {
+    let mut market = setup().await;
+    let (alice, mock_usdc) = (market.alice.pubkey(), market.mock_usdc);
+
+    // alice deposited 1000 mockUSDC, the fee is capped at 1 mockUSDC
+    let vault = market.vault(&alice, &mock_usdc.mint).await;
+    assert_eq!(vault.amount, 999 * USDC);
+    let store = ix::token_store_address(&market.global_state, &mock_usdc.mint);
+    assert_eq!(token_balance(&mut market.context, &store).await, 999 * USDC);
+}
+
+#[tokio::test]
+async fn swap_to_a_transfer_fee_mint() {
+    let mut market = setup().await;
+
+    // the store sends 100 / 1.01 mockUSDC, bob receives it net of the 1% transfer fee
+    let amount_to_send = 99_009_900;
+    let amount_received = 98_019_801;
+    let bob = market.bob.insecure_clone();
+    let instruction = market.swap(SOL, amount_received);
+    market.process(&[instruction], &bob).await.unwrap();
+
+    assert_eq!(
+        market.balances().await,
+        [9 * SOL, amount_received, 999 * USDC - amount_to_send, SOL]
+    );
+    // the vault and its store stay in sync
+    let mock_usdc = market.mock_usdc.mint;
+    let store = ix::token_store_address(&market.global_state, &mock_usdc);
+    assert_eq!(
+        token_balance(&mut market.context, &store).await,
+        999 * USDC - amount_to_send
+    );
+}
+
+#[tokio::test]
+async fn swap_from_a_transfer_fee_mint() {
+    let mut market = setup().await;
+    let (mock_sol, mock_usdc) = (market.mock_sol, market.mock_usdc);
+    // bob provides 1 mockSOL for mockUSDC, alice sells 100 mockUSDC for it
+    let (alice, bob) = (market.alice.insecure_clone(), market.bob.insecure_clone());
+    let instructions = [ix::withdraw(
+        &market.global_state,
+        &alice.pubkey(),
+        &alice.pubkey(),
+        &mock_usdc,
+        100 * USDC,
+        false,
+    )];
+    market.process(&instructions, &alice).await.unwrap();
+    let provide = super_liquidity::states::VaultConfig {
+        max: u64::MAX,
+        provide_status: true,
+        ..Default::default()
+    };
+    let receive = super_liquidity::states::VaultConfig {
+        max: u64::MAX,
+        receive_status: true,
+        ..Default::default()
+    };
+    let instructions = [
+        ix::init_user_vault(&market.global_state, &bob.pubkey(), &mock_sol.mint, provide),
+        ix::init_user_vault(
+            &market.global_state,
+            &bob.pubkey(),
+            &mock_usdc.mint,
+            receive,
+        ),
+        ix::deposit(
+            &market.global_state,
+            &bob.pubkey(),
+            &bob.pubkey(),
+            &mock_sol,
+            SOL,
+        ),
+    ];
+    market.process(&instructions, &bob).await.unwrap();
+    create_token_account(&mut market.context, &mock_sol, &alice.pubkey(), 0).await;
+
+    // alice received 100 mockUSDC minus the fee capped at 1 and sends 99 of them,
+    // the store receives 99 minus 0.99 of fee: 98.01 mockUSDC priced at 0.9801 mockSOL
+    let instruction = ix::swap(
+        &market.global_state,
+        &bob.pubkey(),
+        &alice.pubkey(),
+        &mock_usdc,
+        &mock_sol,
+        99 * USDC,
+        980_100_000,
+    );
+    market.process(&[instruction], &alice).await.unwrap();
+
+    assert_eq!(market.balance(&mock_usdc, &alice.pubkey()).await, 0);
+    assert_eq!(
+        market.balance(&mock_sol, &alice.pubkey()).await,
+        980_100_000
+    );
+    let bob_usdc = market.vault(&bob.pubkey(), &mock_usdc.mint).await;
+    assert_eq!(bob_usdc.amount, 98_010_000);
+    let store = ix::token_store_address(&market.global_state, &mock_usdc.mint);
+    assert_eq!(
+        token_balance(&mut market.context, &store).await,
+        899 * USDC + 98_010_000
+    );
+}
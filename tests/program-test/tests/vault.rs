@@ -0,0 +1,267 @@
+use anchor_lang::AccountSerialize;
+use delphor_program_test::*;
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+use super_liquidity::error::DelphorError;
+
+#[tokio::test]
+async fn deposit_credits_the_vault_and_the_store() {
+    let mut market = Market::new().await;
+    let (alice, mock_usdc) = (market.alice.pubkey(), market.mock_usdc);
+
+    let vault = market.vault(&alice, &mock_usdc.mint).await;
+    assert_eq!(vault.amount, 1000 * USDC);
+    assert_eq!(market.balance(&mock_usdc, &alice).await, 0);
+    let store = ix::token_store_address(&market.global_state, &mock_usdc.mint);
+    assert_eq!(
+        token_balance(&mut market.context, &store).await,
+        1000 * USDC
+    );
+}
+
+#[tokio::test]
+async fn anyone_can_fund_a_vault() {
+    let mut market = Market::new().await;
+    let (alice, bob, mock_sol) = (market.alice.pubkey(), market.bob.pubkey(), market.mock_sol);
+
+    let instruction = ix::deposit(&market.global_state, &alice, &bob, &mock_sol, SOL);
+    let signer = market.bob.insecure_clone();
+    market.process(&[instruction], &signer).await.unwrap();
+
+    assert_eq!(market.vault(&alice, &mock_sol.mint).await.amount, SOL);
+    assert_eq!(market.balance(&mock_sol, &bob).await, 9 * SOL);
+}
+
+#[tokio::test]
+async fn withdraw_debits_the_vault() {
+    let mut market = Market::new().await;
+    let (alice, mock_usdc) = (market.alice.pubkey(), market.mock_usdc);
+
+    let instruction = ix::withdraw(
+        &market.global_state,
+        &alice,
+        &alice,
+        &mock_usdc,
+        400 * USDC,
+        false,
+    );
+    let signer = market.alice.insecure_clone();
+    market.process(&[instruction], &signer).await.unwrap();
+
+    assert_eq!(
+        market.vault(&alice, &mock_usdc.mint).await.amount,
+        600 * USDC
+    );
+    assert_eq!(market.balance(&mock_usdc, &alice).await, 400 * USDC);
+    let store = ix::token_store_address(&market.global_state, &mock_usdc.mint);
+    assert_eq!(token_balance(&mut market.context, &store).await, 600 * USDC);
+}
+
+#[tokio::test]
+async fn withdraw_to_another_account_fails() {
+    let mut market = Market::new().await;
+    let (alice, bob, mock_usdc) = (market.alice.pubkey(), market.bob.pubkey(), market.mock_usdc);
+
+    let instruction = ix::withdraw(&market.global_state, &alice, &bob, &mock_usdc, USDC, false);
+    let signer = market.alice.insecure_clone();
+    let error = market.process(&[instruction], &signer).await.unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(DelphorError::InvalidRecipient)
+    );
+    assert_eq!(
+        market.vault(&alice, &mock_usdc.mint).await.amount,
+        1000 * USDC
+    );
+}
+
+#[tokio::test]
+async fn withdraw_by_another_account_fails() {
+    let mut market = Market::new().await;
+    let (alice, mock_usdc) = (market.alice.pubkey(), market.mock_usdc);
+    let mallory = create_user(&mut market.context, SOL).await;
+    create_token_account(&mut market.context, &mock_usdc, &mallory.pubkey(), 0).await;
+
+    // signed by mallory for the vault of alice, the vault seeds don't match
+    let mut instruction = ix::withdraw(
+        &market.global_state,
+        &mallory.pubkey(),
+        &mallory.pubkey(),
+        &mock_usdc,
+        USDC,
+        false,
+    );
+    instruction.accounts[1].pubkey = ix::user_vault_address(&alice, &mock_usdc.mint);
+    let error = market.process(&[instruction], &mallory).await.unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(anchor_lang::error::ErrorCode::ConstraintSeeds)
+    );
+}
+
+#[tokio::test]
+async fn pause_halts_deposits_and_swaps_but_not_withdrawals() {
+    let mut market = Market::new().await;
+    let (alice, mock_usdc) = (market.alice.pubkey(), market.mock_usdc);
+    process(
+        &mut market.context,
+        &[ix::set_paused(&market.admin, true)],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let bob = market.bob.insecure_clone();
+    let instruction = market.swap(SOL, 0);
+    let error = market.process(&[instruction], &bob).await.unwrap_err();
+    assert_eq!(custom_error(error), u32::from(DelphorError::ProgramPaused));
+
+    let signer = market.alice.insecure_clone();
+    let withdraw = ix::withdraw(
+        &market.global_state,
+        &alice,
+        &alice,
+        &mock_usdc,
+        USDC,
+        false,
+    );
+    let deposit = ix::deposit(&market.global_state, &alice, &alice, &mock_usdc, USDC);
+    market.process(&[withdraw], &signer).await.unwrap();
+    let error = market.process(&[deposit], &signer).await.unwrap_err();
+    assert_eq!(custom_error(error), u32::from(DelphorError::ProgramPaused));
+
+    process(
+        &mut market.context,
+        &[ix::set_paused(&market.admin, false)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let instruction = market.swap(SOL, 0);
+    market.process(&[instruction], &bob).await.unwrap();
+}
+
+#[tokio::test]
+async fn mint_pause_halts_the_mint_swaps() {
+    let mut market = Market::new().await;
+    let instruction = ix::set_mint_paused(&market.admin, &market.mock_usdc.mint, true);
+    process(&mut market.context, &[instruction], &[])
+        .await
+        .unwrap();
+
+    let bob = market.bob.insecure_clone();
+    let instruction = market.swap(SOL, 0);
+    let error = market.process(&[instruction], &bob).await.unwrap_err();
+    assert_eq!(custom_error(error), u32::from(DelphorError::MintPaused));
+
+    // other mints are still accepted
+    let (alice, mock_sol) = (market.alice.pubkey(), market.mock_sol);
+    let instruction = ix::deposit(&market.global_state, &alice, &bob.pubkey(), &mock_sol, SOL);
+    market.process(&[instruction], &bob).await.unwrap();
+}
+
+#[tokio::test]
+async fn pause_is_admin_only() {
+    let mut market = Market::new().await;
+    let mut instruction = ix::set_paused(&market.admin, true);
+    instruction.accounts[1].pubkey = market.bob.pubkey();
+
+    let bob = market.bob.insecure_clone();
+    let error = market.process(&[instruction], &bob).await.unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(anchor_lang::error::ErrorCode::ConstraintHasOne)
+    );
+}
+
+/// Alice vault as created by the first versions: no global state and its tokens in the
+/// associated token account of store_auth
+async fn legacy_vault(market: &mut Market) -> Pubkey {
+    let (alice, mock_usdc) = (market.alice.pubkey(), market.mock_usdc);
+    let address = ix::user_vault_address(&alice, &mock_usdc.mint);
+    let mut vault = market.vault(&alice, &mock_usdc.mint).await;
+    vault.global_state = Pubkey::default();
+    let mut data = Vec::new();
+    vault.try_serialize(&mut data).unwrap();
+    let mut account = get_account(&mut market.context, &address).await;
+    account.data[..data.len()].copy_from_slice(&data);
+    market.context.set_account(&address, &account.into());
+
+    let store_auth = ix::token_store_authority();
+    create_token_account(&mut market.context, &mock_usdc, &store_auth, 500 * USDC).await
+}
+
+#[tokio::test]
+async fn legacy_vault_is_migrated_by_the_admin() {
+    let mut market = Market::new().await;
+    let legacy_store = legacy_vault(&mut market).await;
+    let (alice, mock_usdc) = (market.alice.insecure_clone(), market.mock_usdc);
+    let withdraw = ix::withdraw(
+        &market.global_state,
+        &alice.pubkey(),
+        &alice.pubkey(),
+        &mock_usdc,
+        USDC,
+        false,
+    );
+    let error = market
+        .process(std::slice::from_ref(&withdraw), &alice)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        custom_error(error),
+        u32::from(anchor_lang::error::ErrorCode::ConstraintHasOne)
+    );
+
+    let instructions = [
+        ix::migrate_token_store(&market.admin, &mock_usdc),
+        ix::migrate_user_vault(&market.admin, &alice.pubkey(), &mock_usdc.mint),
+    ];
+    process(&mut market.context, &instructions, &[])
+        .await
+        .unwrap();
+
+    let store = ix::token_store_address(&market.global_state, &mock_usdc.mint);
+    assert_eq!(token_balance(&mut market.context, &legacy_store).await, 0);
+    assert_eq!(
+        token_balance(&mut market.context, &store).await,
+        1500 * USDC
+    );
+    let vault = market.vault(&alice.pubkey(), &mock_usdc.mint).await;
+    assert_eq!(vault.global_state, market.global_state);
+    market.process(&[withdraw], &alice).await.unwrap();
+    assert_eq!(market.balance(&mock_usdc, &alice.pubkey()).await, USDC);
+}
+
+#[tokio::test]
+async fn migrated_vault_cannot_be_migrated_again() {
+    let mut market = Market::new().await;
+    let (alice, mock_usdc) = (market.alice.pubkey(), market.mock_usdc.mint);
+    let instruction = ix::migrate_user_vault(&market.admin, &alice, &mock_usdc);
+    let error = process(&mut market.context, &[instruction], &[])
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(DelphorError::VaultAlreadyMigrated)
+    );
+}
+
+#[tokio::test]
+async fn migration_is_admin_only() {
+    let mut market = Market::new().await;
+    legacy_vault(&mut market).await;
+    let bob = market.bob.insecure_clone();
+    let mut instruction = ix::migrate_token_store(&market.admin, &market.mock_usdc);
+    instruction.accounts[1].pubkey = bob.pubkey();
+
+    let error = market.process(&[instruction], &bob).await.unwrap_err();
+
+    assert_eq!(
+        custom_error(error),
+        u32::from(anchor_lang::error::ErrorCode::ConstraintHasOne)
+    );
+}
@@ -1,10 +1,13 @@
-const anchor = require("@project-serum/anchor");
-const BN = require("@project-serum/anchor").BN;
+const anchor = require("@coral-xyz/anchor");
+const BN = require("@coral-xyz/anchor").BN;
 const PublicKey = require("@solana/web3.js").PublicKey;
+const NATIVE_MINT = require("@solana/spl-token").NATIVE_MINT;
 const {
   programCall,
+  programSimulateReturnData,
   checkEqualValues,
   expectProgramCallRevert,
+  sleep,
 } = require("./utils");
 const assert = require("assert");
 
@@ -26,7 +29,7 @@ function checkData(mockSOL, symbol, price) {
 }
 
 describe("super-liquidity", () => {
-  const provider = anchor.Provider.env();
+  const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
 
   const superLiquidityProgram = anchor.workspace.SuperLiquidity;
@@ -36,6 +39,7 @@ describe("super-liquidity", () => {
   const adminAccount = provider.wallet.publicKey;
   const alice = anchor.web3.Keypair.generate();
   const bob = anchor.web3.Keypair.generate();
+  const carol = anchor.web3.Keypair.generate();
   const payer = provider.wallet.publicKey;
   const authority = adminAccount;
   const systemProgram = anchor.web3.SystemProgram.programId;
@@ -48,7 +52,6 @@ describe("super-liquidity", () => {
     alicemockUSDC,
     bobmockUSDC,
     mockUSDCStore,
-    tokenStoreAuthorityBump,
     tokenStoreAuthority,
     aliceMockSOLVault,
     bobMockSOLVault,
@@ -65,19 +68,93 @@ describe("super-liquidity", () => {
     delphorMockSOLPDA,
     delphorOracleMockSOLPDA,
     delphorOracleMockUSDCPDA,
-    finalAmount;
+    finalAmount,
+    mockSOLTreasury,
+    mockUSDCTreasury,
+    protocolFee,
+    pool,
+    lpMint,
+    poolMockSOLVault,
+    poolMockUSDCVault,
+    bobLpToken,
+    bobSwapQuote,
+    alicePairConfig,
+    wSOLStore,
+    aliceWSOLVault,
+    carolMockSOLVault,
+    carolMockUSDCVault,
+    carolPairConfig,
+    delphorOracleWSOLPDA,
+    delphorWSOLPDA,
+    wSOLTreasury;
 
   function Lamport(value) {
     return new BN(value * 10 ** 9);
   }
 
-  let mintMockSOLAmountToAlice = Lamport(10);
-  let mintMockSOLAmountToBob = Lamport(5);
-  let mintMockUSDCAmountToAlice = Lamport(1750);
-  let depositAmountAliceMockSOL = Lamport(8);
-  let depositAmountAliceMockUSDC = Lamport(500);
-  let bobSwapAmountSOLForUSDC = Lamport(2);
-  let bobSwapUSDCMinAmount = Lamport(250);
+  function USDC(value) {
+    return new BN(value * 10 ** mockUSDC.decimals);
+  }
+
+  // Amount of tokenOut sent for amountIn of tokenIn at the oracle prices,
+  // rounded down in favour of the vault
+  function amountOut(amountIn, tokenIn, tokenOut, buyFee = 0, sellFee = 0) {
+    return amountIn
+      .mul(tokenIn.price)
+      .muln(10000 - buyFee)
+      .mul(new BN(10).pow(new BN(tokenOut.decimals)))
+      .div(
+        tokenOut.price
+          .muln(10000 + sellFee)
+          .mul(new BN(10).pow(new BN(tokenIn.decimals)))
+      );
+  }
+
+  // Share of the fee spread kept by the protocol
+  function swapProtocolFee(amountIn, amountToSend, tokenIn, tokenOut) {
+    return amountOut(amountIn, tokenIn, tokenOut)
+      .sub(amountToSend)
+      .muln(protocolFeeShare)
+      .divn(10000);
+  }
+
+  function pairConfig(config = {}) {
+    return {
+      enabled: true,
+      sellFee: 0,
+      buyFee: 0,
+      limitPriceStatus: false,
+      limitPrice: new BN(0),
+      buyLimitPriceStatus: false,
+      buyLimitPrice: new BN(0),
+      maxAmount: new BN(0),
+      ...config,
+    };
+  }
+
+  function vaultConfig(config = {}) {
+    return {
+      buyFee: 0,
+      sellFee: 0,
+      min: new BN(0),
+      max: new BN(0),
+      receiveStatus: false,
+      provideStatus: false,
+      limitPriceStatus: false,
+      limitPrice: new BN(0),
+      maxPriceAge: new BN(0),
+      referencePrice: new BN(0),
+      maxPriceDeviation: 0,
+      feeCurve: 0,
+      buyLimitPriceStatus: false,
+      buyLimitPrice: new BN(0),
+      limitPriceMint: PublicKey.default,
+      allowlistStatus: false,
+      maxOutflow: new BN(0),
+      outflowWindow: new BN(0),
+      ...config,
+    };
+  }
 
   let mockSOL = {
     price: Lamport(150),
@@ -88,9 +165,24 @@ describe("super-liquidity", () => {
   let mockUSDC = {
     price: Lamport(1),
     symbol: "usdc",
+    decimals: 6,
+  };
+
+  let wSOL = {
+    price: Lamport(150),
+    symbol: "wSOL",
     decimals: 9,
   };
 
+  let mintMockSOLAmountToAlice = Lamport(10);
+  let mintMockSOLAmountToBob = Lamport(5);
+  let mintMockUSDCAmountToAlice = USDC(1750);
+  let depositAmountAliceMockSOL = Lamport(8);
+  let depositAmountAliceMockUSDC = USDC(500);
+  let bobSwapAmountSOLForUSDC = Lamport(2);
+  let bobSwapUSDCMinAmount = USDC(250);
+  let protocolFeeShare = 1000;
+
   let pythProductAccount = systemProgram;
   let pythPriceAccount = systemProgram;
   let switchboardOptimizedFeedAccount = systemProgram;
@@ -172,7 +264,7 @@ describe("super-liquidity", () => {
   });
 
   it("Create MockSOL and mint test tokens", async () => {
-    mockSOLMint = await createMint(provider, adminAccount);
+    mockSOLMint = await createMint(provider, adminAccount, mockSOL.decimals);
 
     alicemockSOL = await createAssociatedTokenAccount(
       provider,
@@ -222,7 +314,11 @@ describe("super-liquidity", () => {
   });
 
   it("Create MockUSDC and mint test tokens", async () => {
-    mockUSDCMint = await createMint(provider, adminAccount);
+    mockUSDCMint = await createMint(
+      provider,
+      adminAccount,
+      mockUSDC.decimals
+    );
 
     alicemockUSDC = await createAssociatedTokenAccount(
       provider,
@@ -383,45 +479,109 @@ describe("super-liquidity", () => {
   });
 
   it("Initialize MockSOL token store", async () => {
-    [tokenStoreAuthority, tokenStoreAuthorityBump] =
-      await PublicKey.findProgramAddress(
-        [Buffer.from("store_auth")],
-        superLiquidityProgram.programId
-      );
+    [tokenStoreAuthority] = await PublicKey.findProgramAddress(
+      [Buffer.from("store_auth")],
+      superLiquidityProgram.programId
+    );
 
-    mockSOLStore = await createAssociatedTokenAccount(
-      provider,
-      mockSOLMint,
-      tokenStoreAuthority
+    [mockSOLStore] = await PublicKey.findProgramAddress(
+      [globalState.toBuffer(), mockSOLMint.toBuffer()],
+      superLiquidityProgram.programId
     );
 
+    await programCall(superLiquidityProgram, "initTokenStore", [], {
+      globalState,
+      adminAccount,
+      mint: mockSOLMint,
+      tokenStoreAuthority,
+      tokenStore: mockSOLStore,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    });
+
+    const storeAccount = await getTokenAccount(provider, mockSOLStore);
     assert.ok(
-      mockSOLStore.toBase58() ==
-        (
-          await getAssociatedTokenAccount(mockSOLMint, tokenStoreAuthority)
-        ).toBase58()
+      storeAccount.owner.toBase58() == tokenStoreAuthority.toBase58() &&
+        storeAccount.mint.toBase58() == mockSOLMint.toBase58()
     );
   });
 
   it("Initialize MockUSDC token store", async () => {
-    [tokenStoreAuthority, tokenStoreAuthorityBump] =
-      await PublicKey.findProgramAddress(
-        [Buffer.from("store_auth")],
-        superLiquidityProgram.programId
-      );
+    [tokenStoreAuthority] = await PublicKey.findProgramAddress(
+      [Buffer.from("store_auth")],
+      superLiquidityProgram.programId
+    );
 
-    mockUSDCStore = await createAssociatedTokenAccount(
-      provider,
-      mockUSDCMint,
-      tokenStoreAuthority
+    [mockUSDCStore] = await PublicKey.findProgramAddress(
+      [globalState.toBuffer(), mockUSDCMint.toBuffer()],
+      superLiquidityProgram.programId
+    );
+
+    await programCall(superLiquidityProgram, "initTokenStore", [], {
+      globalState,
+      adminAccount,
+      mint: mockUSDCMint,
+      tokenStoreAuthority,
+      tokenStore: mockUSDCStore,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    });
+
+    const storeAccount = await getTokenAccount(provider, mockUSDCStore);
+    assert.ok(
+      storeAccount.owner.toBase58() == tokenStoreAuthority.toBase58() &&
+        storeAccount.mint.toBase58() == mockUSDCMint.toBase58()
+    );
+  });
+
+  it("Initialize MockSOL treasury", async () => {
+    [mockSOLTreasury] = await PublicKey.findProgramAddress(
+      [Buffer.from("treasury"), globalState.toBuffer(), mockSOLMint.toBuffer()],
+      superLiquidityProgram.programId
     );
 
+    await programCall(superLiquidityProgram, "initTreasury", [], {
+      globalState,
+      adminAccount,
+      mint: mockSOLMint,
+      tokenStoreAuthority,
+      treasury: mockSOLTreasury,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    });
+
+    const treasuryAccount = await getTokenAccount(provider, mockSOLTreasury);
     assert.ok(
-      mockUSDCStore.toBase58() ==
-        (
-          await getAssociatedTokenAccount(mockUSDCMint, tokenStoreAuthority)
-        ).toBase58()
+      treasuryAccount.owner.toBase58() == tokenStoreAuthority.toBase58()
+    );
+  });
+
+  it("Initialize MockUSDC treasury", async () => {
+    [mockUSDCTreasury] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("treasury"),
+        globalState.toBuffer(),
+        mockUSDCMint.toBuffer(),
+      ],
+      superLiquidityProgram.programId
     );
+
+    await programCall(superLiquidityProgram, "initTreasury", [], {
+      globalState,
+      adminAccount,
+      mint: mockUSDCMint,
+      tokenStoreAuthority,
+      treasury: mockUSDCTreasury,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    });
+
+    const treasuryAccount = await getTokenAccount(provider, mockUSDCTreasury);
+    assert.ok(treasuryAccount.mint.toBase58() == mockUSDCMint.toBase58());
   });
 
   it("Initialize alice mockSOL vault", async () => {
@@ -434,16 +594,7 @@ describe("super-liquidity", () => {
     await programCall(
       superLiquidityProgram,
       "initUserVault",
-      [
-        0,
-        0,
-        new BN(0),
-        new BN(0),
-        false,
-        false,
-        false,
-        new BN(0),
-      ],
+      [vaultConfig()],
       {
         globalState,
         userAccount: alice.publicKey,
@@ -475,19 +626,21 @@ describe("super-liquidity", () => {
         superLiquidityProgram.programId
       );
 
+    // Alice pair config selling mockUSDC for mockSOL, not created yet
+    [alicePairConfig] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("pair_config"),
+        alice.publicKey.toBuffer(),
+        mockUSDCMint.toBuffer(),
+        mockSOLMint.toBuffer(),
+      ],
+      superLiquidityProgram.programId
+    );
+
     await programCall(
       superLiquidityProgram,
       "initUserVault",
-      [
-        0,
-        0,
-        new BN(0),
-        new BN(0),
-        false,
-        false,
-        false,
-        new BN(0),
-      ],
+      [vaultConfig()],
       {
         globalState,
         userAccount: alice.publicKey,
@@ -521,16 +674,7 @@ describe("super-liquidity", () => {
     await programCall(
       superLiquidityProgram,
       "initUserVault",
-      [
-        0,
-        0,
-        new BN(0),
-        new BN(0),
-        false,
-        false,
-        false,
-        new BN(0),
-      ],
+      [vaultConfig()],
       {
         globalState,
         userAccount: bob.publicKey,
@@ -553,16 +697,7 @@ describe("super-liquidity", () => {
     await programCall(
       superLiquidityProgram,
       "initUserVault",
-      [
-        0,
-        0,
-        new BN(0),
-        new BN(0),
-        false,
-        false,
-        false,
-        new BN(0),
-      ],
+      [vaultConfig()],
       {
         globalState,
         userAccount: bob.publicKey,
@@ -577,23 +712,24 @@ describe("super-liquidity", () => {
   it("Alice changes mockSOL fees, min and max", async () => {
     let sellFee = 100;
     let buyFee = 300;
-    let min = new anchor.BN(1 * 10 ** 9);
+    let min = new anchor.BN(0);
     let max = new anchor.BN(0);
 
     await programCall(
       superLiquidityProgram,
       "updateUserVault",
       [
-        sellFee,
-        buyFee,
-        min,
-        max,
-        true,
-        true,
-        true,
-        new BN(0),
+        {
+          sellFee,
+          buyFee,
+          min,
+          max,
+          receiveStatus: true,
+          provideStatus: true,
+        },
       ],
       {
+        authority: alice.publicKey,
         userAccount: alice.publicKey,
         userVault: aliceMockSOLVault,
         mint: mockSOLMint,
@@ -622,23 +758,24 @@ describe("super-liquidity", () => {
   it("Alice changes mockUSDC fees, min and max", async () => {
     let sellFee = 100;
     let buyFee = 300;
-    let min = new anchor.BN(1 * 10 ** 9);
-    let max = new anchor.BN(10 * 10 ** 9);
+    let min = USDC(1);
+    let max = USDC(10);
 
     await programCall(
       superLiquidityProgram,
       "updateUserVault",
       [
-        sellFee,
-        buyFee,
-        min,
-        max,
-        true,
-        true,
-        true,
-        new BN(0),
+        {
+          sellFee,
+          buyFee,
+          min,
+          max,
+          receiveStatus: true,
+          provideStatus: true,
+        },
       ],
       {
+        authority: alice.publicKey,
         userAccount: alice.publicKey,
         userVault: aliceMockUSDCVault,
         mint: mockUSDCMint,
@@ -674,16 +811,17 @@ describe("super-liquidity", () => {
       superLiquidityProgram,
       "updateUserVault",
       [
-        sellFee,
-        buyFee,
-        min,
-        max,
-        true,
-        true,
-        true,
-        new BN(0),
+        {
+          sellFee,
+          buyFee,
+          min,
+          max,
+          receiveStatus: true,
+          provideStatus: true,
+        },
       ],
       {
+        authority: bob.publicKey,
         userAccount: bob.publicKey,
         userVault: bobMockSOLVault,
         mint: mockSOLMint,
@@ -710,23 +848,24 @@ describe("super-liquidity", () => {
   it("Bob changes mockUSDC fees, min and max", async () => {
     let sellFee = 1;
     let buyFee = 3;
-    let min = new anchor.BN(1 * 10 ** 9);
-    let max = new anchor.BN(10 * 10 ** 9);
+    let min = USDC(1);
+    let max = USDC(10);
 
     await programCall(
       superLiquidityProgram,
       "updateUserVault",
       [
-        sellFee,
-        buyFee,
-        min,
-        max,
-        true,
-        true,
-        true,
-        new BN(0),
+        {
+          sellFee,
+          buyFee,
+          min,
+          max,
+          receiveStatus: true,
+          provideStatus: true,
+        },
       ],
       {
+        authority: bob.publicKey,
         userAccount: bob.publicKey,
         userVault: bobMockUSDCVault,
         mint: mockUSDCMint,
@@ -750,15 +889,93 @@ describe("super-liquidity", () => {
     );
   });
 
+  it("Reject deposit with error program paused", async () => {
+    await programCall(superLiquidityProgram, "setPaused", [true], {
+      globalState,
+      adminAccount,
+    });
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "deposit",
+        [depositAmountAliceMockSOL],
+        {
+          globalState,
+          userAccount: alice.publicKey,
+          userVault: aliceMockSOLVault,
+          mint: mockSOLMint,
+          getTokenFrom: alicemockSOL,
+          getTokenFromAuthority: alice.publicKey,
+          tokenStorePda: mockSOLStore,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        "Program paused",
+        [alice]
+      )
+    );
+
+    await programCall(superLiquidityProgram, "setPaused", [false], {
+      globalState,
+      adminAccount,
+    });
+  });
+
+  it("Reject deposit with error mint paused", async () => {
+    await programCall(superLiquidityProgram, "setMintPaused", [true], {
+      globalState,
+      adminAccount,
+      mint: mockSOLMint,
+    });
+
+    let globalStateData =
+      await superLiquidityProgram.account.globalState.fetch(globalState);
+    assert.ok(
+      checkEqualValues(globalStateData.pausedMints, [mockSOLMint])
+    );
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "deposit",
+        [depositAmountAliceMockSOL],
+        {
+          globalState,
+          userAccount: alice.publicKey,
+          userVault: aliceMockSOLVault,
+          mint: mockSOLMint,
+          getTokenFrom: alicemockSOL,
+          getTokenFromAuthority: alice.publicKey,
+          tokenStorePda: mockSOLStore,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        "Mint paused",
+        [alice]
+      )
+    );
+
+    await programCall(superLiquidityProgram, "setMintPaused", [false], {
+      globalState,
+      adminAccount,
+      mint: mockSOLMint,
+    });
+
+    globalStateData =
+      await superLiquidityProgram.account.globalState.fetch(globalState);
+    assert.ok(globalStateData.pausedMints.length == 0);
+  });
+
   it("Alice deposit mockSOL", async () => {
     await programCall(
       superLiquidityProgram,
       "deposit",
       [depositAmountAliceMockSOL],
       {
+        globalState,
         userAccount: alice.publicKey,
         userVault: aliceMockSOLVault,
-        tokenStoreAuthority: tokenStoreAuthority,
         mint: mockSOLMint,
         getTokenFrom: alicemockSOL,
         getTokenFromAuthority: alice.publicKey,
@@ -798,9 +1015,9 @@ describe("super-liquidity", () => {
       "deposit",
       [depositAmountAliceMockUSDC],
       {
+        globalState,
         userAccount: alice.publicKey,
         userVault: aliceMockUSDCVault,
-        tokenStoreAuthority: tokenStoreAuthority,
         mint: mockUSDCMint,
         getTokenFrom: alicemockUSDC,
         getTokenFromAuthority: alice.publicKey,
@@ -839,16 +1056,14 @@ describe("super-liquidity", () => {
       await expectProgramCallRevert(
         superLiquidityProgram,
         "swap",
-        [
-          bobSwapAmountSOLForUSDC,
-          bobSwapUSDCMinAmount,
-          tokenStoreAuthorityBump,
-        ],
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
         {
+          globalState,
           getCoinData: delphorMockSOLPDA,
           sendCoinData: delphorMockUSDCPDA,
           userVaultFrom: aliceMockUSDCVault,
           userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
           tokenStoreAuthority: tokenStoreAuthority,
           mintSend: mockSOLMint,
           mintReceive: mockUSDCMint,
@@ -857,8 +1072,10 @@ describe("super-liquidity", () => {
           sendTokenTo: bobmockUSDC,
           tokenStorePdaFrom: mockUSDCStore,
           tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
           systemProgram,
           tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
         },
         "Operation exceeds max balance to user_vault_to",
         [bob]
@@ -876,16 +1093,17 @@ describe("super-liquidity", () => {
       superLiquidityProgram,
       "updateUserVault",
       [
-        sellFee,
-        buyFee,
-        min,
-        max,
-        false,
-        true,
-        true,
-        new BN(0),
+        {
+          sellFee,
+          buyFee,
+          min,
+          max,
+          receiveStatus: false,
+          provideStatus: true,
+        },
       ],
       {
+        authority: alice.publicKey,
         userAccount: alice.publicKey,
         userVault: aliceMockSOLVault,
         mint: mockSOLMint,
@@ -916,16 +1134,14 @@ describe("super-liquidity", () => {
       await expectProgramCallRevert(
         superLiquidityProgram,
         "swap",
-        [
-          bobSwapAmountSOLForUSDC,
-          bobSwapUSDCMinAmount,
-          tokenStoreAuthorityBump,
-        ],
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
         {
+          globalState,
           getCoinData: delphorMockSOLPDA,
           sendCoinData: delphorMockUSDCPDA,
           userVaultFrom: aliceMockUSDCVault,
           userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
           tokenStoreAuthority: tokenStoreAuthority,
           mintSend: mockSOLMint,
           mintReceive: mockUSDCMint,
@@ -934,8 +1150,10 @@ describe("super-liquidity", () => {
           sendTokenTo: bobmockUSDC,
           tokenStorePdaFrom: mockUSDCStore,
           tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
           systemProgram,
           tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
         },
         "Vault to paused.",
         [bob]
@@ -953,16 +1171,17 @@ describe("super-liquidity", () => {
       superLiquidityProgram,
       "updateUserVault",
       [
-        sellFee,
-        buyFee,
-        min,
-        max,
-        true,
-        true,
-        true,
-        new BN(0),
+        {
+          sellFee,
+          buyFee,
+          min,
+          max,
+          receiveStatus: true,
+          provideStatus: true,
+        },
       ],
       {
+        authority: alice.publicKey,
         userAccount: alice.publicKey,
         userVault: aliceMockSOLVault,
         mint: mockSOLMint,
@@ -988,156 +1207,2467 @@ describe("super-liquidity", () => {
     );
   });
 
-  it("Bob swap mockSOL for mockUSDC", async () => {
+  it("Reject swap with error price too old", async () => {
     await programCall(
       superLiquidityProgram,
-      "swap",
-      [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount, tokenStoreAuthorityBump],
+      "updateOracleConfig",
+      [new BN(0), 200],
       {
-        getCoinData: delphorMockSOLPDA,
-        sendCoinData: delphorMockUSDCPDA,
-        userVaultFrom: aliceMockUSDCVault,
-        userVaultTo: aliceMockSOLVault,
-        tokenStoreAuthority: tokenStoreAuthority,
-        mintSend: mockSOLMint,
-        mintReceive: mockUSDCMint,
-        getTokenFrom: bobmockSOL,
-        getTokenFromAuthority: bob.publicKey,
-        sendTokenTo: bobmockUSDC,
-        tokenStorePdaFrom: mockUSDCStore,
-        tokenStorePdaTo: mockSOLStore,
-        systemProgram,
-        tokenProgram: TOKEN_PROGRAM_ID,
-      },
-      [bob]
-    );
-
-    bobMockSOLAccount = await getTokenAccount(provider, bobmockSOL);
-    programMockSOLAccount = await getTokenAccount(provider, mockSOLStore);
-    const aliceMockSOLVaultData =
-      await superLiquidityProgram.account.userCoinVault.fetch(
-        aliceMockSOLVault
-      );
-    const aliceMockUSDCVaultData =
-      await superLiquidityProgram.account.userCoinVault.fetch(
-        aliceMockUSDCVault
-      );
-
-    finalAmount = new BN(
-      (bobSwapAmountSOLForUSDC *
-        Math.trunc(
-          ((mockSOL.price * (10000 - aliceMockSOLVaultData.buyFee)) /
-            10000 /
-            ((mockUSDC.price * (10000 + aliceMockUSDCVaultData.sellFee)) /
-              10000)) *
-            10 ** 9
-        )) /
-        10 ** 9
+        globalState,
+        adminAccount,
+      }
     );
 
-    bobMockUSDCAccount = await getTokenAccount(provider, bobmockUSDC);
+    await sleep(2000);
 
     assert.ok(
-      checkEqualValues(
-        [
-          aliceMockUSDCVaultData.amount,
-          bobMockUSDCAccount.amount,
-          aliceMockSOLVaultData.amount,
-          programMockSOLAccount.amount,
-          bobMockSOLAccount.amount,
-        ],
-        [
-          depositAmountAliceMockUSDC.sub(finalAmount),
-          finalAmount,
-          depositAmountAliceMockSOL.add(bobSwapAmountSOLForUSDC),
-          depositAmountAliceMockSOL.add(bobSwapAmountSOLForUSDC),
-          mintMockSOLAmountToBob.sub(bobSwapAmountSOLForUSDC),
-        ]
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Oracle price is too old.",
+        [bob]
       )
     );
-  });
-
-  it("Alice withdraw SOL tokens from vault", async () => {
-    let aliceSOLVaultAmount = depositAmountAliceMockSOL.add(
-      bobSwapAmountSOLForUSDC
-    );
-    let aliceBeforeSOLBalance = (await getTokenAccount(provider, alicemockSOL))
-      .amount;
 
     await programCall(
       superLiquidityProgram,
-      "withdraw",
-      [tokenStoreAuthorityBump, aliceSOLVaultAmount],
+      "updateOracleConfig",
+      [new BN(3600), 200],
       {
-        userVault: aliceMockSOLVault,
-        mint: mockSOLMint,
-        sendTokenTo: alicemockSOL,
-        tokenStoreAuthority: tokenStoreAuthority,
-        tokenStorePda: mockSOLStore,
-        userAccount: alice.publicKey,
-        systemProgram,
-        tokenProgram: TOKEN_PROGRAM_ID,
-      },
-      [alice]
+        globalState,
+        adminAccount,
+      }
     );
 
-    aliceMockSOLAccount = await getTokenAccount(provider, alicemockSOL);
-    let delphorMockSOLAccount = await getTokenAccount(provider, mockSOLStore);
-    const aliceMockSOLVaultData =
-      await superLiquidityProgram.account.userCoinVault.fetch(
-        aliceMockSOLVault
-      );
+    const globalStateData =
+      await superLiquidityProgram.account.globalState.fetch(globalState);
+    assert.ok(globalStateData.maxPriceAge.eq(new BN(3600)));
+  });
 
+  it("Reject swapExactOut with error exceeds max amount in", async () => {
     assert.ok(
-      checkEqualValues(
-        [
-          aliceMockSOLVaultData.amount,
-          delphorMockSOLAccount.amount,
-          aliceMockSOLAccount.amount,
-        ],
-        [0, 0, aliceBeforeSOLBalance.add(aliceSOLVaultAmount)]
-      )
-    );
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swapExactOut",
+        [bobSwapUSDCMinAmount, new BN(1)],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Amount required exceeds max_amount_in.",
+        [bob]
+      )
+    );
+  });
+
+  it("Reject vault update with fee curve over 10000 bps", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "updateUserVault",
+        [{ feeCurve: 10001 }],
+        {
+          authority: alice.publicKey,
+          userAccount: alice.publicKey,
+          userVault: aliceMockUSDCVault,
+          mint: mockUSDCMint,
+        },
+        "Fee exceeds 10000 basis points",
+        [alice]
+      )
+    );
+  });
+
+  it("Reject vault update with price deviation and no reference price", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "updateUserVault",
+        [{ maxPriceDeviation: 500 }],
+        {
+          authority: alice.publicKey,
+          userAccount: alice.publicKey,
+          userVault: aliceMockUSDCVault,
+          mint: mockUSDCMint,
+        },
+        "Price deviation requires a reference price",
+        [alice]
+      )
+    );
+  });
+
+  it("Reject vault update with min greater than max", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "updateUserVault",
+        [{ min: USDC(11) }],
+        {
+          authority: alice.publicKey,
+          userAccount: alice.publicKey,
+          userVault: aliceMockUSDCVault,
+          mint: mockUSDCMint,
+        },
+        "Vault min is greater than max",
+        [alice]
+      )
+    );
+  });
+
+  it("Alice operator updates mockUSDC vault until revoked", async () => {
+    const operator = anchor.web3.Keypair.generate();
+    const updateParams = [{ sellFee: 100 }];
+    const updateAccounts = {
+      authority: operator.publicKey,
+      userAccount: alice.publicKey,
+      userVault: aliceMockUSDCVault,
+      mint: mockUSDCMint,
+    };
+    const operatorAccounts = {
+      userAccount: alice.publicKey,
+      mint: mockUSDCMint,
+      userVault: aliceMockUSDCVault,
+    };
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "updateUserVault",
+        updateParams,
+        updateAccounts,
+        "Access denied",
+        [operator]
+      )
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "setVaultOperator",
+      [operator.publicKey],
+      operatorAccounts,
+      [alice]
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "updateUserVault",
+      updateParams,
+      updateAccounts,
+      [operator]
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "revokeVaultOperator",
+      [],
+      operatorAccounts,
+      [alice]
+    );
+
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    assert.ok(
+      aliceMockUSDCVaultData.operator.toBase58() ==
+        PublicKey.default.toBase58()
+    );
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "updateUserVault",
+        updateParams,
+        updateAccounts,
+        "Access denied",
+        [operator]
+      )
+    );
+  });
+
+  it("Reject swap with error price out of vault band", async () => {
+    async function updateAliceMockUSDCVault(referencePrice, maxPriceDeviation) {
+      await programCall(
+        superLiquidityProgram,
+        "updateUserVault",
+        [{ referencePrice, maxPriceDeviation }],
+        {
+          authority: alice.publicKey,
+          userAccount: alice.publicKey,
+          userVault: aliceMockUSDCVault,
+          mint: mockUSDCMint,
+        },
+        [alice]
+      );
+    }
+
+    // mockUSDC reference price 2, allowing 1% deviation
+    await updateAliceMockUSDCVault(Lamport(2), 100);
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Oracle price is out of the vault price band.",
+        [bob]
+      )
+    );
+
+    await updateAliceMockUSDCVault(new BN(0), 0);
+  });
+
+  it("Reject swap with error price over vault to buy limit price", async () => {
+    async function updateAliceMockSOLVault(update) {
+      await programCall(
+        superLiquidityProgram,
+        "updateUserVault",
+        [update],
+        {
+          authority: alice.publicKey,
+          userAccount: alice.publicKey,
+          userVault: aliceMockSOLVault,
+          mint: mockSOLMint,
+        },
+        [alice]
+      );
+    }
+
+    // Alice buys mockSOL up to 100, under the oracle price
+    await updateAliceMockSOLVault({
+      buyLimitPriceStatus: true,
+      buyLimitPrice: Lamport(100),
+    });
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Current price for token sent is over the vault to buy limit price.",
+        [bob]
+      )
+    );
+
+    // Same limit in mockUSDC per mockSOL, scaled by the mockUSDC decimals
+    await updateAliceMockSOLVault({
+      buyLimitPrice: USDC(100),
+      limitPriceMint: mockUSDCMint,
+    });
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Current price for token sent is over the vault to buy limit price.",
+        [bob]
+      )
+    );
+
+    // Limits set in another counter token reject the swap
+    await updateAliceMockSOLVault({ limitPriceMint: alicemockSOL });
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Vault limit prices are set in another counter token.",
+        [bob]
+      )
+    );
+
+    await updateAliceMockSOLVault({
+      buyLimitPriceStatus: false,
+      limitPriceMint: PublicKey.default,
+    });
+  });
+
+  it("Admin sets protocol fee", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "setProtocolFee",
+        [10001],
+        {
+          globalState,
+          adminAccount,
+        },
+        "Fee exceeds 10000 basis points"
+      )
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "setProtocolFee",
+      [protocolFeeShare],
+      {
+        globalState,
+        adminAccount,
+      }
+    );
+
+    const globalStateData =
+      await superLiquidityProgram.account.globalState.fetch(globalState);
+    assert.ok(globalStateData.protocolFeeShare == protocolFeeShare);
+  });
+
+  it("Quote Bob swap mockSOL for mockUSDC", async () => {
+    const returnData = await programSimulateReturnData(
+      superLiquidityProgram,
+      "quoteSwap",
+      [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+      {
+        globalState,
+        getCoinData: delphorMockSOLPDA,
+        sendCoinData: delphorMockUSDCPDA,
+        userVaultFrom: aliceMockUSDCVault,
+        userVaultTo: aliceMockSOLVault,
+        pairConfig: alicePairConfig,
+        mintSend: mockSOLMint,
+        mintReceive: mockUSDCMint,
+        taker: bob.publicKey,
+      }
+    );
+
+    // SwapQuote: swap_amount, amount_to_send, protocol_fee, buy_fee, sell_fee, error_code
+    bobSwapQuote = {
+      swapAmount: new BN(returnData.slice(0, 8), "le"),
+      amountToSend: new BN(returnData.slice(8, 16), "le"),
+      protocolFee: new BN(returnData.slice(16, 24), "le"),
+      errorCode: new BN(returnData.slice(32, 40), "le"),
+    };
+
+    assert.ok(
+      checkEqualValues(
+        [bobSwapQuote.swapAmount, bobSwapQuote.errorCode],
+        [bobSwapAmountSOLForUSDC, 0]
+      )
+    );
+  });
+
+  it("Bob swap mockSOL for mockUSDC", async () => {
+    let listener;
+    let [event] = await new Promise(async (resolve, _reject) => {
+      listener = superLiquidityProgram.addEventListener(
+        "SwapEvent",
+        (_event) => {
+          resolve([_event]);
+        }
+      );
+
+      await programCall(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        [bob]
+      );
+    });
+
+    await superLiquidityProgram.removeEventListener(listener);
+
+    bobMockSOLAccount = await getTokenAccount(provider, bobmockSOL);
+    programMockSOLAccount = await getTokenAccount(provider, mockSOLStore);
+    const aliceMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockSOLVault
+      );
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+
+    // Exact rate with fees, rounded down once in favour of the vault
+    finalAmount = bobSwapAmountSOLForUSDC
+      .mul(mockSOL.price)
+      .muln(10000 - aliceMockSOLVaultData.buyFee)
+      .mul(new BN(10).pow(new BN(mockUSDC.decimals)))
+      .div(
+        mockUSDC.price
+          .muln(10000 + aliceMockUSDCVaultData.sellFee)
+          .mul(new BN(10).pow(new BN(mockSOL.decimals)))
+      );
+
+    // Protocol keeps a share of the spread between amount without fees and finalAmount
+    protocolFee = bobSwapAmountSOLForUSDC
+      .mul(mockSOL.price)
+      .mul(new BN(10).pow(new BN(mockUSDC.decimals)))
+      .div(
+        mockUSDC.price.mul(new BN(10).pow(new BN(mockSOL.decimals)))
+      )
+      .sub(finalAmount)
+      .muln(protocolFeeShare)
+      .divn(10000);
+
+    bobMockUSDCAccount = await getTokenAccount(provider, bobmockUSDC);
+    const mockUSDCTreasuryAccount = await getTokenAccount(
+      provider,
+      mockUSDCTreasury
+    );
+
+    assert.ok(
+      checkEqualValues(
+        [
+          aliceMockUSDCVaultData.amount,
+          bobMockUSDCAccount.amount,
+          mockUSDCTreasuryAccount.amount,
+          aliceMockSOLVaultData.amount,
+          programMockSOLAccount.amount,
+          bobMockSOLAccount.amount,
+        ],
+        [
+          depositAmountAliceMockUSDC.sub(finalAmount).sub(protocolFee),
+          finalAmount,
+          protocolFee,
+          depositAmountAliceMockSOL.add(bobSwapAmountSOLForUSDC),
+          depositAmountAliceMockSOL.add(bobSwapAmountSOLForUSDC),
+          mintMockSOLAmountToBob.sub(bobSwapAmountSOLForUSDC),
+        ]
+      )
+    );
+
+    assert.ok(
+      checkEqualValues(
+        [
+          event.userVaultFrom,
+          event.userVaultTo,
+          event.swapAmount,
+          event.amountToSend,
+          event.protocolFee,
+          event.getCoinPrice,
+          event.sendCoinPrice,
+        ],
+        [
+          aliceMockUSDCVault,
+          aliceMockSOLVault,
+          bobSwapAmountSOLForUSDC,
+          finalAmount,
+          protocolFee,
+          mockSOL.price,
+          mockUSDC.price,
+        ]
+      )
+    );
+
+    // The swap matches its quote
+    assert.ok(
+      checkEqualValues(
+        [bobSwapQuote.amountToSend, bobSwapQuote.protocolFee],
+        [finalAmount, protocolFee]
+      )
+    );
+  });
+
+  it("Alice disables mockUSDC for mockSOL swaps with a pair config", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "initPairConfig",
+      [pairConfig({ enabled: false })],
+      {
+        userAccount: alice.publicKey,
+        mintA: mockUSDCMint,
+        mintB: mockSOLMint,
+        pairConfig: alicePairConfig,
+        systemProgram,
+      },
+      [alice]
+    );
+
+    const alicePairConfigData =
+      await superLiquidityProgram.account.userPairConfig.fetch(alicePairConfig);
+    assert.ok(
+      checkEqualValues(
+        [
+          alicePairConfigData.user,
+          alicePairConfigData.mintA,
+          alicePairConfigData.mintB,
+        ],
+        [alice.publicKey, mockUSDCMint, mockSOLMint]
+      )
+    );
+    assert.ok(!alicePairConfigData.config.enabled);
+  });
+
+  it("Reject swap with error pair disabled", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Swaps of the pair are disabled by the vault owner.",
+        [bob]
+      )
+    );
+  });
+
+  it("Reject swap with error exceeds pair max trade size", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "updatePairConfig",
+      [pairConfig({ maxAmount: new BN(1) })],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        userVault: aliceMockUSDCVault,
+        pairConfig: alicePairConfig,
+      },
+      [alice]
+    );
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Amount to send exceeds the pair max trade size.",
+        [bob]
+      )
+    );
+  });
+
+  it("Alice closes the pair config", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "closePairConfig",
+      [],
+      {
+        userAccount: alice.publicKey,
+        pairConfig: alicePairConfig,
+      },
+      [alice]
+    );
+
+    assert.ok(
+      (await provider.connection.getAccountInfo(alicePairConfig)) == null
+    );
+  });
+
+  it("Reject swap with error taker not allowed", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "updateUserVault",
+      [{ allowlistStatus: true }],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        mint: mockUSDCMint,
+        userVault: aliceMockUSDCVault,
+      },
+      [alice]
+    );
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Taker not in the vault allowlist",
+        [bob]
+      )
+    );
+  });
+
+  it("Alice allows bob to swap against her mockUSDC vault", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "setAllowedTaker",
+      [bob.publicKey, true],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        mint: mockUSDCMint,
+        userVault: aliceMockUSDCVault,
+      },
+      [alice]
+    );
+
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    assert.ok(
+      checkEqualValues(aliceMockUSDCVaultData.allowedTakers, [bob.publicKey])
+    );
+
+    // Past the allowlist, alice mockSOL vault is already at max balance
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Operation exceeds max balance to user_vault_to",
+        [bob]
+      )
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "updateUserVault",
+      [{ allowlistStatus: false }],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        mint: mockUSDCMint,
+        userVault: aliceMockUSDCVault,
+      },
+      [alice]
+    );
+  });
+
+  it("Reject vault update with outflow limit without window", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "updateUserVault",
+        [{ maxOutflow: new BN(1) }],
+        {
+          authority: alice.publicKey,
+          userAccount: alice.publicKey,
+          userVault: aliceMockUSDCVault,
+          mint: mockUSDCMint,
+        },
+        "Outflow limit requires an outflow window",
+        [alice]
+      )
+    );
+  });
+
+  it("Reject swap with error exceeds outflow limit", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "updateUserVault",
+      [{ maxOutflow: new BN(1), outflowWindow: new BN(3600) }],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        userVault: aliceMockUSDCVault,
+        mint: mockUSDCMint,
+      },
+      [alice]
+    );
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swap",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: aliceMockUSDCVault,
+          userVaultTo: aliceMockSOLVault,
+          pairConfig: alicePairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Amount exceeds the vault outflow limit for the current window.",
+        [bob]
+      )
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "updateUserVault",
+      [{ maxOutflow: new BN(0) }],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        userVault: aliceMockUSDCVault,
+        mint: mockUSDCMint,
+      },
+      [alice]
+    );
+  });
+
+  it("Reject swapMulti with error invalid vault pair", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swapMulti",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Remaining accounts must be triples of user_vault_from, user_vault_to and pair_config.",
+        [bob],
+        [
+          { pubkey: aliceMockSOLVault, isWritable: true, isSigner: false },
+          { pubkey: aliceMockUSDCVault, isWritable: true, isSigner: false },
+        ]
+      )
+    );
+  });
+
+  it("Reject swapMulti with error not enough liquidity", async () => {
+    // alice mockSOL vault is already at max balance
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swapMulti",
+        [bobSwapAmountSOLForUSDC, bobSwapUSDCMinAmount],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: bobmockSOL,
+          getTokenFromAuthority: bob.publicKey,
+          sendTokenTo: bobmockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        "Not enough liquidity in the vaults to fill the swap.",
+        [bob],
+        [
+          { pubkey: aliceMockUSDCVault, isWritable: true, isSigner: false },
+          { pubkey: aliceMockSOLVault, isWritable: true, isSigner: false },
+          { pubkey: alicePairConfig, isWritable: false, isSigner: false },
+        ]
+      )
+    );
+  });
+
+  it("Reject withdraw leaving the vault below min", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "withdraw",
+        [depositAmountAliceMockSOL, false],
+        {
+          globalState,
+          userVault: aliceMockSOLVault,
+          mint: mockSOLMint,
+          sendTokenTo: alicemockSOL,
+          tokenStoreAuthority: tokenStoreAuthority,
+          tokenStorePda: mockSOLStore,
+          userAccount: alice.publicKey,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        "Withdrawal leaves the vault below its min amount",
+        [alice]
+      )
+    );
+  });
+
+  it("Reject withdraw to an account not owned by the vault owner", async () => {
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "withdraw",
+        [new BN(1), false],
+        {
+          globalState,
+          userVault: aliceMockSOLVault,
+          mint: mockSOLMint,
+          sendTokenTo: bobmockSOL,
+          tokenStoreAuthority: tokenStoreAuthority,
+          tokenStorePda: mockSOLStore,
+          userAccount: alice.publicKey,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        "Recipient is not the vault owner or an approved recipient",
+        [alice]
+      )
+    );
+  });
+
+  it("Alice sets an approved withdraw recipient", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "setWithdrawRecipient",
+      [adminAccount],
+      {
+        userAccount: alice.publicKey,
+        mint: mockSOLMint,
+        userVault: aliceMockSOLVault,
+      },
+      [alice]
+    );
+
+    const aliceMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockSOLVault
+      );
+    assert.ok(
+      aliceMockSOLVaultData.withdrawRecipient.toBase58() ==
+        adminAccount.toBase58()
+    );
+  });
+
+  it("Alice withdraw SOL tokens from vault", async () => {
+    let aliceSOLVaultAmount = depositAmountAliceMockSOL.add(
+      bobSwapAmountSOLForUSDC
+    );
+    let aliceBeforeSOLBalance = (await getTokenAccount(provider, alicemockSOL))
+      .amount;
+
+    await programCall(
+      superLiquidityProgram,
+      "withdraw",
+      [aliceSOLVaultAmount, true],
+      {
+        globalState,
+        userVault: aliceMockSOLVault,
+        mint: mockSOLMint,
+        sendTokenTo: alicemockSOL,
+        tokenStoreAuthority: tokenStoreAuthority,
+        tokenStorePda: mockSOLStore,
+        userAccount: alice.publicKey,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      [alice]
+    );
+
+    aliceMockSOLAccount = await getTokenAccount(provider, alicemockSOL);
+    let delphorMockSOLAccount = await getTokenAccount(provider, mockSOLStore);
+    const aliceMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockSOLVault
+      );
+
+    assert.ok(
+      checkEqualValues(
+        [
+          aliceMockSOLVaultData.amount,
+          delphorMockSOLAccount.amount,
+          aliceMockSOLAccount.amount,
+        ],
+        [0, 0, aliceBeforeSOLBalance.add(aliceSOLVaultAmount)]
+      )
+    );
+  });
+
+  it("Alice withdraw USDC tokens from vault", async () => {
+    let aliceUSDCVaultAmount = depositAmountAliceMockUSDC
+      .sub(finalAmount)
+      .sub(protocolFee);
+    let aliceBeforeUSDCBalance = (
+      await getTokenAccount(provider, alicemockUSDC)
+    ).amount;
+
+    await programCall(
+      superLiquidityProgram,
+      "withdraw",
+      [aliceUSDCVaultAmount, true],
+      {
+        globalState,
+        userVault: aliceMockUSDCVault,
+        mint: mockUSDCMint,
+        sendTokenTo: alicemockUSDC,
+        tokenStoreAuthority: tokenStoreAuthority,
+        tokenStorePda: mockUSDCStore,
+        userAccount: alice.publicKey,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      [alice]
+    );
+
+    aliceMockUSDCAccount = await getTokenAccount(provider, alicemockUSDC);
+    let delphorMockUSDCAccount = await getTokenAccount(provider, mockUSDCStore);
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+
+    assert.ok(
+      checkEqualValues(
+        [
+          aliceMockUSDCVaultData.amount,
+          delphorMockUSDCAccount.amount,
+          aliceMockUSDCAccount.amount,
+        ],
+        [0, 0, aliceBeforeUSDCBalance.add(aliceUSDCVaultAmount)]
+      )
+    );
+  });
+
+  it("Alice funds bob mockUSDC vault", async () => {
+    const depositAmount = USDC(10);
+
+    await programCall(
+      superLiquidityProgram,
+      "deposit",
+      [depositAmount],
+      {
+        globalState,
+        userAccount: bob.publicKey,
+        userVault: bobMockUSDCVault,
+        mint: mockUSDCMint,
+        getTokenFrom: alicemockUSDC,
+        getTokenFromAuthority: alice.publicKey,
+        tokenStorePda: mockUSDCStore,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      [alice]
+    );
+
+    programMockUSDCAccount = await getTokenAccount(provider, mockUSDCStore);
+    const bobMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(bobMockUSDCVault);
+
+    assert.ok(
+      checkEqualValues(
+        [bobMockUSDCVaultData.amount, programMockUSDCAccount.amount],
+        [depositAmount, depositAmount]
+      )
+    );
+  });
+
+  it("Reject deposit with error external deposit rejected", async () => {
+    await programCall(
+      superLiquidityProgram,
+      "setRejectExternalDeposits",
+      [true],
+      {
+        userAccount: bob.publicKey,
+        mint: mockUSDCMint,
+        userVault: bobMockUSDCVault,
+      },
+      [bob]
+    );
+
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "deposit",
+        [USDC(10)],
+        {
+          globalState,
+          userAccount: bob.publicKey,
+          userVault: bobMockUSDCVault,
+          mint: mockUSDCMint,
+          getTokenFrom: alicemockUSDC,
+          getTokenFromAuthority: alice.publicKey,
+          tokenStorePda: mockUSDCStore,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        "Vault rejects deposits from other accounts",
+        [alice]
+      )
+    );
+  });
+
+  it("Alice closes mockSOL vault and reclaims rent", async () => {
+    const aliceBeforeLamports = await provider.connection.getBalance(
+      alice.publicKey
+    );
+    const vaultLamports = await provider.connection.getBalance(
+      aliceMockSOLVault
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "closeUserVault",
+      [],
+      {
+        globalState,
+        userVault: aliceMockSOLVault,
+        mint: mockSOLMint,
+        sendTokenTo: alicemockSOL,
+        tokenStoreAuthority: tokenStoreAuthority,
+        tokenStorePda: mockSOLStore,
+        userAccount: alice.publicKey,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      [alice]
+    );
+
+    const aliceAfterLamports = await provider.connection.getBalance(
+      alice.publicKey
+    );
+    const vaultAccountInfo = await provider.connection.getAccountInfo(
+      aliceMockSOLVault
+    );
+
+    assert.ok(vaultAccountInfo == null);
+    assert.ok(aliceAfterLamports == aliceBeforeLamports + vaultLamports);
+  });
+
+  it("Admin collects protocol fees", async () => {
+    const adminMockUSDC = await createAssociatedTokenAccount(
+      provider,
+      mockUSDCMint,
+      adminAccount
+    );
+
+    await programCall(superLiquidityProgram, "collectProtocolFees", [], {
+      globalState,
+      adminAccount,
+      mint: mockUSDCMint,
+      tokenStoreAuthority,
+      treasury: mockUSDCTreasury,
+      sendTokenTo: adminMockUSDC,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const adminMockUSDCAccount = await getTokenAccount(provider, adminMockUSDC);
+    const mockUSDCTreasuryAccount = await getTokenAccount(
+      provider,
+      mockUSDCTreasury
+    );
+
+    assert.ok(
+      checkEqualValues(
+        [adminMockUSDCAccount.amount, mockUSDCTreasuryAccount.amount],
+        [protocolFee, 0]
+      )
+    );
+  });
+
+  const poolBuyFee = 100;
+
+  it("Admin initializes a pool with mockSOL and mockUSDC vaults", async () => {
+    [pool] = await PublicKey.findProgramAddress(
+      [Buffer.from("pool"), globalState.toBuffer(), adminAccount.toBuffer()],
+      superLiquidityProgram.programId
+    );
+    [lpMint] = await PublicKey.findProgramAddress(
+      [Buffer.from("lp_mint"), pool.toBuffer()],
+      superLiquidityProgram.programId
+    );
+
+    await programCall(superLiquidityProgram, "initPool", [], {
+      globalState,
+      manager: adminAccount,
+      pool,
+      lpMint,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    });
+
+    [poolMockSOLVault] = await PublicKey.findProgramAddress(
+      [pool.toBuffer(), mockSOLMint.toBuffer()],
+      superLiquidityProgram.programId
+    );
+    [poolMockUSDCVault] = await PublicKey.findProgramAddress(
+      [pool.toBuffer(), mockUSDCMint.toBuffer()],
+      superLiquidityProgram.programId
+    );
+
+    for (const [mint, poolVault] of [
+      [mockSOLMint, poolMockSOLVault],
+      [mockUSDCMint, poolMockUSDCVault],
+    ]) {
+      await programCall(superLiquidityProgram, "initPoolVault", [], {
+        globalState,
+        manager: adminAccount,
+        pool,
+        mint,
+        poolVault,
+        systemProgram,
+      });
+      // deposits are checked and charged as swaps into the pool vault
+      await programCall(
+        superLiquidityProgram,
+        "updateUserVault",
+        [{ buyFee: poolBuyFee, max: Lamport(1000), receiveStatus: true }],
+        {
+          authority: adminAccount,
+          userAccount: pool,
+          userVault: poolVault,
+          mint,
+        }
+      );
+    }
+
+    const poolData = await superLiquidityProgram.account.pool.fetch(pool);
+    const poolMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        poolMockSOLVault
+      );
+
+    assert.ok(poolData.lpMint.toBase58() == lpMint.toBase58());
+    assert.ok(poolData.mints.length == 2);
+    assert.ok(poolMockSOLVaultData.user.toBase58() == pool.toBase58());
+    assert.ok(
+      poolMockSOLVaultData.operator.toBase58() == adminAccount.toBase58()
+    );
+  });
+
+  it("Bob deposits mockSOL into the pool", async () => {
+    const depositAmount = Lamport(1);
+    bobLpToken = await createAssociatedTokenAccount(
+      provider,
+      lpMint,
+      bob.publicKey
+    );
+    const bobBeforeSOLBalance = (await getTokenAccount(provider, bobmockSOL))
+      .amount;
+
+    await programCall(
+      superLiquidityProgram,
+      "depositPool",
+      [depositAmount],
+      {
+        globalState,
+        pool,
+        mint: mockSOLMint,
+        poolVault: poolMockSOLVault,
+        getTokenFrom: bobmockSOL,
+        getTokenFromAuthority: bob.publicKey,
+        tokenStorePda: mockSOLStore,
+        lpMint,
+        lpTokenTo: bobLpToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      [bob],
+      [
+        { pubkey: poolMockSOLVault, isWritable: false, isSigner: false },
+        { pubkey: delphorMockSOLPDA, isWritable: false, isSigner: false },
+        { pubkey: poolMockUSDCVault, isWritable: false, isSigner: false },
+        { pubkey: delphorMockUSDCPDA, isWritable: false, isSigner: false },
+      ]
+    );
+
+    const mockSOLData =
+      await delphorOracleAggregatorProgram.account.coinData.fetch(
+        delphorMockSOLPDA
+      );
+    const expectedShares = depositAmount
+      .mul(mockSOLData.price)
+      .div(new BN(10 ** mockSOLData.decimals))
+      .muln(10000 - poolBuyFee)
+      .divn(10000);
+    const bobLpTokenAccount = await getTokenAccount(provider, bobLpToken);
+    const bobMockSOLAccount = await getTokenAccount(provider, bobmockSOL);
+    const poolMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        poolMockSOLVault
+      );
+
+    assert.ok(
+      checkEqualValues(
+        [
+          bobLpTokenAccount.amount,
+          poolMockSOLVaultData.amount,
+          bobMockSOLAccount.amount,
+        ],
+        [
+          expectedShares,
+          depositAmount,
+          bobBeforeSOLBalance.sub(depositAmount),
+        ]
+      )
+    );
+  });
+
+  it("Bob withdraws his pool shares", async () => {
+    const bobLpTokenAccount = await getTokenAccount(provider, bobLpToken);
+    const bobBeforeSOLBalance = (await getTokenAccount(provider, bobmockSOL))
+      .amount;
+
+    await programCall(
+      superLiquidityProgram,
+      "withdrawPool",
+      [bobLpTokenAccount.amount],
+      {
+        globalState,
+        pool,
+        lpMint,
+        lpTokenFrom: bobLpToken,
+        userAccount: bob.publicKey,
+        tokenStoreAuthority,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      [bob],
+      [
+        { pubkey: poolMockSOLVault, isWritable: true, isSigner: false },
+        { pubkey: mockSOLStore, isWritable: true, isSigner: false },
+        { pubkey: bobmockSOL, isWritable: true, isSigner: false },
+        { pubkey: poolMockUSDCVault, isWritable: true, isSigner: false },
+        { pubkey: mockUSDCStore, isWritable: true, isSigner: false },
+        { pubkey: bobmockUSDC, isWritable: true, isSigner: false },
+      ]
+    );
+
+    const lpSupply = await provider.connection.getTokenSupply(lpMint);
+    const bobMockSOLAccount = await getTokenAccount(provider, bobmockSOL);
+    const poolMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        poolMockSOLVault
+      );
+
+    assert.ok(lpSupply.value.amount == "0");
+    assert.ok(
+      checkEqualValues(
+        [poolMockSOLVaultData.amount, bobMockSOLAccount.amount],
+        [0, bobBeforeSOLBalance.add(Lamport(1))]
+      )
+    );
+  });
+
+  it("Initialize wSOL token store and alice wSOL vault", async () => {
+    [wSOLStore] = await PublicKey.findProgramAddress(
+      [globalState.toBuffer(), NATIVE_MINT.toBuffer()],
+      superLiquidityProgram.programId
+    );
+    [aliceWSOLVault] = await PublicKey.findProgramAddress(
+      [alice.publicKey.toBuffer(), NATIVE_MINT.toBuffer()],
+      superLiquidityProgram.programId
+    );
+
+    await programCall(superLiquidityProgram, "initTokenStore", [], {
+      globalState,
+      adminAccount,
+      mint: NATIVE_MINT,
+      tokenStoreAuthority,
+      tokenStore: wSOLStore,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    });
+
+    await programCall(
+      superLiquidityProgram,
+      "initUserVault",
+      [vaultConfig()],
+      {
+        globalState,
+        userAccount: alice.publicKey,
+        mint: NATIVE_MINT,
+        userVault: aliceWSOLVault,
+        systemProgram,
+      },
+      [alice]
+    );
+  });
+
+  it("Alice deposits native SOL", async () => {
+    const depositAmount = Lamport(1);
+
+    await programCall(
+      superLiquidityProgram,
+      "depositSol",
+      [depositAmount],
+      {
+        globalState,
+        userAccount: alice.publicKey,
+        userVault: aliceWSOLVault,
+        mint: NATIVE_MINT,
+        depositor: alice.publicKey,
+        tokenStorePda: wSOLStore,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      [alice]
+    );
+
+    const storeAccount = await getTokenAccount(provider, wSOLStore);
+    const aliceWSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(aliceWSOLVault);
+
+    assert.ok(
+      checkEqualValues(
+        [aliceWSOLVaultData.amount, storeAccount.amount],
+        [depositAmount, depositAmount]
+      )
+    );
+  });
+
+  it("Alice withdraws native SOL", async () => {
+    const withdrawAmount = Lamport(1);
+    const [unwrapAccount] = await PublicKey.findProgramAddress(
+      [Buffer.from("unwrap"), alice.publicKey.toBuffer()],
+      superLiquidityProgram.programId
+    );
+    const aliceBeforeLamports = await provider.connection.getBalance(
+      alice.publicKey
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "withdrawSol",
+      [withdrawAmount, true],
+      {
+        globalState,
+        userVault: aliceWSOLVault,
+        mint: NATIVE_MINT,
+        sendLamportsTo: alice.publicKey,
+        tokenStoreAuthority,
+        tokenStorePda: wSOLStore,
+        unwrapAccount,
+        userAccount: alice.publicKey,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      [alice]
+    );
+
+    const storeAccount = await getTokenAccount(provider, wSOLStore);
+    const aliceWSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(aliceWSOLVault);
+    const aliceLamports = await provider.connection.getBalance(alice.publicKey);
+
+    // The rent of the unwrap account goes back to alice
+    assert.ok(
+      checkEqualValues(
+        [aliceWSOLVaultData.amount, storeAccount.amount, aliceLamports],
+        [0, 0, withdrawAmount.add(new BN(aliceBeforeLamports))]
+      )
+    );
+  });
+
+  it("Alice and carol provide mockUSDC for mockSOL", async () => {
+    await airdropLamports(carol.publicKey);
+
+    [carolMockSOLVault] = await PublicKey.findProgramAddress(
+      [carol.publicKey.toBuffer(), mockSOLMint.toBuffer()],
+      superLiquidityProgram.programId
+    );
+    [carolMockUSDCVault] = await PublicKey.findProgramAddress(
+      [carol.publicKey.toBuffer(), mockUSDCMint.toBuffer()],
+      superLiquidityProgram.programId
+    );
+    [carolPairConfig] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("pair_config"),
+        carol.publicKey.toBuffer(),
+        mockUSDCMint.toBuffer(),
+        mockSOLMint.toBuffer(),
+      ],
+      superLiquidityProgram.programId
+    );
+
+    for (const [mint, userVault, config] of [
+      [
+        mockSOLMint,
+        carolMockSOLVault,
+        vaultConfig({ buyFee: 50, max: Lamport(10), receiveStatus: true }),
+      ],
+      [
+        mockUSDCMint,
+        carolMockUSDCVault,
+        vaultConfig({ sellFee: 50, provideStatus: true }),
+      ],
+    ]) {
+      await programCall(
+        superLiquidityProgram,
+        "initUserVault",
+        [config],
+        {
+          globalState,
+          userAccount: carol.publicKey,
+          mint,
+          userVault,
+          systemProgram,
+        },
+        [carol]
+      );
+    }
+
+    // Alice opens her closed mockSOL vault again, with cheaper fees than carol
+    // but room for 1 mockSOL only
+    await programCall(
+      superLiquidityProgram,
+      "initUserVault",
+      [vaultConfig({ buyFee: 10, max: Lamport(1), receiveStatus: true })],
+      {
+        globalState,
+        userAccount: alice.publicKey,
+        mint: mockSOLMint,
+        userVault: aliceMockSOLVault,
+        systemProgram,
+      },
+      [alice]
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "updateUserVault",
+      [{ sellFee: 10 }],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        userVault: aliceMockUSDCVault,
+        mint: mockUSDCMint,
+      },
+      [alice]
+    );
+
+    for (const [userAccount, userVault, amount] of [
+      [alice.publicKey, aliceMockUSDCVault, USDC(200)],
+      [carol.publicKey, carolMockUSDCVault, USDC(500)],
+    ]) {
+      await programCall(
+        superLiquidityProgram,
+        "deposit",
+        [amount],
+        {
+          globalState,
+          userAccount,
+          userVault,
+          mint: mockUSDCMint,
+          getTokenFrom: alicemockUSDC,
+          getTokenFromAuthority: alice.publicKey,
+          tokenStorePda: mockUSDCStore,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        [alice]
+      );
+    }
+
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    const carolMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        carolMockUSDCVault
+      );
+
+    assert.ok(
+      checkEqualValues(
+        [aliceMockUSDCVaultData.amount, carolMockUSDCVaultData.amount],
+        [USDC(200), USDC(500)]
+      )
+    );
+  });
+
+  it("Bob swapMulti mockSOL for mockUSDC split between alice and carol", async () => {
+    const swapAmount = Lamport(2);
+    const accounts = {
+      globalState,
+      getCoinData: delphorMockSOLPDA,
+      sendCoinData: delphorMockUSDCPDA,
+      tokenStoreAuthority: tokenStoreAuthority,
+      mintSend: mockSOLMint,
+      mintReceive: mockUSDCMint,
+      getTokenFrom: bobmockSOL,
+      getTokenFromAuthority: bob.publicKey,
+      sendTokenTo: bobmockUSDC,
+      tokenStorePdaFrom: mockUSDCStore,
+      tokenStorePdaTo: mockSOLStore,
+      treasury: mockUSDCTreasury,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    // Carol vaults are passed first, alice cheaper ones must be filled first
+    const remainingAccounts = [
+      { pubkey: carolMockUSDCVault, isWritable: true, isSigner: false },
+      { pubkey: carolMockSOLVault, isWritable: true, isSigner: false },
+      { pubkey: carolPairConfig, isWritable: false, isSigner: false },
+      { pubkey: aliceMockUSDCVault, isWritable: true, isSigner: false },
+      { pubkey: aliceMockSOLVault, isWritable: true, isSigner: false },
+      { pubkey: alicePairConfig, isWritable: false, isSigner: false },
+    ];
+
+    // Alice vaults take 1 mockSOL, up to their max, carol ones the rest
+    const aliceSwapAmount = Lamport(1);
+    const aliceAmountToSend = amountOut(
+      aliceSwapAmount,
+      mockSOL,
+      mockUSDC,
+      10,
+      10
+    );
+    const aliceProtocolFee = swapProtocolFee(
+      aliceSwapAmount,
+      aliceAmountToSend,
+      mockSOL,
+      mockUSDC
+    );
+    const carolSwapAmount = swapAmount.sub(aliceSwapAmount);
+    const carolAmountToSend = amountOut(
+      carolSwapAmount,
+      mockSOL,
+      mockUSDC,
+      50,
+      50
+    );
+    const carolProtocolFee = swapProtocolFee(
+      carolSwapAmount,
+      carolAmountToSend,
+      mockSOL,
+      mockUSDC
+    );
+    const amountToSend = aliceAmountToSend.add(carolAmountToSend);
+
+    // min_amount applies to the amount sent by all the vaults
+    assert.ok(
+      await expectProgramCallRevert(
+        superLiquidityProgram,
+        "swapMulti",
+        [swapAmount, amountToSend.addn(1)],
+        accounts,
+        "Final amount lower than min_amount.",
+        [bob],
+        remainingAccounts
+      )
+    );
+
+    const bobBeforeSOLBalance = (await getTokenAccount(provider, bobmockSOL))
+      .amount;
+    const bobBeforeUSDCBalance = (await getTokenAccount(provider, bobmockUSDC))
+      .amount;
+    const treasuryBeforeBalance = (
+      await getTokenAccount(provider, mockUSDCTreasury)
+    ).amount;
+
+    let listener;
+    const events = [];
+    await new Promise(async (resolve, _reject) => {
+      listener = superLiquidityProgram.addEventListener(
+        "SwapEvent",
+        (_event) => {
+          events.push(_event);
+          if (events.length == 2) {
+            resolve();
+          }
+        }
+      );
+
+      await programCall(
+        superLiquidityProgram,
+        "swapMulti",
+        [swapAmount, amountToSend],
+        accounts,
+        [bob],
+        remainingAccounts
+      );
+    });
+
+    await superLiquidityProgram.removeEventListener(listener);
+
+    // One event per vault pair, in the order they were filled
+    assert.ok(
+      checkEqualValues(
+        [
+          events[0].userVaultFrom,
+          events[0].userVaultTo,
+          events[0].swapAmount,
+          events[0].amountToSend,
+          events[0].protocolFee,
+          events[0].buyFee,
+          events[0].sellFee,
+          events[1].userVaultFrom,
+          events[1].userVaultTo,
+          events[1].swapAmount,
+          events[1].amountToSend,
+          events[1].protocolFee,
+          events[1].buyFee,
+          events[1].sellFee,
+        ],
+        [
+          aliceMockUSDCVault,
+          aliceMockSOLVault,
+          aliceSwapAmount,
+          aliceAmountToSend,
+          aliceProtocolFee,
+          10,
+          10,
+          carolMockUSDCVault,
+          carolMockSOLVault,
+          carolSwapAmount,
+          carolAmountToSend,
+          carolProtocolFee,
+          50,
+          50,
+        ]
+      )
+    );
+
+    // Vaults passed as remaining accounts are written back by the instruction
+    const aliceMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockSOLVault
+      );
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    const carolMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        carolMockSOLVault
+      );
+    const carolMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        carolMockUSDCVault
+      );
+    bobMockSOLAccount = await getTokenAccount(provider, bobmockSOL);
+    bobMockUSDCAccount = await getTokenAccount(provider, bobmockUSDC);
+    const mockUSDCTreasuryAccount = await getTokenAccount(
+      provider,
+      mockUSDCTreasury
+    );
+
+    assert.ok(
+      checkEqualValues(
+        [
+          aliceMockSOLVaultData.amount,
+          aliceMockUSDCVaultData.amount,
+          carolMockSOLVaultData.amount,
+          carolMockUSDCVaultData.amount,
+          bobMockSOLAccount.amount,
+          bobMockUSDCAccount.amount,
+          mockUSDCTreasuryAccount.amount,
+        ],
+        [
+          aliceSwapAmount,
+          USDC(200).sub(aliceAmountToSend).sub(aliceProtocolFee),
+          carolSwapAmount,
+          USDC(500).sub(carolAmountToSend).sub(carolProtocolFee),
+          bobBeforeSOLBalance.sub(swapAmount),
+          bobBeforeUSDCBalance.add(amountToSend),
+          treasuryBeforeBalance.add(aliceProtocolFee).add(carolProtocolFee),
+        ]
+      )
+    );
+  });
+
+  it("Bob swapExactOut mockSOL for exactly 30 mockUSDC", async () => {
+    const amountToSend = USDC(30);
+    const carolMockSOLVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        carolMockSOLVault
+      );
+    const carolMockUSDCVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        carolMockUSDCVault
+      );
+
+    // Amount bob must send at carol fees, rounded up in favour of the vault
+    const numerator = amountToSend
+      .mul(mockUSDC.price)
+      .muln(10000 + carolMockUSDCVaultBefore.sellFee)
+      .mul(new BN(10).pow(new BN(mockSOL.decimals)));
+    const denominator = mockSOL.price
+      .muln(10000 - carolMockSOLVaultBefore.buyFee)
+      .mul(new BN(10).pow(new BN(mockUSDC.decimals)));
+    const swapAmount = numerator.add(denominator).subn(1).div(denominator);
+    const protocolFee = swapProtocolFee(
+      swapAmount,
+      amountToSend,
+      mockSOL,
+      mockUSDC
+    );
+
+    const bobBeforeSOLBalance = (await getTokenAccount(provider, bobmockSOL))
+      .amount;
+    const bobBeforeUSDCBalance = (await getTokenAccount(provider, bobmockUSDC))
+      .amount;
+    const treasuryBeforeBalance = (
+      await getTokenAccount(provider, mockUSDCTreasury)
+    ).amount;
+
+    await programCall(
+      superLiquidityProgram,
+      "swapExactOut",
+      [amountToSend, swapAmount],
+      {
+        globalState,
+        getCoinData: delphorMockSOLPDA,
+        sendCoinData: delphorMockUSDCPDA,
+        userVaultFrom: carolMockUSDCVault,
+        userVaultTo: carolMockSOLVault,
+        pairConfig: carolPairConfig,
+        tokenStoreAuthority: tokenStoreAuthority,
+        mintSend: mockSOLMint,
+        mintReceive: mockUSDCMint,
+        getTokenFrom: bobmockSOL,
+        getTokenFromAuthority: bob.publicKey,
+        sendTokenTo: bobmockUSDC,
+        tokenStorePdaFrom: mockUSDCStore,
+        tokenStorePdaTo: mockSOLStore,
+        treasury: mockUSDCTreasury,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramReceive: TOKEN_PROGRAM_ID,
+      },
+      [bob]
+    );
+
+    const carolMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        carolMockSOLVault
+      );
+    const carolMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        carolMockUSDCVault
+      );
+    bobMockSOLAccount = await getTokenAccount(provider, bobmockSOL);
+    bobMockUSDCAccount = await getTokenAccount(provider, bobmockUSDC);
+    const mockUSDCTreasuryAccount = await getTokenAccount(
+      provider,
+      mockUSDCTreasury
+    );
+
+    // Rounded up, one unit less would not be enough for amountToSend
+    assert.ok(
+      amountOut(
+        swapAmount.subn(1),
+        mockSOL,
+        mockUSDC,
+        carolMockSOLVaultBefore.buyFee,
+        carolMockUSDCVaultBefore.sellFee
+      ).lt(amountToSend)
+    );
+    assert.ok(
+      checkEqualValues(
+        [
+          bobMockUSDCAccount.amount,
+          bobMockSOLAccount.amount,
+          carolMockSOLVaultData.amount,
+          carolMockUSDCVaultData.amount,
+          mockUSDCTreasuryAccount.amount,
+        ],
+        [
+          bobBeforeUSDCBalance.add(amountToSend),
+          bobBeforeSOLBalance.sub(swapAmount),
+          carolMockSOLVaultBefore.amount.add(swapAmount),
+          carolMockUSDCVaultBefore.amount.sub(amountToSend).sub(protocolFee),
+          treasuryBeforeBalance.add(protocolFee),
+        ]
+      )
+    );
   });
 
-  it("Alice withdraw USDC tokens from vault", async () => {
-    let aliceUSDCVaultAmount = depositAmountAliceMockUSDC.sub(finalAmount);
-    let aliceBeforeUSDCBalance = (
+  it("Alice swaps mockSOL for mockUSDC against bob vaults with a fee curve", async () => {
+    async function updateBobVault(userVault, mint, update) {
+      await programCall(
+        superLiquidityProgram,
+        "updateUserVault",
+        [update],
+        {
+          authority: bob.publicKey,
+          userAccount: bob.publicKey,
+          userVault,
+          mint,
+        },
+        [bob]
+      );
+    }
+
+    await updateBobVault(bobMockSOLVault, mockSOLMint, {
+      buyFee: 300,
+      feeCurve: 100,
+    });
+    await updateBobVault(bobMockUSDCVault, mockUSDCMint, {
+      sellFee: 300,
+      feeCurve: 100,
+    });
+
+    const [bobPairConfig] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("pair_config"),
+        bob.publicKey.toBuffer(),
+        mockUSDCMint.toBuffer(),
+        mockSOLMint.toBuffer(),
+      ],
+      superLiquidityProgram.programId
+    );
+    const bobMockUSDCVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(bobMockUSDCVault);
+    const aliceBeforeUSDCBalance = (
       await getTokenAccount(provider, alicemockUSDC)
     ).amount;
 
+    // bob mockSOL vault is empty, at its min, and his mockUSDC vault is at
+    // its max. Both fees are 100 bps lower for a swap rebalancing them.
+    const buyFee = 200;
+    const sellFee = 200;
+    const swapAmount = Lamport(1).divn(20);
+    const amountToSend = amountOut(
+      swapAmount,
+      mockSOL,
+      mockUSDC,
+      buyFee,
+      sellFee
+    );
+    const protocolFee = swapProtocolFee(
+      swapAmount,
+      amountToSend,
+      mockSOL,
+      mockUSDC
+    );
+    assert.ok(
+      amountToSend.gt(amountOut(swapAmount, mockSOL, mockUSDC, 300, 300))
+    );
+
+    let listener;
+    let [event] = await new Promise(async (resolve, _reject) => {
+      listener = superLiquidityProgram.addEventListener(
+        "SwapEvent",
+        (_event) => {
+          resolve([_event]);
+        }
+      );
+
+      await programCall(
+        superLiquidityProgram,
+        "swap",
+        [swapAmount, amountToSend],
+        {
+          globalState,
+          getCoinData: delphorMockSOLPDA,
+          sendCoinData: delphorMockUSDCPDA,
+          userVaultFrom: bobMockUSDCVault,
+          userVaultTo: bobMockSOLVault,
+          pairConfig: bobPairConfig,
+          tokenStoreAuthority: tokenStoreAuthority,
+          mintSend: mockSOLMint,
+          mintReceive: mockUSDCMint,
+          getTokenFrom: alicemockSOL,
+          getTokenFromAuthority: alice.publicKey,
+          sendTokenTo: alicemockUSDC,
+          tokenStorePdaFrom: mockUSDCStore,
+          tokenStorePdaTo: mockSOLStore,
+          treasury: mockUSDCTreasury,
+          systemProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramReceive: TOKEN_PROGRAM_ID,
+        },
+        [alice]
+      );
+    });
+
+    await superLiquidityProgram.removeEventListener(listener);
+
+    const bobMockSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(bobMockSOLVault);
+    const bobMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(bobMockUSDCVault);
+    aliceMockUSDCAccount = await getTokenAccount(provider, alicemockUSDC);
+
+    assert.ok(
+      checkEqualValues(
+        [
+          event.buyFee,
+          event.sellFee,
+          event.swapAmount,
+          event.amountToSend,
+          event.protocolFee,
+        ],
+        [buyFee, sellFee, swapAmount, amountToSend, protocolFee]
+      )
+    );
+    assert.ok(
+      checkEqualValues(
+        [
+          aliceMockUSDCAccount.amount,
+          bobMockSOLVaultData.amount,
+          bobMockUSDCVaultData.amount,
+        ],
+        [
+          aliceBeforeUSDCBalance.add(amountToSend),
+          swapAmount,
+          bobMockUSDCVaultBefore.amount.sub(amountToSend).sub(protocolFee),
+        ]
+      )
+    );
+  });
+
+  it("DelphorOracle init wSOL coin", async () => {
+    [delphorOracleWSOLPDA] = await PublicKey.findProgramAddress(
+      [wSOL.symbol],
+      delphorOracleProgram.programId
+    );
+    [delphorWSOLPDA] = await PublicKey.findProgramAddress(
+      [NATIVE_MINT.toBuffer()],
+      delphorOracleAggregatorProgram.programId
+    );
+
+    await programCall(
+      delphorOracleProgram,
+      "createCoin",
+      [wSOL.price, wSOL.price, wSOL.symbol],
+      {
+        coin: delphorOracleWSOLPDA,
+        authority,
+        payer,
+        systemProgram,
+      }
+    );
+
+    await programCall(
+      delphorOracleAggregatorProgram,
+      "initCoin",
+      [wSOL.decimals, wSOL.symbol],
+      {
+        switchboardOptimizedFeedAccount: switchboardOptimizedFeedAccount,
+        pythProductAccount: pythProductAccount,
+        coinData: delphorWSOLPDA,
+        mint: NATIVE_MINT,
+        authority,
+        payer,
+        systemProgram,
+      }
+    );
+
+    await programCall(delphorOracleAggregatorProgram, "updateCoinPrice", [], {
+      switchboardOptimizedFeedAccount,
+      pythPriceAccount,
+      delphorOracle: delphorOracleWSOLPDA,
+      coinData: delphorWSOLPDA,
+      payer,
+      systemProgram,
+    });
+
+    const delphorWSOLData =
+      await delphorOracleAggregatorProgram.account.coinData.fetch(
+        delphorWSOLPDA
+      );
+
+    checkData(wSOL, delphorWSOLData.symbol, delphorWSOLData.price);
+    assert.ok(delphorWSOLData.mint.toBase58() == NATIVE_MINT.toBase58());
+  });
+
+  it("Initialize wSOL treasury", async () => {
+    [wSOLTreasury] = await PublicKey.findProgramAddress(
+      [Buffer.from("treasury"), globalState.toBuffer(), NATIVE_MINT.toBuffer()],
+      superLiquidityProgram.programId
+    );
+
+    await programCall(superLiquidityProgram, "initTreasury", [], {
+      globalState,
+      adminAccount,
+      mint: NATIVE_MINT,
+      tokenStoreAuthority,
+      treasury: wSOLTreasury,
+      systemProgram,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    });
+
+    const treasuryAccount = await getTokenAccount(provider, wSOLTreasury);
+    assert.ok(treasuryAccount.mint.toBase58() == NATIVE_MINT.toBase58());
+  });
+
+  it("Alice provides mockUSDC for native SOL", async () => {
     await programCall(
       superLiquidityProgram,
-      "withdraw",
-      [tokenStoreAuthorityBump, aliceUSDCVaultAmount],
+      "updateUserVault",
+      [
+        {
+          buyFee: 100,
+          sellFee: 100,
+          max: Lamport(10),
+          receiveStatus: true,
+          provideStatus: true,
+        },
+      ],
+      {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
+        userVault: aliceWSOLVault,
+        mint: NATIVE_MINT,
+      },
+      [alice]
+    );
+
+    await programCall(
+      superLiquidityProgram,
+      "updateUserVault",
+      [{ max: USDC(1000) }],
       {
+        authority: alice.publicKey,
+        userAccount: alice.publicKey,
         userVault: aliceMockUSDCVault,
         mint: mockUSDCMint,
-        sendTokenTo: alicemockUSDC,
-        tokenStoreAuthority: tokenStoreAuthority,
-        tokenStorePda: mockUSDCStore,
+      },
+      [alice]
+    );
+
+    const aliceMockUSDCVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+
+    await programCall(
+      superLiquidityProgram,
+      "deposit",
+      [USDC(200)],
+      {
+        globalState,
         userAccount: alice.publicKey,
+        userVault: aliceMockUSDCVault,
+        mint: mockUSDCMint,
+        getTokenFrom: alicemockUSDC,
+        getTokenFromAuthority: alice.publicKey,
+        tokenStorePda: mockUSDCStore,
         systemProgram,
         tokenProgram: TOKEN_PROGRAM_ID,
       },
       [alice]
     );
 
-    aliceMockUSDCAccount = await getTokenAccount(provider, alicemockUSDC);
-    let delphorMockUSDCAccount = await getTokenAccount(provider, mockUSDCStore);
     const aliceMockUSDCVaultData =
       await superLiquidityProgram.account.userCoinVault.fetch(
         aliceMockUSDCVault
       );
+    const aliceWSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(aliceWSOLVault);
 
     assert.ok(
       checkEqualValues(
         [
           aliceMockUSDCVaultData.amount,
-          delphorMockUSDCAccount.amount,
-          aliceMockUSDCAccount.amount,
+          aliceWSOLVaultData.receiveStatus,
+          aliceWSOLVaultData.provideStatus,
         ],
-        [0, 0, aliceBeforeUSDCBalance.add(aliceUSDCVaultAmount)]
+        [aliceMockUSDCVaultBefore.amount.add(USDC(200)), true, true]
+      )
+    );
+  });
+
+  it("Bob swaps lamports for mockUSDC", async () => {
+    const swapAmount = Lamport(1);
+    const [alicePairConfigUSDCForWSOL] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("pair_config"),
+        alice.publicKey.toBuffer(),
+        mockUSDCMint.toBuffer(),
+        NATIVE_MINT.toBuffer(),
+      ],
+      superLiquidityProgram.programId
+    );
+    const aliceWSOLVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(aliceWSOLVault);
+    const aliceMockUSDCVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    const amountToSend = amountOut(
+      swapAmount,
+      wSOL,
+      mockUSDC,
+      aliceWSOLVaultBefore.buyFee,
+      aliceMockUSDCVaultBefore.sellFee
+    );
+    const protocolFee = swapProtocolFee(
+      swapAmount,
+      amountToSend,
+      wSOL,
+      mockUSDC
+    );
+
+    const bobBeforeLamports = await provider.connection.getBalance(
+      bob.publicKey
+    );
+    const bobBeforeUSDCBalance = (await getTokenAccount(provider, bobmockUSDC))
+      .amount;
+    const storeBeforeBalance = (await getTokenAccount(provider, wSOLStore))
+      .amount;
+
+    await programCall(
+      superLiquidityProgram,
+      "swapSolIn",
+      [swapAmount, amountToSend],
+      {
+        globalState,
+        getCoinData: delphorWSOLPDA,
+        sendCoinData: delphorMockUSDCPDA,
+        userVaultFrom: aliceMockUSDCVault,
+        userVaultTo: aliceWSOLVault,
+        pairConfig: alicePairConfigUSDCForWSOL,
+        tokenStoreAuthority,
+        mintSend: NATIVE_MINT,
+        mintReceive: mockUSDCMint,
+        getTokenFromAuthority: bob.publicKey,
+        sendTokenTo: bobmockUSDC,
+        tokenStorePdaFrom: mockUSDCStore,
+        tokenStorePdaTo: wSOLStore,
+        treasury: mockUSDCTreasury,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramReceive: TOKEN_PROGRAM_ID,
+      },
+      [bob]
+    );
+
+    const bobLamports = await provider.connection.getBalance(bob.publicKey);
+    const storeAccount = await getTokenAccount(provider, wSOLStore);
+    const aliceWSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(aliceWSOLVault);
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    bobMockUSDCAccount = await getTokenAccount(provider, bobmockUSDC);
+
+    // Transaction fees are paid by the provider wallet, not by bob
+    assert.ok(
+      checkEqualValues(
+        [
+          bobLamports,
+          storeAccount.amount,
+          aliceWSOLVaultData.amount,
+          aliceMockUSDCVaultData.amount,
+          bobMockUSDCAccount.amount,
+        ],
+        [
+          new BN(bobBeforeLamports).sub(swapAmount),
+          storeBeforeBalance.add(swapAmount),
+          aliceWSOLVaultBefore.amount.add(swapAmount),
+          aliceMockUSDCVaultBefore.amount.sub(amountToSend).sub(protocolFee),
+          bobBeforeUSDCBalance.add(amountToSend),
+        ]
+      )
+    );
+  });
+
+  it("Bob swaps mockUSDC for lamports", async () => {
+    const swapAmount = USDC(75);
+    const [alicePairConfigWSOLForUSDC] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("pair_config"),
+        alice.publicKey.toBuffer(),
+        NATIVE_MINT.toBuffer(),
+        mockUSDCMint.toBuffer(),
+      ],
+      superLiquidityProgram.programId
+    );
+    const [unwrapAccount] = await PublicKey.findProgramAddress(
+      [Buffer.from("unwrap"), bob.publicKey.toBuffer()],
+      superLiquidityProgram.programId
+    );
+    const aliceWSOLVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(aliceWSOLVault);
+    const aliceMockUSDCVaultBefore =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    const amountToSend = amountOut(
+      swapAmount,
+      mockUSDC,
+      wSOL,
+      aliceMockUSDCVaultBefore.buyFee,
+      aliceWSOLVaultBefore.sellFee
+    );
+    const protocolFee = swapProtocolFee(
+      swapAmount,
+      amountToSend,
+      mockUSDC,
+      wSOL
+    );
+
+    const bobBeforeLamports = await provider.connection.getBalance(
+      bob.publicKey
+    );
+    const bobBeforeUSDCBalance = (await getTokenAccount(provider, bobmockUSDC))
+      .amount;
+    const storeBeforeBalance = (await getTokenAccount(provider, wSOLStore))
+      .amount;
+    const treasuryBeforeBalance = (
+      await getTokenAccount(provider, wSOLTreasury)
+    ).amount;
+
+    await programCall(
+      superLiquidityProgram,
+      "swapSolOut",
+      [swapAmount, amountToSend],
+      {
+        globalState,
+        getCoinData: delphorMockUSDCPDA,
+        sendCoinData: delphorWSOLPDA,
+        userVaultFrom: aliceWSOLVault,
+        userVaultTo: aliceMockUSDCVault,
+        pairConfig: alicePairConfigWSOLForUSDC,
+        tokenStoreAuthority,
+        mintSend: mockUSDCMint,
+        mintReceive: NATIVE_MINT,
+        getTokenFrom: bobmockUSDC,
+        getTokenFromAuthority: bob.publicKey,
+        tokenStorePdaFrom: wSOLStore,
+        tokenStorePdaTo: mockUSDCStore,
+        unwrapAccount,
+        treasury: wSOLTreasury,
+        systemProgram,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramReceive: TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      [bob]
+    );
+
+    const bobLamports = await provider.connection.getBalance(bob.publicKey);
+    const storeAccount = await getTokenAccount(provider, wSOLStore);
+    const treasuryAccount = await getTokenAccount(provider, wSOLTreasury);
+    const aliceWSOLVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(aliceWSOLVault);
+    const aliceMockUSDCVaultData =
+      await superLiquidityProgram.account.userCoinVault.fetch(
+        aliceMockUSDCVault
+      );
+    bobMockUSDCAccount = await getTokenAccount(provider, bobmockUSDC);
+
+    // The rent of the unwrap account goes back to bob
+    assert.ok(
+      checkEqualValues(
+        [
+          bobLamports,
+          storeAccount.amount,
+          treasuryAccount.amount,
+          aliceWSOLVaultData.amount,
+          aliceMockUSDCVaultData.amount,
+          bobMockUSDCAccount.amount,
+        ],
+        [
+          amountToSend.add(new BN(bobBeforeLamports)),
+          storeBeforeBalance.sub(amountToSend).sub(protocolFee),
+          treasuryBeforeBalance.add(protocolFee),
+          aliceWSOLVaultBefore.amount.sub(amountToSend).sub(protocolFee),
+          aliceMockUSDCVaultBefore.amount.add(swapAmount),
+          bobBeforeUSDCBalance.sub(swapAmount),
+        ]
       )
     );
   });
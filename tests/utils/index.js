@@ -1,6 +1,6 @@
 // TODO: use the `@solana/spl-token` package instead of utils here.
 
-const anchor = require("@project-serum/anchor");
+const anchor = require("@coral-xyz/anchor");
 const serumCmn = require("@project-serum/common");
 const TokenInstructions = require("@project-serum/serum").TokenInstructions;
 const { Connection } = require("@solana/web3.js");
@@ -29,7 +29,7 @@ function checkEqualValues(localValue, storedValue) {
   return result;
 }
 
-async function createMint(provider, authority) {
+async function createMint(provider, authority, decimals = 6) {
   if (authority === undefined) {
     authority = provider.wallet.publicKey;
   }
@@ -37,13 +37,14 @@ async function createMint(provider, authority) {
   const instructions = await createMintInstructions(
     provider,
     authority,
-    mint.publicKey
+    mint.publicKey,
+    decimals
   );
 
   const tx = new anchor.web3.Transaction();
   tx.add(...instructions);
 
-  await provider.send(tx, [mint]);
+  await provider.sendAndConfirm(tx, [mint]);
 
   return mint.publicKey;
 }
@@ -85,13 +86,21 @@ function checkError(error, errorExpected = undefined) {
   return result;
 }
 
-async function programCall(program, f, params, accounts, signers = []) {
+async function programCall(
+  program,
+  f,
+  params,
+  accounts,
+  signers = [],
+  remainingAccounts = []
+) {
   let tx;
   pauseConsole();
 
   if (signers.length == 0) {
     tx = await program.rpc[f](...params, {
       accounts,
+      remainingAccounts,
     }).catch((err) => {
       checkError(err);
     });
@@ -99,6 +108,7 @@ async function programCall(program, f, params, accounts, signers = []) {
     tx = await program.rpc[f](...params, {
       accounts,
       signers,
+      remainingAccounts,
     }).catch((err) => {
       checkError(err);
     });
@@ -108,19 +118,30 @@ async function programCall(program, f, params, accounts, signers = []) {
   return tx;
 }
 
+// Simulates an instruction and returns the data set with set_return_data
+async function programSimulateReturnData(program, f, params, accounts) {
+  const tx = program.transaction[f](...params, { accounts });
+  const simulation = await program.provider.simulate(tx);
+  const prefix = `Program return: ${program.programId.toBase58()} `;
+  const log = simulation.logs.find((log) => log.startsWith(prefix));
+  return log ? Buffer.from(log.slice(prefix.length), "base64") : null;
+}
+
 async function expectProgramCallRevert(
   program,
   f,
   params,
   accounts,
   errorExpected,
-  signers = []
+  signers = [],
+  remainingAccounts = []
 ) {
   let errorResult;
   pauseConsole();
   if (signers.length == 0) {
     errorResult = await program.rpc[f](...params, {
       accounts,
+      remainingAccounts,
     }).catch((err) => {
       return checkError(err, errorExpected);
     });
@@ -128,6 +149,7 @@ async function expectProgramCallRevert(
     errorResult = await program.rpc[f](...params, {
       accounts,
       signers,
+      remainingAccounts,
     }).catch((err) => {
       return checkError(err, errorExpected);
     });
@@ -137,7 +159,7 @@ async function expectProgramCallRevert(
   return errorResult;
 }
 
-async function createMintInstructions(provider, authority, mint) {
+async function createMintInstructions(provider, authority, mint, decimals) {
   return [
     anchor.web3.SystemProgram.createAccount({
       fromPubkey: provider.wallet.publicKey,
@@ -148,7 +170,7 @@ async function createMintInstructions(provider, authority, mint) {
     }),
     TokenInstructions.initializeMint({
       mint,
-      decimals: 6,
+      decimals,
       mintAuthority: authority,
     }),
   ];
@@ -171,7 +193,7 @@ async function mintToAccount(
       mintAuthority
     ))
   );
-  await provider.send(tx, []);
+  await provider.sendAndConfirm(tx, []);
   return;
 }
 
@@ -268,7 +290,7 @@ async function createAssociatedTokenAccount(provider, mint, owner) {
       )
     );
 
-    await provider.send(tx, []);
+    await provider.sendAndConfirm(tx, []);
   }
   return associated;
 }
@@ -303,6 +325,7 @@ module.exports = {
   airdropLamports,
   getAssociatedTokenAccount,
   programCall,
+  programSimulateReturnData,
   expectProgramCallRevert,
   sleep,
   checkEqualValues,